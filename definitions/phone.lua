@@ -1,8 +1,23 @@
 --- @meta
 
 --- Provides information about and exposes functionality specific to the phone state and physical interface.
+---
+--- When the engine drives more than one phone, these functions apply to the line that the calling agent is on.
+--- Agents that aren't on a call act on the first line.
 phone = {}
 
+--- Gets the name of the line that the current agent is acting on.
+--- @return string
+function phone.line_name() end
+
+--- Gets the extension that other lines dial to reach the line that the current agent is acting on, if any.
+--- @return string?
+function phone.line_extension() end
+
+--- Gets the number of lines driven by the engine.
+--- @return integer
+function phone.line_count() end
+
 --- Returns the internal ID of the last agent who called the phone, or `nil` if nobody called yet.
 --- @return integer?
 function phone.last_caller_id() end
@@ -11,10 +26,124 @@ function phone.last_caller_id() end
 --- @return string
 function phone.last_dialed_number() end
 
---- Gets the number dialed by the user to place the current call.
+--- Gets the number dialed by the user to place the current call, after any dial plan rewrites.
 --- @return string?
 function phone.call_dialed_number() end
 
+--- Gets the names of the agents on the current call: the agent that the call was placed to or from, followed by any agents bridged into the call.
+--- @return string[]
+function phone.call_parties() end
+
+--- Gets the name of the agent that the specified agent is on an agent-to-agent call with, if any.
+--- @param agent_name string
+--- @return string?
+function phone.agent_call_partner(agent_name) end
+
+--- Ends the agent-to-agent call that the specified agent is on.
+--- @param agent_name string
+--- @return boolean @ Indicates whether the agent was on a call with another agent.
+function phone.end_agent_call(agent_name) end
+
+--- Gets the number of messages on the answering machine.
+--- @return integer
+function phone.voicemail_count() end
+
+--- Sets the message-waiting indicator of the current line, which makes the dial tone stutter and lights the message-waiting lamp.
+--- The indicator is also shown while there are voicemail messages.
+--- @param message_waiting boolean
+function phone.set_message_waiting(message_waiting) end
+
+--- Returns true if the current line is showing that a message is waiting.
+--- @return boolean
+function phone.is_message_waiting() end
+
+--- @class DialRule
+--- @field pattern string @ Pattern matched against the whole number, e.g. `555-XXXX` or `1NXXNXXXXXX`.
+--- @field strip integer? @ Number of leading digits removed from matching numbers.
+--- @field prefix string? @ Digits added to the front of matching numbers, after stripping.
+--- @field agent string? @ Name of the agent that matching numbers are routed to. If unset, the rewritten number is passed on.
+--- @field complete boolean? @ Places the call as soon as a fixed-length pattern matches, without waiting out the post-dial delay.
+
+--- Adds a rule to the end of the dial plan, replacing any rule with the same pattern.
+---
+--- Patterns may contain digits, `*`, `#`, and:
+--- * `X` - any digit from 0 to 9
+--- * `N` - any digit from 2 to 9
+--- * `Z` - any digit from 1 to 9
+--- * `[...]` - any of the listed digits or ranges, e.g. `[2-5]` or `[147]`
+--- * `.` - one or more further digits (end of pattern only)
+--- * `!` - zero or more further digits (end of pattern only)
+---
+--- Dashes, spaces and parentheses are ignored.
+--- @param rule DialRule
+function phone.add_dial_rule(rule) end
+
+--- Removes the dial plan rule with the specified pattern. Returns `true` if there was one.
+--- @param pattern string
+--- @return boolean
+function phone.remove_dial_rule(pattern) end
+
+--- @alias ServiceCodeAction
+--- | 'last-call-return' # Calls back the last agent that called the line.
+--- | 'redial' # Calls the last number dialed on the line.
+--- | 'volume' # Sets the line volume to the digit (0-9) dialed after the code.
+--- | 'call-forward' # Forwards calls to the line to the number dialed after the code.
+--- | 'cancel-call-forward' # Cancels call forwarding on the line.
+
+--- Binds a vertical service code (the two digits after `*`, or after `11` on rotary phones) to a handler, replacing any existing binding.
+---
+--- The handler can be a built-in action, `@` followed by the name of an agent to connect the call to,
+--- or a function called with the digits dialed after the code.
+--- If the function returns a number, that number is called; otherwise the user hears a confirmation tone and dial tone.
+--- @param code string
+--- @param handler ServiceCodeAction|string|fun(digits: string): string?
+function phone.register_service_code(code, handler) end
+
+--- Removes the binding of a vertical service code. Returns `true` if the code was bound.
+--- @param code string
+--- @return boolean
+function phone.unregister_service_code(code) end
+
+--- Gets the number that calls to the current line are forwarded to, if any.
+--- @return string?
+function phone.call_forward_number() end
+
+--- Sets the number that calls to the current line are forwarded to. Pass `nil` to cancel forwarding.
+--- Calls from agents can only be forwarded to other lines.
+--- @param number string?
+function phone.set_call_forward_number(number) end
+
+--- @class CallHistoryEntry
+--- @field line string @ Name of the line the call took place on.
+--- @field direction "outgoing" | "incoming" @ Whether the user placed the call or received it.
+--- @field agent string? @ Name of the last agent on the call.
+--- @field other_line string? @ Name of the other line, for calls between lines.
+--- @field dialed string? @ Number dialed by the user.
+--- @field time integer @ Unix time (in seconds) at which the call started.
+--- @field age integer @ Seconds since the call ended.
+--- @field answered boolean @ Whether the call was connected.
+--- @field duration number @ Length of time (in seconds) the call was connected.
+
+--- Gets the most recent finished calls on all lines, most recent first.
+--- The history survives restarts when `data-dir` is set in the config.
+--- @param count integer? @ Maximum number of calls to return. Returns the whole history if omitted.
+--- @return CallHistoryEntry[]
+function phone.call_history(count) end
+
+--- @class AgentCallStats
+--- @field times_called integer @ Number of times the user called the agent.
+--- @field calls_placed integer @ Number of times the agent called the user.
+--- @field missed_calls integer @ Number of calls from the agent that the user didn't answer.
+--- @field answered_calls integer @ Number of calls with the agent that were connected.
+--- @field last_call_time integer? @ Unix time (in seconds) at which the last call with the agent ended.
+--- @field last_call_age integer? @ Seconds since the last call with the agent ended.
+
+--- Gets the running call counts for the specified agent.
+--- The counts survive restarts when `data-dir` is set in the config.
+--- @param agent_name string
+--- @return AgentCallStats
+function phone.agent_call_stats(agent_name) end
+
 --- Forces the phone to dial the specified digit(s).
 function phone.dial(digits) end
 
@@ -10,6 +10,19 @@
 --- @field delay number? @ Add `delay` seconds of silence before the sound. Not affected by `speed`. (Default: `nil`)
 --- @field fadein number? @ Fades in the sound over `fadein` seconds. Not affected by `speed`. (Default: `0`)
 
+--- @class LineFilterOptions
+--- @field band_pass boolean? @ Limits sounds to the bandwidth of a telephone line.
+--- @field low_cutoff number? @ Lower cutoff frequency (in Hz) of the band-pass filter.
+--- @field high_cutoff number? @ Upper cutoff frequency (in Hz) of the band-pass filter.
+--- @field companding 'mu-law' | 'a-law' | 'none'? @ Companding law whose quantization noise is added to sounds.
+--- @field soft_clip boolean? @ Soft-clips loud sounds.
+--- @field drive number? @ Gain (in dB) applied before soft clipping.
+--- @field hum_gain number? @ Gain (in dB) of mains hum.
+--- @field hum_frequency number? @ Frequency (in Hz) of mains hum.
+--- @field crackle number? @ Amount of crackle, from `0.0` (none) to `1.0` (a very bad line).
+
+--- @alias EffectName 'reverb' | 'echo' | 'pitch_shift' | 'bitcrush' | 'ring_mod' | 'distortion'
+
 --- Provides functions for controlling multi-channel sound playback.
 --- @class SoundLib
 sound = {}
@@ -68,6 +81,46 @@ function sound.is_channel_muted(channel) end
 ---@param muted boolean @ The muted status to set on the channel.
 function sound.set_channel_muted(channel, muted) end
 
+--- Sets the telephone line filter of the specified sound channel. Applies to sounds that are already playing.
+--- The filter is reset to the one in the config after each call.
+--- @param channel Channel @ The sound channel whose filter to change.
+--- @param filter boolean | LineFilterOptions @ `true` to use the filter from the config, `false` to disable the filter, or options that override the filter from the config.
+function sound.set_channel_filter(channel, filter) end
+
+--- Enables, disables or changes an effect on the specified sound channel. Applies to sounds that are already playing.
+--- Effects are applied in the order `pitch_shift`, `ring_mod`, `distortion`, `bitcrush`, `echo`, `reverb`, before the line filter, and are removed after each call.
+---
+--- Parameters of each effect (with their defaults):
+--- * `reverb`: `room_size` (0.5), `damping` (0.5), `mix` (0.3)
+--- * `echo`: `delay` in seconds, up to 2 (0.3), `feedback` (0.4), `mix` (0.5)
+--- * `pitch_shift`: `semitones` (0), `mix` (1); doesn't change tempo
+--- * `bitcrush`: `bits` (8), `downsample` factor (1), `mix` (1)
+--- * `ring_mod`: `frequency` in Hz (30), `mix` (1)
+--- * `distortion`: `drive` in dB (20), `mix` (1)
+--- @param channel Channel @ The sound channel whose effect to change.
+--- @param effect EffectName @ The effect to change.
+--- @param params boolean | table<string, number> | nil @ `false` to disable the effect, `true` or `nil` to enable it with default parameters, or parameters that override the defaults.
+function sound.set_channel_effect(channel, effect, params) end
+
+--- Gets a parameter of an effect on the specified sound channel.
+--- @param channel Channel
+--- @param effect EffectName
+--- @param param string
+--- @return number? @ The parameter value, or `nil` if the effect isn't enabled on the channel.
+--- @nodiscard
+function sound.get_channel_effect_param(channel, effect, param) end
+
+--- Sets a parameter of an effect that is enabled on the specified sound channel.
+--- @param channel Channel
+--- @param effect EffectName
+--- @param param string
+--- @param value number
+function sound.set_channel_effect_param(channel, effect, param, value) end
+
+--- Disables all effects on the specified sound channel.
+--- @param channel Channel
+function sound.clear_channel_effects(channel) end
+
 --- Sets the master volume.
 --- @param volume number
 function sound.set_master_volume(volume) end
@@ -0,0 +1,40 @@
+--- @meta
+
+--- Provides key-value storage that persists across calls and restarts.
+---
+--- Values are grouped into namespaces, each of which is saved to its own file in the `store` folder of the configured data directory.
+--- Namespace names may only contain letters, digits, `_`, `-` and `.`.
+--- If no data directory is configured, values are kept in memory until the engine stops.
+---
+--- Stored values can be booleans, numbers, strings, or tables containing them (keyed by strings or integers).
+--- Tables are copied when stored and when read back, so changing a table after storing it has no effect until it is stored again.
+--- @class StoreLib
+store = {}
+
+--- Gets the value stored under a key, or `nil` if there is none.
+--- @param namespace string
+--- @param key string
+--- @return any
+function store.get(namespace, key) end
+
+--- Stores a value under a key and saves the namespace. Storing `nil` deletes the key.
+--- @param namespace string
+--- @param key string
+--- @param value boolean|number|string|table|nil
+function store.set(namespace, key, value) end
+
+--- Deletes the value stored under a key and saves the namespace.
+--- Returns `true` if there was a value to delete.
+--- @param namespace string
+--- @param key string
+--- @return boolean
+function store.delete(namespace, key) end
+
+--- Gets the keys of all values in a namespace, in sorted order.
+--- @param namespace string
+--- @return string[]
+function store.keys(namespace) end
+
+--- Deletes all values in a namespace and saves it.
+--- @param namespace string
+function store.clear(namespace) end
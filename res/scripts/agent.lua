@@ -38,7 +38,10 @@ AgentState = {
     --- Agent is being called by the host.
     CALL_IN = 2,
     --- Agent is in a call.
-    CALL = 3
+    CALL = 3,
+    --- Agent is in a call that the user has put on hold.
+    --- Sounds played in this state are still heard by the user, so hold handlers should normally stay quiet.
+    HOLD = 4
 }
 
 --- @enum AgentRole
@@ -80,7 +83,7 @@ local M_AgentModule = {
 
 --- Creates a new phone agent module.
 --- @param name string @ The display name of the phone agent
---- @param phone_number string? @ The number associated with the phone agent
+--- @param phone_number string? @ The number associated with the phone agent; may be a dial pattern such as `555-XXXX` (see `phone.add_dial_rule`)
 --- @param role AgentRole? @ The role of the agent in the system; defaults to regular role
 --- @return AgentModule
 function AgentModule(name, phone_number, role)
@@ -112,7 +115,7 @@ function AgentModule(name, phone_number, role)
         _messages = messages
     }, M_AgentModule)
 
-    agent:set_sound_banks_loaded_during(AgentState.CALL_OUT, AgentState.CALL)
+    agent:set_sound_banks_loaded_during(AgentState.CALL_OUT, AgentState.CALL, AgentState.HOLD)
 
     return agent
 end
@@ -144,7 +147,7 @@ function C_AgentModule:on_load(handler)
 end
 
 --- Sets the unload handler for the agent.
---- This handler runs before the agent module has been unloaded on engine shutdown.
+--- This handler runs before the agent module has been unloaded on engine shutdown, or before it is replaced by a reload.
 --- @param handler fun(self: AgentModule)
 function C_AgentModule:on_unload(handler)
     assert(type(handler) == 'function', "Handler must be a function")
@@ -158,6 +161,60 @@ function C_AgentModule:start()
     return true
 end
 
+--- Gets the running call counts for the agent.
+--- @return AgentCallStats
+function C_AgentModule:call_stats()
+    return phone.agent_call_stats(self._name)
+end
+
+--- Gets the name of the agent that this agent is on a call with, if any.
+--- @return string?
+function C_AgentModule:agent_call_partner()
+    return phone.agent_call_partner(self._name)
+end
+
+--- Ends the agent's call with another agent. The other agent receives an `agent_call_ended` message.
+--- @return boolean @ Indicates whether the agent was on a call with another agent.
+function C_AgentModule:end_agent_call()
+    return phone.end_agent_call(self._name)
+end
+
+--- Binds a vertical service code to the agent, so that dialing it connects the call to the agent.
+--- The agent can read the full dialed number with `phone.call_dialed_number()`.
+--- @param code string @ The two digits after `*` (or `11` on rotary phones)
+function C_AgentModule:bind_service_code(code)
+    phone.register_service_code(code, "@" .. self._name)
+end
+
+--- Gets a value from the agent's persistent store, or `nil` if there is none.
+--- @param key string
+--- @return any
+function C_AgentModule:store_get(key)
+    return store.get(self._name, key)
+end
+
+--- Saves a value to the agent's persistent store. Saving `nil` deletes the key.
+--- @param key string
+--- @param value boolean|number|string|table|nil
+function C_AgentModule:store_set(key, value)
+    store.set(self._name, key, value)
+end
+
+--- Deletes a value from the agent's persistent store.
+--- @param key string
+--- @return boolean
+function C_AgentModule:store_delete(key)
+    return store.delete(self._name, key)
+end
+
+--- Restarts the agent's state machine from the idle state.
+--- Unlike a transition, the current state's exit handler does not run, and any pending message handler is discarded.
+function C_AgentModule:restart()
+    self._message_coroutine = nil
+    self._state = nil
+    transition_agent_state(self, AgentState.IDLE)
+end
+
 --- Sends a message to another agent.
 --- @param dest_name string
 --- @param msg_type AgentMessageKey
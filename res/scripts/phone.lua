@@ -25,7 +25,11 @@ CallReason = {
     --- Call was placed by the user.
     USER_INIT = 3,
     --- Call was placed by an agent.
-    AGENT_INIT = 4
+    AGENT_INIT = 4,
+    --- Agent was bridged into a call that was already in progress.
+    CONFERENCE = 5,
+    --- Call was answered by the answering machine.
+    VOICEMAIL = 6
 }
 
 
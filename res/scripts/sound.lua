@@ -150,6 +150,27 @@ function sound.fade_out(channels, duration)
     end
 end
 
+--- @async
+--- *(Agent use only)*
+---
+--- Changes a parameter of an effect on the specified channel to `to_value` over `duration` seconds.
+--- Stops early if the effect is disabled.
+--- @param channel Channel @ The channel whose effect to change
+--- @param effect EffectName @ The effect to change
+--- @param param string @ The effect parameter to change
+--- @param to_value number @ The value to change the parameter to
+--- @param duration number @ The duration of the change in seconds
+--- @param ease_func? fun(x: number): number @ Provides an easing function to use for the change.
+function sound.tween_effect_param(channel, effect, param, to_value, duration, ease_func)
+    ease_func = ease_func or ease.linear
+    local from_value = sound.get_channel_effect_param(channel, effect, param)
+    if from_value == nil then return end
+    task.tween(duration, function(_, _, progress)
+        if sound.get_channel_effect_param(channel, effect, param) == nil then return end
+        sound.set_channel_effect_param(channel, effect, param, math.lerp(from_value, to_value, ease_func(progress), true))
+    end)
+end
+
 --- @async
 --- *(Agent use only)*
 ---
@@ -20,6 +20,12 @@ IntentCode = {
     FORWARD_CALL = 6,
     --- Agent wants to end its current state and transition to another one.
     STATE_END = 7,
+    --- Agent wants to know if the user flashed the switchhook.
+    READ_HOOKFLASH = 8,
+    --- Agent wants to bridge another agent into its call.
+    BRIDGE_CALL = 9,
+    --- Agent wants to call another agent.
+    CALL_AGENT = 10,
 }
 
 --- @enum IntentResponseCode
@@ -33,6 +39,8 @@ IntentResponseCode = {
     LINE_BUSY = 2,
     --- Indicates that a phrase was recognized.
     SPEECH = 3,
+    --- Indicates that the user flashed the switchhook.
+    HOOKFLASH = 4,
 }
 
 --- @async
@@ -390,9 +398,10 @@ end
 
 --- @async
 --- Starts a call with the user, if the line is open.
+--- @param ring_pattern (string|RingPattern)? @ Ring pattern for this call only; takes priority over ring rules and the agent's custom ring pattern.
 --- @return boolean
-function task.start_call()
-    local data_code = task.intent(IntentCode.CALL_USER)
+function task.start_call(ring_pattern)
+    local data_code = task.intent(IntentCode.CALL_USER, ring_pattern)
     return data_code ~= IntentResponseCode.LINE_BUSY
 end
 
@@ -408,6 +417,26 @@ function task.end_call()
     yield(IntentCode.END_CALL)
 end
 
+--- @async
+--- Bridges another agent into the current call, so that the user can talk to both at once.
+--- The bridged agent enters its `CALL` state with the `CONFERENCE` call reason.
+--- @param destination string @ The phone number or agent handle (`@name`) of the agent to bridge.
+--- @return boolean @ Indicates whether the agent was bridged. Fails if the agent is busy or the conference is full.
+function task.bridge_call(destination)
+    local data_code = task.intent(IntentCode.BRIDGE_CALL, destination)
+    return data_code ~= IntentResponseCode.LINE_BUSY
+end
+
+--- @async
+--- Calls another agent without involving the user. The call is connected right away, and the other agent receives an `agent_call` message.
+--- While the agents are talking to each other, they appear busy to the user and can't call the user themselves.
+--- @param destination string @ The phone number or agent handle (`@name`) of the agent to call.
+--- @return boolean @ Indicates whether the call was connected. Fails if either agent is already on a call.
+function task.call_agent(destination)
+    local data_code = task.intent(IntentCode.CALL_AGENT, destination)
+    return data_code ~= IntentResponseCode.LINE_BUSY
+end
+
 --- @async
 --- Asynchronously waits for the user to dial a digit, then returns the digit as a string.
 --- If a timeout is specified, and no digit is entered within that time, this function returns `nil`.
@@ -434,6 +463,23 @@ function task.read_digit(timeout)
     end
 end
 
+--- @async
+--- Asynchronously waits for the user to flash the switchhook.
+--- If a timeout is specified, and there's no hookflash within that time, this function returns `false`.
+--- @param timeout number? @ The maximum amount of time in seconds to wait for.
+--- @return boolean
+function task.wait_flash(timeout)
+    local timed = is_number(timeout) and timeout > 0
+    local start_time = engine_time()
+    while not timed or engine_time() - start_time < timeout do
+        local data_code = task.intent(IntentCode.READ_HOOKFLASH)
+        if data_code == IntentResponseCode.HOOKFLASH then
+            return true
+        end
+    end
+    return false
+end
+
 --- @async
 --- @return string?
 function task.read_digits(digit_count, digit_timeout)
@@ -0,0 +1,35 @@
+-- Hangs up when called and calls the user back, leaving a message on PHONE01 if the answering machine picks up.
+local agent = AgentModule("callback", "5550130")
+agent:set_ringback_enabled(false)
+
+local wants_callback = false
+
+agent:state(AgentState.IDLE, {
+    tick = function(self)
+        if wants_callback then
+            -- A successful call leaves this state before start_call() returns
+            wants_callback = false
+            wants_callback = not task.start_call()
+        end
+    end
+})
+
+agent:state(AgentState.CALL_IN, {
+    enter = function(self)
+        task.accept_call()
+    end
+})
+
+agent:state(AgentState.CALL, {
+    enter = function(self)
+        if self:get_call_reason() == CallReason.VOICEMAIL then
+            sound.play("intercept/emergency_stub", Channel.PHONE01)
+            task.wait(3.0)
+        else
+            wants_callback = true
+        end
+        task.end_call()
+    end
+})
+
+return agent
@@ -0,0 +1,13 @@
+-- Calls the "gossip" agent as soon as it starts and hangs up after ten seconds.
+local agent = AgentModule("chatterbox")
+
+agent:state(AgentState.IDLE, {
+    enter = function(self)
+        if task.call_agent("5550129") then
+            task.wait(10)
+            self:end_agent_call()
+        end
+    end
+})
+
+return agent
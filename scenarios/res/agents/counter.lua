@@ -0,0 +1,24 @@
+-- Counts calls in the agent store, answering the first call on PHONE01 and later calls on PHONE02.
+local agent = AgentModule("counter", "5550125")
+agent:set_ringback_enabled(false)
+
+agent:state(AgentState.CALL_IN, {
+    enter = function(self)
+        task.accept_call()
+    end
+})
+
+agent:state(AgentState.CALL, {
+    enter = function(self)
+        local visits = self:store_get("visits") or { count = 0 }
+        visits.count = visits.count + 1
+        self:store_set("visits", visits)
+        if visits.count == 1 then
+            sound.play("intercept/emergency_stub", Channel.PHONE01)
+        else
+            sound.play("intercept/emergency_stub", Channel.PHONE02)
+        end
+    end
+})
+
+return agent
@@ -0,0 +1,21 @@
+-- Answers every number in the 666 exchange, playing PHONE01 for 666-1234 and PHONE02 for anything else, to exercise the dial plan.
+local agent = AgentModule("directory", "666-XXXX")
+agent:set_ringback_enabled(false)
+
+agent:state(AgentState.CALL_IN, {
+    enter = function(self)
+        task.accept_call()
+    end
+})
+
+agent:state(AgentState.CALL, {
+    enter = function(self)
+        if phone.call_dialed_number() == "6661234" then
+            sound.play("intercept/emergency_stub", Channel.PHONE01)
+        else
+            sound.play("intercept/emergency_stub", Channel.PHONE02)
+        end
+    end
+})
+
+return agent
@@ -0,0 +1,22 @@
+-- Raises an error the first time it is called, to exercise agent recovery.
+local agent = AgentModule("flaky", "5550123")
+agent:set_ringback_enabled(false)
+
+agent:state(AgentState.CALL_IN, {
+    enter = function(self)
+        if not self.has_failed then
+            self.has_failed = true
+            error("simulated script error")
+        end
+        task.accept_call()
+    end
+})
+
+agent:state(AgentState.CALL, {
+    enter = function(self)
+        task.wait(5.0)
+        task.end_call()
+    end
+})
+
+return agent
@@ -0,0 +1,19 @@
+-- Answers calls and plays PHONE01 once the user flashes the switchhook.
+local agent = AgentModule("flash_waiter", "5550127")
+agent:set_ringback_enabled(false)
+
+agent:state(AgentState.CALL_IN, {
+    enter = function(self)
+        task.accept_call()
+    end
+})
+
+agent:state(AgentState.CALL, {
+    enter = function(self)
+        if task.wait_flash() then
+            sound.play("intercept/emergency_stub", Channel.PHONE01)
+        end
+    end
+})
+
+return agent
@@ -0,0 +1,17 @@
+-- Answers calls and plays PHONE01, but is busy talking to the "chatterbox" agent for the first ten seconds.
+local agent = AgentModule("gossip", "5550129")
+agent:set_ringback_enabled(false)
+
+agent:state(AgentState.CALL_IN, {
+    enter = function(self)
+        task.accept_call()
+    end
+})
+
+agent:state(AgentState.CALL, {
+    enter = function(self)
+        sound.play("intercept/emergency_stub", Channel.PHONE01)
+    end
+})
+
+return agent
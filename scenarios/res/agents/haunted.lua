@@ -0,0 +1,29 @@
+-- Talks through a chain of effects while tweening its pitch down, and hangs up if the effects don't behave as expected.
+local agent = AgentModule("haunted", "5550166")
+
+agent:state(AgentState.CALL_IN, {
+    enter = function(self)
+        task.accept_call()
+    end
+})
+
+agent:state(AgentState.CALL, {
+    enter = function(self)
+        sound.set_channel_effect(Channel.PHONE01, "reverb", { room_size = 0.9, mix = 0.5 })
+        sound.set_channel_effect(Channel.PHONE01, "echo", { delay = 0.25 })
+        sound.set_channel_effect(Channel.PHONE01, "pitch_shift")
+        sound.set_channel_effect(Channel.PHONE01, "bitcrush", { bits = 6, downsample = 2 })
+        sound.set_channel_effect(Channel.PHONE01, "ring_mod", { frequency = 20 })
+        sound.set_channel_effect(Channel.PHONE01, "distortion", true)
+        sound.play("intercept/emergency_stub", Channel.PHONE01, { looping = true })
+        sound.tween_effect_param(Channel.PHONE01, "pitch_shift", "semitones", -7, 2.0)
+        sound.set_channel_effect(Channel.PHONE01, "ring_mod", false)
+        local semitones = sound.get_channel_effect_param(Channel.PHONE01, "pitch_shift", "semitones")
+        local ring_mod_frequency = sound.get_channel_effect_param(Channel.PHONE01, "ring_mod", "frequency")
+        if semitones ~= -7 or ring_mod_frequency ~= nil then
+            task.end_call()
+        end
+    end
+})
+
+return agent
@@ -0,0 +1,36 @@
+-- Stays on the line when called, then calls the user back and gives up if nobody answers within a few seconds.
+local agent = AgentModule("impatient", "5550131")
+agent:set_ringback_enabled(false)
+
+local wants_callback = false
+
+agent:state(AgentState.IDLE, {
+    tick = function(self)
+        if wants_callback then
+            -- A successful call leaves this state before start_call() returns
+            wants_callback = false
+            wants_callback = not task.start_call()
+        end
+    end
+})
+
+agent:state(AgentState.CALL_IN, {
+    enter = function(self)
+        task.accept_call()
+    end
+})
+
+agent:state(AgentState.CALL, {
+    enter = function(self)
+        wants_callback = true
+    end
+})
+
+agent:state(AgentState.CALL_OUT, {
+    enter = function(self)
+        task.wait(4.0)
+        task.end_call()
+    end
+})
+
+return agent
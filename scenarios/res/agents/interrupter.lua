@@ -0,0 +1,20 @@
+-- Calls the user once while they are talking to the "patient" agent, playing PHONE02 when answered, to exercise call waiting.
+local agent = AgentModule("interrupter")
+
+local has_called = false
+
+agent:state(AgentState.IDLE, {
+    tick = function(self)
+        if not has_called and phone.call_dialed_number() == "5550126" then
+            has_called = task.start_call()
+        end
+    end
+})
+
+agent:state(AgentState.CALL, {
+    enter = function(self)
+        sound.play("intercept/emergency_stub", Channel.PHONE02)
+    end
+})
+
+return agent
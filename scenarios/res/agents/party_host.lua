@@ -0,0 +1,18 @@
+-- Answers calls, plays PHONE01 and bridges the "patient" agent into the call.
+local agent = AgentModule("party_host", "5550128")
+agent:set_ringback_enabled(false)
+
+agent:state(AgentState.CALL_IN, {
+    enter = function(self)
+        task.accept_call()
+    end
+})
+
+agent:state(AgentState.CALL, {
+    enter = function(self)
+        sound.play("intercept/emergency_stub", Channel.PHONE01, { looping = true })
+        task.bridge_call("5550126")
+    end
+})
+
+return agent
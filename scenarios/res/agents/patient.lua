@@ -0,0 +1,17 @@
+-- Answers calls and plays PHONE01, to be interrupted by the "interrupter" agent.
+local agent = AgentModule("patient", "5550126")
+agent:set_ringback_enabled(false)
+
+agent:state(AgentState.CALL_IN, {
+    enter = function(self)
+        task.accept_call()
+    end
+})
+
+agent:state(AgentState.CALL, {
+    enter = function(self)
+        sound.play("intercept/emergency_stub", Channel.PHONE01)
+    end
+})
+
+return agent
@@ -0,0 +1,22 @@
+-- Answers first-time callers on PHONE01 and repeat callers on PHONE02, to exercise the call history.
+local agent = AgentModule("regular", "5550124")
+agent:set_ringback_enabled(false)
+
+agent:state(AgentState.CALL_IN, {
+    enter = function(self)
+        task.accept_call()
+    end
+})
+
+agent:state(AgentState.CALL, {
+    enter = function(self)
+        local last_call = phone.call_history(1)[1]
+        if self:call_stats().times_called > 0 and last_call and last_call.agent == "regular" then
+            sound.play("intercept/emergency_stub", Channel.PHONE02)
+        else
+            sound.play("intercept/emergency_stub", Channel.PHONE01)
+        end
+    end
+})
+
+return agent
@@ -0,0 +1,25 @@
+-- Plays sounds from its load and unload handlers, so that scenarios can follow it being reloaded.
+local agent = AgentModule("reloadable", "5550169")
+agent:set_ringback_enabled(false)
+
+agent:on_load(function(self)
+    RELOADABLE_AGENT_ID = self:id()
+end)
+
+agent:on_unload(function(self)
+    sound.play("intercept/emergency_stub", Channel.BG01)
+end)
+
+agent:state(AgentState.CALL_IN, {
+    enter = function(self)
+        task.accept_call()
+    end
+})
+
+agent:state(AgentState.CALL, {
+    enter = function(self)
+        sound.play("intercept/emergency_stub", Channel.PHONE01)
+    end
+})
+
+return agent
@@ -0,0 +1,20 @@
+-- Plays streamed sounds with looping, skip and take, and hangs up if their durations aren't known.
+local agent = AgentModule("streamer", "5550167")
+
+agent:state(AgentState.CALL_IN, {
+    enter = function(self)
+        task.accept_call()
+    end
+})
+
+agent:state(AgentState.CALL, {
+    enter = function(self)
+        local _, ogg_duration = sound.play("intercept/emergency_stub", Channel.PHONE01, { looping = true, skip = "random" })
+        local _, wav_duration = sound.play("ambient/static", Channel.PHONE02, { looping = true, take = 0.5 })
+        if not ogg_duration or ogg_duration <= 0 or wav_duration ~= 0.5 then
+            task.end_call()
+        end
+    end
+})
+
+return agent
@@ -0,0 +1,17 @@
+-- Fails to load the first time, to exercise recovery of agents that can't start.
+local agent = AgentModule("fragile", "5550168")
+
+agent:on_load(function(self)
+    if not FRAGILE_AGENT_LOADED_BEFORE then
+        FRAGILE_AGENT_LOADED_BEFORE = true
+        error("simulated load error")
+    end
+end)
+
+agent:state(AgentState.CALL_IN, {
+    enter = function(self)
+        task.accept_call()
+    end
+})
+
+return agent
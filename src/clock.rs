@@ -0,0 +1,81 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use chrono::prelude::*;
+
+/// Provides the current time to the engine and its peripherals.
+pub trait Clock: Send + Sync {
+    /// Gets the current monotonic time.
+    fn now(&self) -> Instant;
+
+    /// Gets the current local date and time.
+    fn local_now(&self) -> DateTime<Local>;
+
+    /// Blocks until the specified amount of time has passed.
+    fn sleep(&self, time: Duration);
+}
+
+/// A clock that follows the system time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    #[inline]
+    fn local_now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+
+    fn sleep(&self, time: Duration) {
+        spin_sleep::sleep(time);
+    }
+}
+
+/// A clock that only moves forward when it is explicitly advanced.
+pub struct SimulatedClock {
+    /// Monotonic time at which the clock was created.
+    origin: Instant,
+    /// Local time at which the clock was created.
+    origin_local: DateTime<Local>,
+    /// Simulated time elapsed since creation.
+    elapsed_nanos: AtomicU64,
+}
+
+impl SimulatedClock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            origin_local: Local::now(),
+            elapsed_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Moves the clock forward by the specified amount of time.
+    pub fn advance(&self, time: Duration) {
+        self.elapsed_nanos.fetch_add(time.as_nanos() as u64, Ordering::SeqCst);
+    }
+
+    /// Gets the amount of simulated time elapsed since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed_nanos.load(Ordering::SeqCst))
+    }
+}
+
+impl Clock for SimulatedClock {
+    #[inline]
+    fn now(&self) -> Instant {
+        self.origin + self.elapsed()
+    }
+
+    #[inline]
+    fn local_now(&self) -> DateTime<Local> {
+        self.origin_local + chrono::Duration::from_std(self.elapsed()).unwrap_or_else(|_| chrono::Duration::max_value())
+    }
+
+    /// Moves the clock forward instead of blocking.
+    fn sleep(&self, time: Duration) {
+        self.advance(time);
+    }
+}
@@ -0,0 +1,127 @@
+use enum_iterator::Sequence;
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AgentRole {
+    Normal = 0,
+    Intercept = 1,
+    Tollmaster = 2
+}
+
+const ALL_AGENT_ROLES: &[AgentRole] = { use AgentRole::*; &[Normal, Intercept, Tollmaster] };
+
+impl From<usize> for AgentRole {
+    fn from(value: usize) -> AgentRole {
+        ALL_AGENT_ROLES[value]
+    }
+}
+
+/// Provides reason codes to pass to an agent when connects to a call.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CallReason {
+    /// No call reason given.
+    None = 0,
+    /// Call was placed because of an off-hook timeout.
+    OffHook = 1,
+    /// Call was placed because the originally dialed number was disconnected.
+    NumberDisconnected = 2,
+    /// Call was placed by the user.
+    UserInit = 3,
+    /// Call was placed by an agent.
+    AgentInit = 4,
+    /// Agent was bridged into a call that was already in progress.
+    Conference = 5,
+    /// Call was answered by the answering machine.
+    Voicemail = 6
+}
+
+impl From<usize> for CallReason {
+    fn from(value: usize) -> Self {
+        use CallReason::*;
+        match value {
+            0 => None,
+            1 => OffHook,
+            2 => NumberDisconnected,
+            3 => UserInit,
+            4 => AgentInit,
+            5 => Conference,
+            6 => Voicemail,
+            _ => None
+        }
+    }
+}
+
+impl CallReason {
+    pub fn as_index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Represents a playback channel for sounds.
+#[derive(Sequence, Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Channel {
+    /// Channel for incoming telephony signal tones.
+    SignalIn,
+    /// Channel for incoming comfort noise.
+    NoiseIn,
+    /// Channel for outgoing telephony signal tones.
+    SignalOut,
+    /// Phone Channel 1.
+    Phone01,
+    /// Phone Channel 2.
+    Phone02,
+    /// Phone Channel 3.
+    Phone03,
+    /// Phone Channel 4.
+    Phone04,
+    /// Phone Channel 5.
+    Phone05,
+    /// Phone Channel 6.
+    Phone06,
+    /// Phone Channel 7.
+    Phone07,
+    /// Phone Channel 8.
+    Phone08,
+    /// Phone Channel 9.
+    Phone09,
+    /// Phone Channel 10.
+    Phone10,
+    /// Soul Channel 1.
+    Soul1,
+    /// Soul Channel 2.
+    Soul2,
+    /// Soul Channel 3.
+    Soul3,
+    /// Soul Channel 4.
+    Soul4,
+    /// Background Channel 1.
+    Bg1,
+    /// Background Channel 2.
+    Bg2,
+    /// Background Channel 3.
+    Bg3,
+    /// Background Channel 4.
+    Bg4,
+    /// Debug channel.
+    Debug,
+}
+
+impl Channel {
+    pub fn as_index(self) -> usize {
+        self as usize
+    }
+}
+
+impl From<usize> for Channel {    
+    fn from(val: usize) -> Self {
+        ALL_CHANNELS[val]
+    }
+}
+
+pub const ALL_CHANNELS: &[Channel] = { use Channel::*; &[SignalIn, NoiseIn, SignalOut, Phone01, Phone02, Phone03, Phone04, Phone05, Phone06, Phone07, Phone08, Phone09, Phone10, Soul1, Soul2, Soul3, Soul4, Bg1, Bg2, Bg3, Bg4, Debug] };
+pub const PHONE_CHANNELS: &[Channel] = { use Channel::*; &[Phone01, Phone02, Phone03, Phone04, Phone05, Phone06, Phone07, Phone08, Phone09, Phone10] };
+pub const SOUL_CHANNELS: &[Channel] = { use Channel::*; &[Soul1, Soul2, Soul3, Soul4] };
+pub const BG_CHANNELS: &[Channel] = { use Channel::*; &[Bg1, Bg2, Bg3, Bg4] };
+pub const NON_SOUL_CHANNELS: &[Channel] = { use Channel::*; &[SignalIn, NoiseIn, SignalOut, Phone01, Phone02, Phone03, Phone04, Phone05, Phone06, Phone07, Phone08, Phone09, Phone10, Bg1, Bg2, Bg3, Bg4, Debug] };
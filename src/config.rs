@@ -1,6 +1,9 @@
 use std::fs;
+use std::collections::HashMap;
+use std::path::Path;
 use serde::Deserialize;
 use toml;
+use crate::common::{AgentRole, CallReason, Channel, PHONE_CHANNELS, SOUL_CHANNELS, BG_CHANNELS};
 
 #[allow(non_camel_case_types)]
 type ms = u64;
@@ -12,9 +15,16 @@ pub struct CursedConfig {
     /// Higher is better, but will also consume more CPU cycles.
     pub tick_rate: f64,
 
+    /// Time source used by the engine.
+    #[serde(default)]
+    pub clock: ClockMode,
+
     /// Max number of instructions to allow per script execution
     pub script_execution_limit: Option<u32>,
 
+    /// Interval (in seconds) between checks for changed agent scripts. Disables hot-reloading if unset.
+    pub agent_reload_interval: Option<f32>,
+
     /// Direcories to load resources from
     pub include_resources: Vec<String>,
 
@@ -33,6 +43,15 @@ pub struct CursedConfig {
     /// Enables ringer.
     pub ringer_enabled: Option<bool>,
 
+    /// Lets a second agent call while the user is on a call with another agent.
+    pub call_waiting_enabled: Option<bool>,
+
+    /// Lets the user bridge a second agent into a call by flashing the switchhook and dialing its number.
+    pub three_way_calling_enabled: Option<bool>,
+
+    /// Sound key of the music played while the user waits for a call on hold to be picked back up.
+    pub hold_music: Option<String>,
+
     /// The default ring pattern expression assigned to agents.
     pub default_ring_pattern: String,
 
@@ -49,6 +68,19 @@ pub struct CursedConfig {
     /// (Only used if `shd_enabled == true`)
     pub shd_hangup_delay: f32,
 
+    /// Shortest number of seconds the switchhook must be held down to count as a hookflash.
+    /// 
+    /// Shorter presses are treated as switchhook dialing pulses (or ignored, if switchhook dialing is disabled).
+    #[serde(default = "default_hookflash_min_duration")]
+    pub hookflash_min_duration: f32,
+
+    /// Longest number of seconds the switchhook can be held down to count as a hookflash.
+    /// 
+    /// Holding the switchhook down for longer ends the call.
+    /// If switchhook dialing is enabled, `shd_hangup_delay` also limits the length of a hookflash.
+    #[serde(default = "default_hookflash_max_duration")]
+    pub hookflash_max_duration: f32,
+
     /// Rotary dial configuration.
     #[serde(default)]
     pub rotary: RotaryDialConfig,
@@ -61,6 +93,33 @@ pub struct CursedConfig {
     #[serde(default)]
     pub payphone: PayphoneConfig,
 
+    /// Agent error recovery configuration.
+    #[serde(default)]
+    pub agent_recovery: AgentRecoveryConfig,
+
+    /// Directory that persistent engine data (such as the call history) is saved to. Nothing is saved if unset.
+    pub data_dir: Option<String>,
+
+    /// Number of finished calls remembered by the call history.
+    #[serde(default = "default_call_history_size")]
+    pub call_history_size: usize,
+
+    /// Call detail record configuration.
+    #[serde(default)]
+    pub cdr: CdrConfig,
+
+    /// Answering machine configuration.
+    #[serde(default)]
+    pub voicemail: VoicemailConfig,
+
+    /// Caller ID configuration.
+    #[serde(default)]
+    pub caller_id: CallerIdConfig,
+
+    /// Telephone line filter configuration.
+    #[serde(default)]
+    pub line_filter: LineFilterConfig,
+
     /// Sound configuration.
     pub sound: SoundConfig,
 
@@ -68,7 +127,158 @@ pub struct CursedConfig {
     pub gpio: GpioConfig,
 
     /// Debug feature configuration.
-    pub debug: Option<DebugConfig>
+    pub debug: Option<DebugConfig>,
+
+    /// Dial plan rules, applied in order to numbers dialed by the user.
+    #[serde(default, rename = "dial-rule")]
+    pub dial_rules: Vec<DialRuleConfig>,
+
+    /// Ring pattern selection rules, applied in order to incoming calls.
+    #[serde(default, rename = "ring-rule")]
+    pub ring_rules: Vec<RingRuleConfig>,
+
+    /// Vertical service codes (without the `*` or `11` prefix) and the handlers bound to them.
+    /// Handlers are built-in action names, or `@` followed by an agent name.
+    #[serde(default = "default_vertical_service_codes")]
+    pub vertical_service_codes: HashMap<String, String>,
+
+    /// Host phone lines driven by the engine.
+    /// If empty, a single line is created from the top-level peripheral configuration.
+    #[serde(default, rename = "line")]
+    pub lines: Vec<LineConfig>,
+}
+
+fn default_call_history_size() -> usize {
+    100
+}
+
+fn default_hookflash_min_duration() -> f32 {
+    0.1
+}
+
+fn default_hookflash_max_duration() -> f32 {
+    0.6
+}
+
+fn default_call_waiting_tone_gain() -> f32 {
+    -13.0
+}
+
+fn default_vertical_service_codes() -> HashMap<String, String> {
+    [
+        ("11", "volume"),
+        ("66", "redial"),
+        ("69", "last-call-return"),
+        ("72", "call-forward"),
+        ("73", "cancel-call-forward"),
+    ].into_iter().map(|(code, action)| (code.to_owned(), action.to_owned())).collect()
+}
+
+impl CursedConfig {
+    /// Gets the line settings and peripheral configuration of each host phone line.
+    pub fn line_configs(&self) -> Vec<(LineConfig, CursedConfig)> {
+        if self.lines.is_empty() {
+            return vec![(LineConfig::default(), self.clone())]
+        }
+
+        self.lines.iter().map(|line| {
+            let mut config = self.clone();
+            config.lines.clear();
+            if let Some(gpio) = &line.gpio {
+                config.gpio = gpio.clone();
+            }
+            if let Some(rotary) = &line.rotary {
+                config.rotary = rotary.clone();
+            }
+            if let Some(keypad) = &line.keypad {
+                config.keypad = keypad.clone();
+            }
+            if line.output_device.is_some() {
+                config.sound.output_device = line.output_device.clone();
+            }
+            if line.input_device.is_some() {
+                config.sound.input_device = line.input_device.clone();
+            }
+            if let Some(ring_pattern) = &line.ring_pattern {
+                config.default_ring_pattern = ring_pattern.clone();
+            }
+            // Keep lines from recording over each other
+            match (&line.output_path, &self.sound.output_path) {
+                (Some(path), _) => config.sound.output_path = Some(path.clone()),
+                (None, Some(path)) => {
+                    let path = Path::new(path);
+                    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                    let file_name = match path.extension() {
+                        Some(ext) => format!("{}-{}.{}", stem, line.name, ext.to_string_lossy()),
+                        None => format!("{}-{}", stem, line.name),
+                    };
+                    config.sound.output_path = Some(path.with_file_name(file_name).to_string_lossy().into_owned());
+                },
+                (None, None) => {}
+            }
+            (line.clone(), config)
+        }).collect()
+    }
+}
+
+/// Name of the line created when no lines are configured.
+pub const DEFAULT_LINE_NAME: &str = "main";
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct LineConfig {
+    /// Name of the line.
+    pub name: String,
+
+    /// GPIO configuration of the line. Replaces the top-level `gpio` table.
+    pub gpio: Option<GpioConfig>,
+
+    /// Rotary dial configuration of the line. Replaces the top-level `rotary` table.
+    pub rotary: Option<RotaryDialConfig>,
+
+    /// Keypad configuration of the line. Replaces the top-level `keypad` table.
+    pub keypad: Option<KeypadConfig>,
+
+    /// Number that other lines dial to call this line.
+    pub extension: Option<String>,
+
+    /// Ring pattern expression used when the line is called. Replaces `default-ring-pattern`.
+    pub ring_pattern: Option<String>,
+
+    /// Name of the audio device that plays to the line's handset.
+    pub output_device: Option<String>,
+
+    /// Name of the audio device that captures the line's handset microphone.
+    pub input_device: Option<String>,
+
+    /// Path of the file written by the `wav` output for this line.
+    pub output_path: Option<String>,
+}
+
+impl Default for LineConfig {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_LINE_NAME.to_owned(),
+            gpio: None,
+            rotary: None,
+            keypad: None,
+            extension: None,
+            ring_pattern: None,
+            output_device: None,
+            input_device: None,
+            output_path: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClockMode {
+    /// Time follows the system clock and ticks run at `tick_rate`.
+    #[default]
+    Realtime,
+    /// Time advances by one tick interval per tick, as fast as the engine can run.
+    Simulated,
 }
 
 #[derive(Deserialize, Clone, Debug, Default)]
@@ -162,20 +372,350 @@ impl Default for PayphoneConfig {
     }
 }
 
+/// File format of the call detail record log.
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CdrFormat {
+    /// One JSON object per line.
+    #[default]
+    Jsonl,
+    /// Comma-separated values with a header row.
+    Csv,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct CdrConfig {
+    /// Path of the call detail record log. Disables logging if unset.
+    pub path: Option<String>,
+
+    /// Format of the log.
+    #[serde(default)]
+    pub format: CdrFormat,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct VoicemailConfig {
+    /// Enables the answering machine.
+    pub enabled: bool,
+
+    /// Number of rings after which an unanswered call from an agent is answered by the machine.
+    pub answer_after_rings: u32,
+
+    /// Number the user dials to listen to their messages.
+    pub access_number: String,
+
+    /// Longest message (in seconds) that an agent can leave.
+    pub max_message_length: f32,
+
+    /// Maximum number of messages kept. The oldest message is dropped to make room for a new one.
+    pub capacity: usize,
+}
+
+impl Default for VoicemailConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            answer_after_rings: 4,
+            access_number: String::from("*98"),
+            max_message_length: 60.0,
+            capacity: 20,
+        }
+    }
+}
+
+/// Encoding of caller ID sent between rings.
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CallerIdFormat {
+    /// Bell 202 FSK, Single Data Message Format (date, time and number).
+    Sdmf,
+    /// Bell 202 FSK, Multiple Data Message Format (date, time, number and name).
+    #[default]
+    Mdmf,
+    /// The number as DTMF digits.
+    Dtmf,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct CallerIdConfig {
+    /// Sends caller ID between the first and second rings of calls from agents.
+    pub enabled: bool,
+
+    /// Encoding of the caller ID burst.
+    pub format: CallerIdFormat,
+
+    /// Channel the caller ID burst is played on.
+    pub channel: Channel,
+
+    /// Time (in seconds) between the end of the first ring and the start of the burst.
+    pub delay: f32,
+
+    /// Gain (as dB) of the burst.
+    pub gain: f32,
+}
+
+impl Default for CallerIdConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            format: Default::default(),
+            channel: Channel::Soul4,
+            delay: 0.5,
+            gain: -13.5,
+        }
+    }
+}
+
+/// A group of sound channels.
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChannelGroup {
+    /// The incoming and outgoing signal tone channels.
+    Signal,
+    /// The comfort noise channel.
+    Noise,
+    /// The Phone channels, where agents play dialogue.
+    Phone,
+    /// The Soul channels.
+    Soul,
+    /// The background channels.
+    Bg,
+}
+
+impl ChannelGroup {
+    pub fn channels(self) -> &'static [Channel] {
+        match self {
+            ChannelGroup::Signal => &[Channel::SignalIn, Channel::SignalOut],
+            ChannelGroup::Noise => &[Channel::NoiseIn],
+            ChannelGroup::Phone => PHONE_CHANNELS,
+            ChannelGroup::Soul => SOUL_CHANNELS,
+            ChannelGroup::Bg => BG_CHANNELS,
+        }
+    }
+}
+
+/// Companding law simulated by the line filter.
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Companding {
+    /// No companding.
+    None,
+    /// 8-bit μ-law (G.711, North America and Japan).
+    #[default]
+    MuLaw,
+    /// 8-bit A-law (G.711, Europe and most other regions).
+    ALaw,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct LineFilterConfig {
+    /// Channel groups that the filter is enabled on.
+    pub channels: Vec<ChannelGroup>,
+
+    /// Limits the bandwidth of sounds to that of a telephone line.
+    pub band_pass: bool,
+
+    /// Lower cutoff frequency (in Hz) of the band-pass filter.
+    pub low_cutoff: f32,
+
+    /// Upper cutoff frequency (in Hz) of the band-pass filter.
+    pub high_cutoff: f32,
+
+    /// Companding law whose quantization noise is added to sounds.
+    pub companding: Companding,
+
+    /// Soft-clips loud sounds like an overdriven line.
+    pub soft_clip: bool,
+
+    /// Gain (as dB) applied before soft clipping.
+    pub drive: f32,
+
+    /// Gain (as dB) of mains hum. No hum is added if unset.
+    pub hum_gain: Option<f32>,
+
+    /// Frequency (in Hz) of mains hum.
+    pub hum_frequency: f32,
+
+    /// Amount of crackle, from 0.0 (none) to 1.0 (a very bad line).
+    pub crackle: f32,
+}
+
+impl Default for LineFilterConfig {
+    fn default() -> Self {
+        Self {
+            channels: vec![],
+            band_pass: true,
+            low_cutoff: 300.0,
+            high_cutoff: 3400.0,
+            companding: Default::default(),
+            soft_clip: true,
+            drive: 0.0,
+            hum_gain: None,
+            hum_frequency: 60.0,
+            crackle: 0.0,
+        }
+    }
+}
+
+/// A dial plan rule that matches dialed numbers against a pattern.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct DialRuleConfig {
+    /// Pattern matched against the whole dialed number.
+    pub pattern: String,
+
+    /// Number of leading digits removed from a matching number.
+    #[serde(default)]
+    pub strip: usize,
+
+    /// Digits added to the front of a matching number, after stripping.
+    pub prefix: Option<String>,
+
+    /// Name of the agent that matching numbers are routed to.
+    /// If unset, the rewritten number is passed on to the following rules and the phone book.
+    pub agent: Option<String>,
+
+    /// Places the call as soon as the dialed number matches, without waiting for the post-dial delay.
+    #[serde(default)]
+    pub complete: bool,
+}
+
+/// A rule that picks the ring pattern for incoming calls. Unset conditions match any call.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct RingRuleConfig {
+    /// Ring pattern expression used for matching calls.
+    pub pattern: String,
+
+    /// Name of the calling agent.
+    pub agent: Option<String>,
+
+    /// Role of the calling agent.
+    pub role: Option<AgentRole>,
+
+    /// Cron expression for the times at which the rule applies.
+    pub schedule: Option<String>,
+
+    /// Reason the calling agent gave for the call.
+    pub reason: Option<CallReason>,
+}
+
+/// What to do with an agent after it raises a Lua error.
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AgentRecoveryAction {
+    /// Leave the agent suspended until the engine restarts.
+    #[default]
+    Suspend,
+    /// Restart the agent's state machine from the idle state.
+    Restart,
+    /// Reload the agent's module from its script.
+    Reload,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct AgentRecoveryConfig {
+    /// Action taken after an agent error.
+    pub action: Option<AgentRecoveryAction>,
+
+    /// Delay (in seconds) before the first recovery attempt. Doubles with each consecutive error.
+    pub retry_delay: Option<f32>,
+
+    /// Maximum delay (in seconds) between recovery attempts.
+    pub max_retry_delay: Option<f32>,
+
+    /// Number of consecutive errors to recover from before leaving the agent suspended.
+    pub max_retries: Option<u32>,
+
+    /// Time (in seconds) without errors after which an agent's error count is reset.
+    pub retry_reset: Option<f32>,
+
+    /// Per-agent settings, by agent name. Unset values fall back to the global settings.
+    #[serde(default)]
+    pub agent: HashMap<String, AgentRecoveryConfig>,
+}
+
+/// Resolved error recovery settings for a single agent.
+#[derive(Clone, Debug)]
+pub struct AgentRecoverySettings {
+    pub action: AgentRecoveryAction,
+    pub retry_delay: f32,
+    pub max_retry_delay: f32,
+    pub max_retries: u32,
+    pub retry_reset: f32,
+}
+
+impl AgentRecoveryConfig {
+    /// Gets the recovery settings for the agent with the specified name.
+    pub fn for_agent(&self, agent_name: &str) -> AgentRecoverySettings {
+        let agent = self.agent.get(agent_name);
+        AgentRecoverySettings {
+            action: agent.and_then(|agent| agent.action).or(self.action).unwrap_or_default(),
+            retry_delay: agent.and_then(|agent| agent.retry_delay).or(self.retry_delay).unwrap_or(5.0),
+            max_retry_delay: agent.and_then(|agent| agent.max_retry_delay).or(self.max_retry_delay).unwrap_or(300.0),
+            max_retries: agent.and_then(|agent| agent.max_retries).or(self.max_retries).unwrap_or(5),
+            retry_reset: agent.and_then(|agent| agent.retry_reset).or(self.retry_reset).unwrap_or(3600.0),
+        }
+    }
+}
+
 #[derive(Deserialize, Clone, Debug)]
 #[serde(rename_all = "kebab-case")]
 pub struct SoundConfig {
+    /// Audio backend to play sounds through.
+    #[serde(default)]
+    pub output: SoundOutputType,
+    /// Name of the audio device used by the `device` output. Uses the default device if unspecified.
+    pub output_device: Option<String>,
+    /// Name of the audio device that captures the handset microphone for calls between lines.
+    pub input_device: Option<String>,
+    /// Path of the file written by the `wav` output.
+    pub output_path: Option<String>,
+    /// Sample rate (in Hz) of the `null` and `wav` outputs.
+    pub output_sample_rate: Option<u32>,
     /// Initial master volume.
     pub master_volume: f32,
     pub dtmf_volume: f32,
     pub dtmf_tone_duration_ms: ms,
+    /// Level (in dB) of the high-group (column) DTMF tone relative to the low-group (row) tone.
+    #[serde(default)]
+    pub dtmf_twist: f32,
+    /// Maximum random deviation (in %) applied to each DTMF frequency on every keypress.
+    #[serde(default)]
+    pub dtmf_drift: f32,
+    /// Name of the tone plan (in the `tone_plans` resource directory) that defines call progress tones.
+    /// Uses the North American Precise Tone Plan if unspecified.
+    pub tone_plan: Option<String>,
     pub dial_tone_gain: f32,
     pub ringback_tone_gain: f32,
     pub busy_tone_gain: f32,
     pub off_hook_tone_gain: f32,
+    #[serde(default = "default_call_waiting_tone_gain")]
+    pub call_waiting_tone_gain: f32,
     pub special_info_tone_gain: f32,
     pub comfort_noise_name: Option<String>,
-    pub comfort_noise_volume: f32
+    pub comfort_noise_volume: f32,
+    /// Sound files at least this large (in KiB) are streamed from disk instead of being decoded into memory.
+    pub stream_min_size_kb: Option<u64>,
+    /// Sounds at least this long are streamed from disk instead of being decoded into memory.
+    pub stream_min_duration_ms: Option<ms>,
+}
+
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SoundOutputType {
+    /// Plays sound through the default audio device.
+    #[default]
+    Device,
+    /// Consumes sound in real time without an audio device.
+    Null,
+    /// Records the mixed output of all channels to a WAV file.
+    Wav,
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -210,17 +750,33 @@ pub struct InputPinConfig {
 pub struct GpioOutputsConfig {
     /// BCM pin number of ringer output.
     pub pin_ringer: Option<u8>,
+    /// BCM pin number of message-waiting lamp output.
+    pub pin_mwi: Option<u8>,
 }
 
 #[derive(Deserialize, Clone, Debug)]
 #[serde(rename_all = "kebab-case")]
 pub struct DebugConfig {
-    /// Plays the panic tone when a Lua script encounters an error.
+    /// Plays the panic tone when a Lua script encounters an error. (default: `true`)
     pub enable_panic_tone: Option<bool>
 }
 
-pub fn load_config(path: &str) -> CursedConfig {
+pub fn load_config(path: &str, overrides: &toml::Table) -> CursedConfig {
     let config_str = fs::read_to_string(path).expect("Unable to read config file");
-    let config: CursedConfig = toml::from_str(&config_str).expect("Unable to parse config file");
+    let mut config_table: toml::Table = toml::from_str(&config_str).expect("Unable to parse config file");
+    merge_config_tables(&mut config_table, overrides);
+    let config: CursedConfig = toml::Value::Table(config_table).try_into().expect("Unable to parse config file");
     config
+}
+
+/// Recursively copies the values in `overrides` into `table`, replacing any existing non-table values.
+fn merge_config_tables(table: &mut toml::Table, overrides: &toml::Table) {
+    for (key, value) in overrides.iter() {
+        match (table.get_mut(key), value) {
+            (Some(toml::Value::Table(sub_table)), toml::Value::Table(sub_overrides)) => merge_config_tables(sub_table, sub_overrides),
+            _ => {
+                table.insert(key.clone(), value.clone());
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -5,6 +5,7 @@ use super::*;
 
 pub struct AgentModule<'lua> {
     id: RefCell<Option<AgentId>>,
+    script_path: VfsPath,
     name: String,
     phone_number: Option<String>,
     role: AgentRole,
@@ -16,6 +17,7 @@ pub struct AgentModule<'lua> {
     func_unload: Option<LuaFunction<'lua>>,
     func_tick: LuaFunction<'lua>,
     suspended: Cell<bool>,
+    unloaded: Cell<bool>,
 }
 
 impl<'lua> AgentModule<'lua> {
@@ -52,6 +54,7 @@ impl<'lua> AgentModule<'lua> {
 
         Ok(Self {
             id: Default::default(),
+            script_path: path.clone(),
             required_sound_banks,
             ringback_enabled,
             tbl_module: module,
@@ -63,6 +66,7 @@ impl<'lua> AgentModule<'lua> {
             func_unload,
             func_tick,
             suspended: Default::default(),
+            unloaded: Default::default(),
         })
     }
 
@@ -70,17 +74,17 @@ impl<'lua> AgentModule<'lua> {
         self.tbl_module.get::<&str, Option<LuaRingPattern>>("_custom_ring_pattern").ok().flatten().map(|p| p.0)
     }
 
-    pub fn load_sound_banks(&self, sound_engine: &Rc<RefCell<SoundEngine>>) {
-        let mut sound_engine = sound_engine.borrow_mut();
+    pub fn load_sound_banks(&self, sound_library: &Rc<RefCell<SoundLibrary>>) {
+        let mut sound_library = sound_library.borrow_mut();
         for bank_name in &self.required_sound_banks {
-            sound_engine.add_sound_bank_user(bank_name, SoundBankUser(self.id().unwrap()));
+            sound_library.add_sound_bank_user(bank_name, SoundBankUser(self.id().unwrap()));
         }
     }
 
-    pub fn unload_sound_banks(&self, sound_engine: &Rc<RefCell<SoundEngine>>) {
-        let mut sound_engine = sound_engine.borrow_mut();
+    pub fn unload_sound_banks(&self, sound_library: &Rc<RefCell<SoundLibrary>>) {
+        let mut sound_library = sound_library.borrow_mut();
         for bank_name in &self.required_sound_banks {
-            sound_engine.remove_sound_bank_user(bank_name, SoundBankUser(self.id().unwrap()), true);
+            sound_library.remove_sound_bank_user(bank_name, SoundBankUser(self.id().unwrap()), true);
         }
     }
 
@@ -88,6 +92,11 @@ impl<'lua> AgentModule<'lua> {
         self.tbl_module.call_method::<&str, _, bool>("start", ())
     }
 
+    /// Restarts the state machine from the idle state, abandoning whatever the agent was doing.
+    pub fn restart_state_machine(&self) -> Result<(), LuaError> {
+        self.tbl_module.call_method::<&str, _, ()>("restart", ())
+    }
+
     pub fn call_load_handler(&self) -> Result<(), LuaError> {
         if let Some(func_load) = &self.func_load {
             func_load.call::<LuaTable, ()>(self.tbl_module.clone())?;
@@ -96,6 +105,7 @@ impl<'lua> AgentModule<'lua> {
     }
 
     pub fn call_unload_handler(&self) -> Result<(), LuaError> {
+        if self.unloaded.replace(true) { return Ok(()) }
         if let Some(func_unload) = &self.func_unload {
             func_unload.call::<LuaTable, ()>(self.tbl_module.clone())?;
         }
@@ -119,6 +129,11 @@ impl<'lua> AgentModule<'lua> {
         self.name.as_str()
     }
 
+    /// Gets the path of the script the agent was loaded from.
+    pub fn script_path(&self) -> &VfsPath {
+        &self.script_path
+    }
+
     pub fn ringback_enabled(&self) -> bool {
         self.ringback_enabled
     }
@@ -144,6 +159,11 @@ impl<'lua> AgentModule<'lua> {
         }
     }
 
+    /// Gets the reason given for the agent's current call.
+    pub fn call_reason(&self) -> CallReason {
+        self.tbl_module.get::<&str, Option<usize>>("_call_reason").ok().flatten().map(CallReason::from).unwrap_or(CallReason::None)
+    }
+
     pub fn set_call_reason(&self, reason: CallReason) -> LuaResult<()> {
         self.tbl_module.call_method("set_call_reason", reason.as_index())?;
         Ok(())
@@ -167,7 +187,7 @@ impl<'lua> AgentModule<'lua> {
         let (intent_code, intent_data, should_continue) = match data {
             AgentIntentResponse::None => self.func_tick.call((agent_table, data_code))?,
             AgentIntentResponse::Digit(digit) => self.func_tick.call((agent_table, data_code, digit.to_string()))?,
-            AgentIntentResponse::LineBusy => self.func_tick.call((agent_table, data_code))?
+            AgentIntentResponse::LineBusy | AgentIntentResponse::Hookflash => self.func_tick.call((agent_table, data_code))?
         };
 
         let intent = AgentIntent::from_lua_value(intent_code, intent_data);
@@ -181,6 +201,11 @@ impl<'lua> AgentModule<'lua> {
         Ok((intent, continuation))
     }
 
+    /// Sends a message to another agent, as if this agent had sent it from Lua.
+    pub fn send_message(&self, dest_name: &str, msg_type: &str) -> LuaResult<()> {
+        self.tbl_module.call_method("send", (dest_name, msg_type))
+    }
+
     pub fn transition_state(&self, state: AgentState) -> LuaResult<()> {
         self.tbl_module.call_method("transition", state.as_index())?;
         Ok(())
@@ -189,6 +214,8 @@ impl<'lua> AgentModule<'lua> {
 
 impl<'lua> Drop for AgentModule<'lua> {
     fn drop(&mut self) {
+        // Agents replaced by a reload have already been unloaded
+        if self.unloaded.get() { return }
         if let Some(unload) = &self.func_unload {
             if let Err(error) = unload.call::<(), ()>(()) {
                 error!("Agent module '{}' encountered error while unloading: {:?}", self.name, error);
@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+use super::AgentId;
+
+/// Type of the message an agent receives when another agent calls it.
+pub const AGENT_CALL_MESSAGE: &str = "agent_call";
+/// Type of the message an agent receives when the other agent ends their call.
+pub const AGENT_CALL_ENDED_MESSAGE: &str = "agent_call_ended";
+
+/// Calls between agents that don't involve a host phone.
+#[derive(Default)]
+pub struct AgentCalls {
+    /// The agent on the other end of each agent's call.
+    partners: HashMap<AgentId, AgentId>,
+}
+
+impl AgentCalls {
+    /// Connects two agents. Returns `false` if either of them is already on a call.
+    pub fn connect(&mut self, caller: AgentId, callee: AgentId) -> bool {
+        if caller == callee || self.partners.contains_key(&caller) || self.partners.contains_key(&callee) {
+            return false
+        }
+        self.partners.insert(caller, callee);
+        self.partners.insert(callee, caller);
+        true
+    }
+
+    /// Ends the call of the specified agent and returns the agent on the other end, if there was a call.
+    pub fn disconnect(&mut self, agent: AgentId) -> Option<AgentId> {
+        let partner = self.partners.remove(&agent)?;
+        self.partners.remove(&partner);
+        Some(partner)
+    }
+
+    /// Gets the agent on the other end of the specified agent's call.
+    pub fn partner(&self, agent: AgentId) -> Option<AgentId> {
+        self.partners.get(&agent).copied()
+    }
+}
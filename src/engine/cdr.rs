@@ -0,0 +1,314 @@
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use chrono::prelude::*;
+use log::{info, error};
+use crate::config::*;
+
+/// Which side placed a call.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CallDirection {
+    /// The user picked up the phone and dialed out.
+    Outgoing,
+    /// The phone rang and the user answered (or didn't).
+    Incoming,
+}
+
+impl CallDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Outgoing => "outgoing",
+            Self::Incoming => "incoming",
+        }
+    }
+}
+
+/// Why a call ended.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DisconnectCause {
+    /// The user hung up a connected call.
+    UserHangup,
+    /// The other party ended a connected call.
+    RemoteHangup,
+    /// The user hung up before the call connected.
+    Abandoned,
+    /// The caller gave up before the user answered.
+    Unanswered,
+    /// The call could not be connected and the line went busy.
+    Busy,
+    /// The call was cut off because the payphone ran out of time credit.
+    CreditExhausted,
+}
+
+impl DisconnectCause {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UserHangup => "user-hangup",
+            Self::RemoteHangup => "remote-hangup",
+            Self::Abandoned => "abandoned",
+            Self::Unanswered => "unanswered",
+            Self::Busy => "busy",
+            Self::CreditExhausted => "credit-exhausted",
+        }
+    }
+}
+
+/// A call detail record describing a single call from pickup (or first ring) to hangup.
+#[derive(Clone, Debug)]
+pub struct CallRecord {
+    /// Name of the line the call took place on.
+    pub line: String,
+    /// Direction of the call.
+    pub direction: CallDirection,
+    /// Time the phone went off-hook or started ringing.
+    pub start_time: DateTime<Local>,
+    /// Time the call was connected, if it was.
+    pub answer_time: Option<DateTime<Local>>,
+    /// Time the line returned to idle.
+    pub end_time: DateTime<Local>,
+    /// Name of the last agent on the call.
+    pub agent: Option<String>,
+    /// Name of the other line, for calls between lines.
+    pub other_line: Option<String>,
+    /// Number dialed by the user.
+    pub dialed: Option<String>,
+    /// Why the call ended.
+    pub disconnect_cause: DisconnectCause,
+    /// Money deposited during the call.
+    pub deposited: u32,
+    /// Money consumed by the call.
+    pub consumed: u32,
+}
+
+impl CallRecord {
+    /// Starts a record for a call beginning at the specified time. Fields are filled in as the call progresses.
+    pub fn new(line: &str, direction: CallDirection, start_time: DateTime<Local>) -> Self {
+        Self {
+            line: line.to_owned(),
+            direction,
+            start_time,
+            answer_time: None,
+            end_time: start_time,
+            agent: None,
+            other_line: None,
+            dialed: None,
+            disconnect_cause: match direction {
+                CallDirection::Outgoing => DisconnectCause::Abandoned,
+                CallDirection::Incoming => DisconnectCause::Unanswered,
+            },
+            deposited: 0,
+            consumed: 0,
+        }
+    }
+
+    /// Gets the length of time the call was connected.
+    pub fn talk_time(&self) -> chrono::Duration {
+        self.answer_time.map_or_else(chrono::Duration::zero, |answer_time| self.end_time - answer_time)
+    }
+
+    fn values(&self) -> [CdrValue; CDR_FIELD_NAMES.len()] {
+        [
+            CdrValue::Text(Some(self.line.clone())),
+            CdrValue::Text(Some(self.direction.as_str().to_owned())),
+            CdrValue::Text(Some(self.start_time.to_rfc3339())),
+            CdrValue::Text(self.answer_time.map(|time| time.to_rfc3339())),
+            CdrValue::Text(Some(self.end_time.to_rfc3339())),
+            CdrValue::Number(self.talk_time().num_milliseconds() as f64 / 1000.0),
+            CdrValue::Text(self.agent.clone()),
+            CdrValue::Text(self.other_line.clone()),
+            CdrValue::Text(self.dialed.clone()),
+            CdrValue::Text(Some(self.disconnect_cause.as_str().to_owned())),
+            CdrValue::Number(self.deposited as f64),
+            CdrValue::Number(self.consumed as f64),
+        ]
+    }
+
+    /// Formats the record as a single-line JSON object.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{");
+        for (i, (key, value)) in CDR_FIELD_NAMES.iter().zip(self.values()).enumerate() {
+            if i > 0 { json.push(',') }
+            write!(json, "\"{}\":", key).unwrap();
+            match value {
+                CdrValue::Text(Some(text)) => write_json_string(&mut json, &text),
+                CdrValue::Text(None) => json.push_str("null"),
+                CdrValue::Number(n) => write!(json, "{}", n).unwrap(),
+            }
+        }
+        json.push('}');
+        json
+    }
+
+    /// Formats the record as a CSV row.
+    pub fn to_csv(&self) -> String {
+        self.values().into_iter().map(|value| match value {
+            CdrValue::Text(Some(text)) => csv_field(&text),
+            CdrValue::Text(None) => String::new(),
+            CdrValue::Number(n) => n.to_string(),
+        }).collect::<Vec<_>>().join(",")
+    }
+}
+
+/// Names of the fields in a record, in the order they are written.
+const CDR_FIELD_NAMES: [&str; 12] = [
+    "line", "direction", "start_time", "answer_time", "end_time", "talk_seconds",
+    "agent", "other_line", "dialed", "disconnect_cause", "deposited", "consumed",
+];
+
+enum CdrValue {
+    Text(Option<String>),
+    Number(f64),
+}
+
+fn write_json_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+/// Append-only log of finished calls.
+pub struct CallRecordLog {
+    path: PathBuf,
+    format: CdrFormat,
+}
+
+impl CallRecordLog {
+    pub fn from_config(config: &CdrConfig) -> Option<Self> {
+        let path = PathBuf::from(config.path.as_deref()?);
+        info!("Logging call detail records to: {}", path.display());
+        Some(Self {
+            path,
+            format: config.format,
+        })
+    }
+
+    /// Appends a record to the log.
+    pub fn write(&self, record: &CallRecord) {
+        if let Err(err) = self.try_write(record) {
+            error!("Failed to write call detail record to '{}': {}", self.path.display(), err);
+        }
+    }
+
+    fn try_write(&self, record: &CallRecord) -> std::io::Result<()> {
+        let is_new = self.path.metadata().map_or(true, |metadata| metadata.len() == 0);
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        let line = match self.format {
+            CdrFormat::Jsonl => record.to_json(),
+            CdrFormat::Csv => {
+                if is_new {
+                    writeln!(file, "{}", CDR_FIELD_NAMES.join(","))?;
+                }
+                record.to_csv()
+            }
+        };
+        writeln!(file, "{}", line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn start_time() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 3, 1, 12, 30, 0).unwrap()
+    }
+
+    fn answered_record() -> CallRecord {
+        let start_time = start_time();
+        let mut record = CallRecord::new("Line 1", CallDirection::Incoming, start_time);
+        record.answer_time = Some(start_time + chrono::Duration::seconds(5));
+        record.end_time = start_time + chrono::Duration::milliseconds(17500);
+        record.agent = Some("gossip".to_owned());
+        record.dialed = Some("5550123".to_owned());
+        record.disconnect_cause = DisconnectCause::RemoteHangup;
+        record.deposited = 25;
+        record.consumed = 10;
+        record
+    }
+
+    #[test]
+    fn missing_values_are_null_in_json_and_empty_in_csv() {
+        let record = CallRecord::new("Line 1", CallDirection::Outgoing, start_time());
+        let time = start_time().to_rfc3339();
+        assert_eq!(record.to_json(), format!(
+            "{{\"line\":\"Line 1\",\"direction\":\"outgoing\",\"start_time\":\"{0}\",\"answer_time\":null,\"end_time\":\"{0}\",\"talk_seconds\":0,\
+            \"agent\":null,\"other_line\":null,\"dialed\":null,\"disconnect_cause\":\"abandoned\",\"deposited\":0,\"consumed\":0}}", time));
+        assert_eq!(record.to_csv(), format!("Line 1,outgoing,{0},,{0},0,,,,abandoned,0,0", time));
+    }
+
+    #[test]
+    fn fields_are_written_in_header_order() {
+        let record = answered_record();
+        let (start, answer, end) = (record.start_time.to_rfc3339(), record.answer_time.unwrap().to_rfc3339(), record.end_time.to_rfc3339());
+        assert_eq!(record.to_csv(), format!("Line 1,incoming,{},{},{},12.5,gossip,,5550123,remote-hangup,25,10", start, answer, end));
+        assert_eq!(record.to_json(), format!(
+            "{{\"line\":\"Line 1\",\"direction\":\"incoming\",\"start_time\":\"{}\",\"answer_time\":\"{}\",\"end_time\":\"{}\",\"talk_seconds\":12.5,\
+            \"agent\":\"gossip\",\"other_line\":null,\"dialed\":\"5550123\",\"disconnect_cause\":\"remote-hangup\",\"deposited\":25,\"consumed\":10}}",
+            start, answer, end));
+    }
+
+    #[test]
+    fn unfinished_calls_default_to_caller_giving_up() {
+        assert_eq!(CallRecord::new("Line 1", CallDirection::Outgoing, start_time()).disconnect_cause.as_str(), "abandoned");
+        assert_eq!(CallRecord::new("Line 1", CallDirection::Incoming, start_time()).disconnect_cause.as_str(), "unanswered");
+    }
+
+    #[test]
+    fn special_characters_are_escaped() {
+        let mut record = CallRecord::new("Line 1", CallDirection::Outgoing, start_time());
+        record.agent = Some("say \"hi\", then\nleave\\\u{1}".to_owned());
+        record.other_line = Some("plain".to_owned());
+        record.dialed = Some("a,b".to_owned());
+        let json = record.to_json();
+        assert!(json.contains(r#""agent":"say \"hi\", then\nleave\\\u0001""#), "{}", json);
+        assert!(json.contains(r#""dialed":"a,b""#), "{}", json);
+        let csv = record.to_csv();
+        assert!(csv.contains(",\"say \"\"hi\"\", then\nleave\\\u{1}\",plain,\"a,b\","), "{}", csv);
+    }
+
+    #[test]
+    fn csv_header_is_only_written_to_new_files() {
+        let path = std::env::temp_dir().join(format!("cursed_phone_cdr_test_{}.csv", std::process::id()));
+        fs::remove_file(&path).ok();
+        let config = CdrConfig { path: Some(path.to_string_lossy().into_owned()), format: CdrFormat::Csv };
+        let record = answered_record();
+        CallRecordLog::from_config(&config).unwrap().write(&record);
+        CallRecordLog::from_config(&config).unwrap().write(&record);
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).ok();
+        let expected_row = record.to_csv();
+        assert_eq!(contents.lines().collect::<Vec<_>>(), [CDR_FIELD_NAMES.join(",").as_str(), &expected_row, &expected_row]);
+    }
+
+    #[test]
+    fn json_lines_have_no_header() {
+        let path = std::env::temp_dir().join(format!("cursed_phone_cdr_test_{}.jsonl", std::process::id()));
+        fs::remove_file(&path).ok();
+        let config = CdrConfig { path: Some(path.to_string_lossy().into_owned()), format: CdrFormat::Jsonl };
+        let record = answered_record();
+        CallRecordLog::from_config(&config).unwrap().write(&record);
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(contents, format!("{}\n", record.to_json()));
+    }
+}
@@ -0,0 +1,250 @@
+use log::{info, error};
+use crate::config::DialRuleConfig;
+
+/// Characters that may separate digit groups in a pattern for readability. They are ignored when matching.
+const PATTERN_SEPARATORS: [char; 4] = ['-', ' ', '(', ')'];
+
+#[derive(Clone, Debug)]
+enum PatternElement {
+    /// Matches a single specific digit.
+    Literal(char),
+    /// Matches a single digit in any of the (inclusive) ranges.
+    Class(Vec<(char, char)>),
+    /// Matches all remaining digits, as long as there are at least the specified number of them.
+    Rest(usize),
+}
+
+impl PatternElement {
+    fn matches(&self, digit: char) -> bool {
+        match self {
+            Self::Literal(c) => *c == digit,
+            Self::Class(ranges) => ranges.iter().any(|(from, to)| (*from..=*to).contains(&digit)),
+            Self::Rest(_) => true,
+        }
+    }
+}
+
+/// How a (possibly incomplete) number compares to a pattern.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PatternMatch {
+    /// The number can't match the pattern, however many digits are added.
+    None,
+    /// The number doesn't match yet, but could once more digits are dialed.
+    Partial,
+    /// The number matches the pattern.
+    Full,
+}
+
+/// A pattern matching a set of phone numbers.
+///
+/// Besides literal digits, `*` and `#`, patterns may contain:
+/// * `X` - any digit from 0 to 9
+/// * `N` - any digit from 2 to 9
+/// * `Z` - any digit from 1 to 9
+/// * `[...]` - any of the listed digits or ranges, e.g. `[2-5]` or `[147]`
+/// * `.` - one or more further digits (end of pattern only)
+/// * `!` - zero or more further digits (end of pattern only)
+///
+/// Dashes, spaces and parentheses are ignored, so `555-XXXX` and `555XXXX` are equivalent.
+#[derive(Clone, Debug)]
+pub struct DialPattern {
+    elements: Vec<PatternElement>,
+}
+
+impl DialPattern {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let mut elements = vec![];
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            if matches!(elements.last(), Some(PatternElement::Rest(_))) {
+                return Err(format!("'.' and '!' must come at the end of pattern '{}'", pattern))
+            }
+            let element = match c {
+                c if PATTERN_SEPARATORS.contains(&c) => continue,
+                '0'..='9' | '*' | '#' | 'A'..='D' => PatternElement::Literal(c),
+                'X' | 'x' => PatternElement::Class(vec![('0', '9')]),
+                'N' | 'n' => PatternElement::Class(vec![('2', '9')]),
+                'Z' | 'z' => PatternElement::Class(vec![('1', '9')]),
+                '.' => PatternElement::Rest(1),
+                '!' => PatternElement::Rest(0),
+                '[' => {
+                    let mut class = vec![];
+                    loop {
+                        match chars.next() {
+                            Some(']') => break,
+                            Some(c) => class.push(c),
+                            None => return Err(format!("unterminated digit class in pattern '{}'", pattern))
+                        }
+                    }
+                    let mut ranges = vec![];
+                    let mut i = 0;
+                    while i < class.len() {
+                        if i + 2 < class.len() && class[i + 1] == '-' {
+                            ranges.push((class[i], class[i + 2]));
+                            i += 3;
+                        } else {
+                            ranges.push((class[i], class[i]));
+                            i += 1;
+                        }
+                    }
+                    if ranges.is_empty() {
+                        return Err(format!("empty digit class in pattern '{}'", pattern))
+                    }
+                    if ranges.iter().any(|(start, end)| *start == '-' || *end == '-') {
+                        return Err(format!("incomplete range in digit class in pattern '{}'", pattern))
+                    }
+                    PatternElement::Class(ranges)
+                },
+                other => return Err(format!("unexpected character '{}' in pattern '{}'", other, pattern))
+            };
+            elements.push(element);
+        }
+        if elements.is_empty() {
+            return Err("pattern is empty".to_owned())
+        }
+        Ok(Self { elements })
+    }
+
+    /// Returns `true` if the string uses pattern syntax, rather than being a plain number.
+    pub fn is_pattern(number: &str) -> bool {
+        number.contains(['X', 'x', 'N', 'n', 'Z', 'z', '[', '.', '!'])
+    }
+
+    /// Returns `true` if every number matching the pattern has the same length.
+    pub fn is_fixed_length(&self) -> bool {
+        !matches!(self.elements.last(), Some(PatternElement::Rest(_)))
+    }
+
+    /// Compares a number against the pattern.
+    pub fn compare(&self, number: &str) -> PatternMatch {
+        let mut digits = number.chars();
+        for element in self.elements.iter() {
+            if let PatternElement::Rest(min) = element {
+                return if digits.count() >= *min { PatternMatch::Full } else { PatternMatch::Partial }
+            }
+            match digits.next() {
+                Some(digit) if element.matches(digit) => continue,
+                Some(_) => return PatternMatch::None,
+                None => return PatternMatch::Partial,
+            }
+        }
+        if digits.next().is_none() { PatternMatch::Full } else { PatternMatch::None }
+    }
+
+    /// Returns `true` if the number matches the pattern.
+    pub fn matches(&self, number: &str) -> bool {
+        self.compare(number) == PatternMatch::Full
+    }
+}
+
+/// A dial plan rule that rewrites and/or routes numbers matching a pattern.
+#[derive(Clone, Debug)]
+pub struct DialRule {
+    /// The pattern as written.
+    source: String,
+    pattern: DialPattern,
+    strip: usize,
+    prefix: String,
+    agent: Option<String>,
+    complete: bool,
+}
+
+impl DialRule {
+    pub fn from_config(config: &DialRuleConfig) -> Result<Self, String> {
+        Ok(Self {
+            source: config.pattern.clone(),
+            pattern: DialPattern::parse(config.pattern.as_str())?,
+            strip: config.strip,
+            prefix: config.prefix.clone().unwrap_or_default(),
+            agent: config.agent.clone(),
+            complete: config.complete,
+        })
+    }
+
+    /// Applies the rule's stripping and prefixing to a number.
+    fn rewrite(&self, number: &str) -> String {
+        let stripped: String = number.chars().skip(self.strip).collect();
+        format!("{}{}", self.prefix, stripped)
+    }
+}
+
+/// The outcome of passing a number through the dial plan.
+pub struct DialPlanResult {
+    /// The number after all rewrites.
+    pub number: String,
+    /// Name of the agent the number was routed to by a rule, if any.
+    pub agent: Option<String>,
+}
+
+/// An ordered list of rules applied to numbers dialed by the user.
+///
+/// Each matching rule rewrites the number before it is passed on to the next rule.
+/// Numbers are routed by the first matching rule that names an agent.
+#[derive(Default)]
+pub struct DialPlan {
+    rules: Vec<DialRule>,
+}
+
+impl DialPlan {
+    pub fn from_config(rules: &[DialRuleConfig]) -> Self {
+        let mut plan = Self::default();
+        for rule in rules {
+            match DialRule::from_config(rule) {
+                Ok(rule) => plan.rules.push(rule),
+                Err(err) => error!("Ignoring invalid dial rule: {}", err)
+            }
+        }
+        if !plan.rules.is_empty() {
+            info!("Loaded {} dial rule(s).", plan.rules.len());
+        }
+        plan
+    }
+
+    /// Adds a rule to the end of the plan, replacing any rule with the same pattern.
+    pub fn add_rule(&mut self, rule: DialRule) {
+        match self.rules.iter_mut().find(|existing| existing.source == rule.source) {
+            Some(existing) => *existing = rule,
+            None => self.rules.push(rule)
+        }
+    }
+
+    /// Removes the rule with the specified pattern. Returns `true` if there was one.
+    pub fn remove_rule(&mut self, pattern: &str) -> bool {
+        let len = self.rules.len();
+        self.rules.retain(|rule| rule.source != pattern);
+        self.rules.len() != len
+    }
+
+    /// Passes a number through the rules.
+    pub fn resolve(&self, number: &str) -> DialPlanResult {
+        let mut number = number.to_owned();
+        for rule in self.rules.iter() {
+            if !rule.pattern.matches(number.as_str()) {
+                continue
+            }
+            number = rule.rewrite(number.as_str());
+            if rule.agent.is_some() {
+                return DialPlanResult { number, agent: rule.agent.clone() }
+            }
+        }
+        DialPlanResult { number, agent: None }
+    }
+
+    /// Returns `true` if the dialed number fully matches a complete-number rule, so the call can be placed without waiting out the post-dial delay.
+    pub fn is_complete(&self, number: &str) -> bool {
+        let mut number = number.to_owned();
+        for rule in self.rules.iter() {
+            if !rule.pattern.matches(number.as_str()) {
+                continue
+            }
+            if rule.complete && rule.pattern.is_fixed_length() {
+                return true
+            }
+            number = rule.rewrite(number.as_str());
+            if rule.agent.is_some() {
+                break
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use super::cdr::*;
+use super::store::{load_toml_file, save_toml_file};
+
+const CALL_HISTORY_FILE_NAME: &str = "call_history.toml";
+
+/// A finished call, as remembered by the call history.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct CallHistoryEntry {
+    /// Name of the line the call took place on.
+    pub line: String,
+    /// `true` if the user placed the call.
+    pub outgoing: bool,
+    /// Name of the last agent on the call.
+    pub agent: Option<String>,
+    /// Name of the other line, for calls between lines.
+    pub other_line: Option<String>,
+    /// Number dialed by the user.
+    pub dialed: Option<String>,
+    /// Unix time (in seconds) at which the call started.
+    pub start_time: i64,
+    /// Unix time (in seconds) at which the call ended.
+    pub end_time: i64,
+    /// `true` if the call was connected.
+    pub answered: bool,
+    /// Length of time (in seconds) the call was connected.
+    pub duration: f64,
+}
+
+impl From<&CallRecord> for CallHistoryEntry {
+    fn from(record: &CallRecord) -> Self {
+        Self {
+            line: record.line.clone(),
+            outgoing: record.direction == CallDirection::Outgoing,
+            agent: record.agent.clone(),
+            other_line: record.other_line.clone(),
+            dialed: record.dialed.clone(),
+            start_time: record.start_time.timestamp(),
+            end_time: record.end_time.timestamp(),
+            answered: record.answer_time.is_some(),
+            duration: record.talk_time().num_milliseconds() as f64 / 1000.0,
+        }
+    }
+}
+
+/// Running call counts for a single agent.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct AgentCallStats {
+    /// Number of times the user called the agent.
+    pub times_called: u32,
+    /// Number of times the agent called the user.
+    pub calls_placed: u32,
+    /// Number of calls from the agent that the user didn't answer.
+    pub missed_calls: u32,
+    /// Number of calls with the agent that were connected.
+    pub answered_calls: u32,
+    /// Unix time (in seconds) at which the last call with the agent ended.
+    pub last_call_time: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct CallHistoryFile {
+    call: Vec<CallHistoryEntry>,
+    agent: HashMap<String, AgentCallStats>,
+}
+
+/// Recent calls and per-agent call counts, optionally persisted to the data directory.
+pub struct CallHistory {
+    /// Most recent calls, oldest first.
+    entries: VecDeque<CallHistoryEntry>,
+    /// Call counts by agent name.
+    agent_stats: HashMap<String, AgentCallStats>,
+    /// Maximum number of calls to remember.
+    capacity: usize,
+    /// File the history is saved to, if any.
+    path: Option<PathBuf>,
+}
+
+impl CallHistory {
+    /// Loads the call history from the specified data directory, or starts an empty one if there is no directory or file.
+    pub fn load(data_dir: Option<&Path>, capacity: usize) -> Self {
+        let path = data_dir.map(|dir| dir.join(CALL_HISTORY_FILE_NAME));
+        let file: CallHistoryFile = match &path {
+            Some(path) => load_toml_file(path, "call history"),
+            None => Default::default()
+        };
+
+        let mut entries: VecDeque<_> = file.call.into();
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+
+        Self {
+            entries,
+            agent_stats: file.agent,
+            capacity,
+            path,
+        }
+    }
+
+    /// Adds a finished call to the history and saves it.
+    pub fn add(&mut self, record: &CallRecord) {
+        let entry = CallHistoryEntry::from(record);
+
+        if let Some(agent_name) = &entry.agent {
+            let stats = self.agent_stats.entry(agent_name.clone()).or_default();
+            if entry.outgoing {
+                stats.times_called += 1;
+            } else {
+                stats.calls_placed += 1;
+                if !entry.answered {
+                    stats.missed_calls += 1;
+                }
+            }
+            if entry.answered {
+                stats.answered_calls += 1;
+            }
+            stats.last_call_time = Some(entry.end_time);
+        }
+
+        if self.capacity > 0 {
+            if self.entries.len() >= self.capacity {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(entry);
+        }
+
+        self.save();
+    }
+
+    /// Iterates over the remembered calls, most recent first.
+    pub fn recent(&self) -> impl Iterator<Item = &CallHistoryEntry> {
+        self.entries.iter().rev()
+    }
+
+    /// Gets the call counts for the specified agent.
+    pub fn agent_stats(&self, agent_name: &str) -> AgentCallStats {
+        self.agent_stats.get(agent_name).cloned().unwrap_or_default()
+    }
+
+    fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return
+        };
+        let file = CallHistoryFile {
+            call: self.entries.iter().cloned().collect(),
+            agent: self.agent_stats.clone(),
+        };
+        save_toml_file(path, &file, "call history");
+    }
+}
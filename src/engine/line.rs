@@ -0,0 +1,277 @@
+use super::*;
+
+/// Index of a line in the engine.
+pub type LineId = usize;
+
+/// The party on the other end of a line's active or pending call.
+pub enum OtherParty<'lua> {
+    /// A Lua agent.
+    Agent(Rc<AgentModule<'lua>>),
+    /// Another host phone line driven by the same engine.
+    Line(LineId),
+}
+
+/// A second agent calling a line that is already on a call.
+pub struct WaitingParty<'lua> {
+    pub agent: Rc<AgentModule<'lua>>,
+    /// `true` if the user has already talked to the agent and put it on hold; `false` if the call hasn't been answered yet.
+    pub on_hold: bool,
+}
+
+/// An agent bridged into a line's call in addition to its other party.
+pub struct ConferenceParty<'lua> {
+    pub agent: Rc<AgentModule<'lua>>,
+    /// Index of the block of phone channels that the agent's sounds are played on.
+    pub slot: usize,
+}
+
+/// A host phone connected to the engine, along with the state of its calls.
+pub struct PhoneLine<'lua> {
+    /// The name of the line.
+    pub(super) name: String,
+    /// The number other lines dial to reach this line.
+    pub(super) extension: Option<String>,
+    /// Ring pattern used when the caller doesn't specify one.
+    pub(super) default_ring_pattern: Option<Arc<RingPattern>>,
+    /// Ring pattern chosen by the caller for the pending incoming call, if any.
+    pub(super) call_ring_pattern: RefCell<Option<Arc<RingPattern>>>,
+    /// Indicates whether caller ID has been sent for the pending incoming call.
+    pub(super) caller_id_sent: Cell<bool>,
+    /// Message-waiting indicator set by agents.
+    pub(super) message_waiting: Cell<bool>,
+    /// Last message-waiting state shown to the user, through the dial tone and lamp.
+    pub(super) message_waiting_shown: Cell<bool>,
+    /// Configuration for the line's peripherals.
+    pub(super) config: Rc<CursedConfig>,
+    /// The sound engine that plays to the line's handset.
+    pub(super) sound_engine: RcRefCell<SoundEngine>,
+    /// Channel for sending output signals to the host phone.
+    pub(super) phone_output: RefCell<Option<mpsc::Sender<PhoneOutputSignal>>>,
+    /// Channel for receiving input signals from the host phone.
+    pub(super) phone_input: RefCell<Option<mpsc::Receiver<PhoneInputSignal>>>,
+    /// The party to which the line is connecting/has connected the host.
+    pub(super) other_party: RefCell<Option<OtherParty<'lua>>>,
+    /// The agent waiting to be connected or on hold, if any.
+    pub(super) waiting_party: RefCell<Option<WaitingParty<'lua>>>,
+    /// Agents bridged into the call in addition to the other party.
+    pub(super) conference: RefCell<Vec<ConferenceParty<'lua>>>,
+    /// Conference slot of the other party.
+    pub(super) other_party_slot: Cell<usize>,
+    /// Indicates whether the user is dialing a number to bridge into the call.
+    pub(super) three_way_dialing: Cell<bool>,
+    /// The last time the call-waiting tone was played.
+    pub(super) call_waiting_tone_time: Cell<Instant>,
+    /// The message an agent is leaving on the answering machine, if any.
+    pub(super) voicemail_recording: RefCell<Option<VoicemailRecording<'lua>>>,
+    /// Messages being played back to the user, if any.
+    pub(super) voicemail_playback: RefCell<Option<VoicemailPlayback>>,
+    /// Microphone capture routed to the other line during a line-to-line call.
+    pub(super) voice_input: RefCell<Option<VoiceInput>>,
+    /// The record of the call in progress.
+    pub(super) call_record: RefCell<Option<CallRecord>>,
+    /// Records of calls finished since the call record log was last drained, if logging is enabled.
+    pub(super) call_record_log: RefCell<Option<Vec<CallRecord>>>,
+    /// The last agent who called the line.
+    pub(super) last_caller_id: Cell<Option<AgentId>>,
+    /// The current state of the line.
+    pub(super) state: RefCell<PhoneLineState>,
+    /// States entered since the state log was last drained, if logging is enabled.
+    pub(super) state_log: RefCell<Option<Vec<PhoneLineState>>>,
+    /// Time when PDD last started.
+    pub(super) pdd_start: RefCell<Instant>,
+    /// Time when the current state started.
+    pub(super) state_start: RefCell<Instant>,
+    /// The currently queued digits dialed by the user.
+    pub(super) dialed_digits: RefCell<String>,
+    /// The number currently being called.
+    pub(super) called_number: RefCell<Option<String>>,
+    /// The last number dialed AND called by the user.
+    pub(super) last_dialed_number: RefCell<Option<String>>,
+    /// Number that calls to the line are forwarded to.
+    pub(super) call_forward_number: RefCell<Option<String>>,
+    /// Amount of coins deposited for the next call.
+    pub(super) deposit: RefCell<u32>,
+    /// Indicates whether the initial coin deposit for the call has been consumed.
+    pub(super) deposit_consumed: RefCell<bool>,
+    /// Indicates whether the line is waiting for a coin deposit to start the call.
+    pub(super) deposit_needed: RefCell<bool>,
+    /// Max call length credited for the current (or next) call.
+    pub(super) total_time_credit: RefCell<Unlimited<Duration>>,
+    /// Last known state of the host's switchhook.
+    pub(super) switchhook_closed: Cell<bool>,
+    /// Locked status of the switchhook.
+    pub(super) switchhook_locked: Cell<bool>,
+    /// Time of the last state change of the host's switchhook.
+    pub(super) switchhook_change_time: Cell<Instant>,
+    /// Number of host pulses since last dialed digit.
+    pub(super) pending_pulse_count: Cell<usize>,
+    /// Indicates whether there's a hookflash that the other party hasn't read yet.
+    pub(super) pending_hookflash: Cell<bool>,
+    /// Indicates whether the other party was waiting for a hookflash when agents were last updated.
+    pub(super) hookflash_awaited: Cell<bool>,
+    /// Is host rotary dial resting?
+    pub(super) rotary_resting: Cell<bool>,
+    /// Time of the last lifting of the rotary dial rest switch.
+    pub(super) rotary_dial_lift_time: Cell<Instant>,
+    /// Delay between rotary dial leaving resting state and first valid pulse.
+    pub(super) rotary_first_pulse_delay: Duration,
+}
+
+impl<'lua> PhoneLine<'lua> {
+    pub fn new(line: &LineConfig, config: &Rc<CursedConfig>, clock: &Arc<dyn Clock>, sound_engine: &Rc<RefCell<SoundEngine>>) -> Self {
+        let now = clock.now();
+
+        Self {
+            name: line.name.clone(),
+            extension: line.extension.clone().filter(|extension| !extension.is_empty()),
+            default_ring_pattern: RingPattern::try_parse(config.default_ring_pattern.as_str()).map(Arc::new),
+            call_ring_pattern: Default::default(),
+            caller_id_sent: Default::default(),
+            message_waiting: Default::default(),
+            message_waiting_shown: Default::default(),
+            config: Rc::clone(config),
+            sound_engine: Rc::clone(sound_engine),
+            phone_output: Default::default(),
+            phone_input: Default::default(),
+            other_party: Default::default(),
+            waiting_party: Default::default(),
+            conference: Default::default(),
+            other_party_slot: Default::default(),
+            three_way_dialing: Default::default(),
+            call_waiting_tone_time: Cell::new(now),
+            voicemail_recording: Default::default(),
+            voicemail_playback: Default::default(),
+            voice_input: Default::default(),
+            call_record: Default::default(),
+            call_record_log: Default::default(),
+            last_caller_id: Cell::new(None),
+            state: RefCell::new(PhoneLineState::Idle),
+            state_log: Default::default(),
+            pdd_start: RefCell::new(now),
+            state_start: RefCell::new(now),
+            dialed_digits: Default::default(),
+            called_number: Default::default(),
+            last_dialed_number: Default::default(),
+            call_forward_number: Default::default(),
+            deposit: RefCell::new(0),
+            deposit_consumed: RefCell::new(false),
+            deposit_needed: RefCell::new(false),
+            total_time_credit: Default::default(),
+            switchhook_closed: Cell::new(true),
+            switchhook_locked: Cell::new(false),
+            switchhook_change_time: Cell::new(now),
+            pending_pulse_count: Default::default(),
+            pending_hookflash: Default::default(),
+            hookflash_awaited: Default::default(),
+            rotary_resting: Cell::new(true),
+            rotary_dial_lift_time: Cell::new(now),
+            rotary_first_pulse_delay: Duration::from_millis(config.rotary.first_pulse_delay_ms.unwrap_or(DEFAULT_FIRST_PULSE_DELAY_MS)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// Gets the number other lines dial to reach this line.
+    pub fn extension(&self) -> Option<&str> {
+        self.extension.as_deref()
+    }
+
+    #[inline]
+    pub fn state(&self) -> PhoneLineState {
+        *self.state.borrow()
+    }
+
+    /// Enables or disables recording of state transitions.
+    pub fn set_state_logging(&self, enabled: bool) {
+        self.state_log.replace(if enabled { Some(vec![]) } else { None });
+    }
+
+    /// Removes and returns all states entered since the last call.
+    pub fn drain_state_log(&self) -> Vec<PhoneLineState> {
+        self.state_log.borrow_mut().as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Enables or disables recording of finished calls.
+    pub fn set_call_record_logging(&self, enabled: bool) {
+        self.call_record_log.replace(if enabled { Some(vec![]) } else { None });
+    }
+
+    /// Removes and returns the records of all calls finished since the last call.
+    pub fn drain_call_record_log(&self) -> Vec<CallRecord> {
+        self.call_record_log.borrow_mut().as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Gets the name of the agent associated with the active or pending call.
+    pub fn other_party_name(&self) -> Option<String> {
+        match self.other_party.borrow().as_ref() {
+            Some(OtherParty::Agent(agent)) => Some(agent.name().to_owned()),
+            _ => None
+        }
+    }
+
+    /// Gets the line associated with the active or pending call, if the other party is a host phone.
+    pub fn other_line(&self) -> Option<LineId> {
+        match self.other_party.borrow().as_ref() {
+            Some(OtherParty::Line(line_id)) => Some(*line_id),
+            _ => None
+        }
+    }
+
+    /// Returns `true` if the line has no active or pending call.
+    pub(super) fn is_vacant(&self) -> bool {
+        self.state() == PhoneLineState::Idle
+            && self.other_party.borrow().is_none()
+            && self.voicemail_recording.borrow().is_none()
+    }
+
+    /// Returns `true` if the specified agent is leaving a message on this line's answering machine.
+    pub(super) fn is_voicemail_party(&self, agent: &AgentModule) -> bool {
+        match self.voicemail_recording.borrow().as_ref() {
+            Some(recording) => recording.agent.id() == agent.id(),
+            None => false
+        }
+    }
+
+    /// Returns `true` if the specified agent is waiting or on hold on this line.
+    pub(super) fn is_waiting_party(&self, agent: &AgentModule) -> bool {
+        match self.waiting_party.borrow().as_ref() {
+            Some(waiting_party) => waiting_party.agent.id() == agent.id(),
+            None => false
+        }
+    }
+
+    /// Returns `true` if the specified agent is bridged into this line's call.
+    pub(super) fn is_conference_party(&self, agent: &AgentModule) -> bool {
+        self.conference.borrow().iter().any(|party| party.agent.id() == agent.id())
+    }
+
+    /// Gets the conference slot of the specified agent, if it's on a conference call on this line.
+    pub(super) fn conference_slot(&self, agent_id: AgentId) -> Option<usize> {
+        let conference = self.conference.borrow();
+        if let Some(party) = conference.iter().find(|party| party.agent.id() == Some(agent_id)) {
+            return Some(party.slot)
+        }
+        // The other party keeps its slot until the call ends, even once it's the only agent left
+        let slot = self.other_party_slot.get();
+        match self.other_party.borrow().as_ref() {
+            Some(OtherParty::Agent(agent)) if agent.id() == Some(agent_id) && (!conference.is_empty() || slot != 0) => Some(slot),
+            _ => None
+        }
+    }
+
+    /// Gets the lowest conference slot that isn't taken by an agent on the call.
+    pub(super) fn free_conference_slot(&self) -> Option<usize> {
+        let conference = self.conference.borrow();
+        (0..MAX_CONFERENCE_PARTIES).find(|slot| *slot != self.other_party_slot.get() && !conference.iter().any(|party| party.slot == *slot))
+    }
+
+    /// Returns `true` if the specified agent is the other party of this line.
+    pub(super) fn is_other_party(&self, agent: &AgentModule) -> bool {
+        match self.other_party.borrow().as_ref() {
+            Some(OtherParty::Agent(other_party)) => other_party.id() == agent.id(),
+            _ => false
+        }
+    }
+}
@@ -4,6 +4,15 @@
 mod props;
 mod scripting;
 mod agent;
+mod line;
+mod cdr;
+mod history;
+mod store;
+mod dialplan;
+mod vsc;
+mod agent_calls;
+mod voicemail;
+mod ring_rules;
 
 use std::fmt::Display;
 use std::ops::{Add, Sub};
@@ -18,6 +27,8 @@ use mlua::{prelude::*, Debug as LuaDebug};
 use indexmap::IndexMap;
 use log::{info, warn, trace, error};
 use vfs::VfsPath;
+use serde::Deserialize;
+use crate::clock::Clock;
 use crate::sound::*;
 use crate::phone::*;
 use crate::config::*;
@@ -25,6 +36,15 @@ use crate::config::*;
 pub use self::scripting::*;
 pub use self::props::*;
 pub use self::agent::*;
+pub use self::line::*;
+pub use self::cdr::*;
+pub use self::history::*;
+pub use self::store::*;
+pub use self::dialplan::*;
+pub use self::vsc::*;
+pub use self::agent_calls::*;
+pub use self::voicemail::*;
+pub use self::ring_rules::*;
 
 #[cfg(feature = "rpi")]
 use crate::gpio::*;
@@ -39,7 +59,37 @@ const DEFAULT_FIRST_PULSE_DELAY_MS: u64 = 200;
 
 type AgentId = usize;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// Ring cycle assumed for the answering machine when the ring pattern doesn't have a length.
+const DEFAULT_RING_CYCLE_DURATION: Duration = Duration::from_secs(6);
+
+/// Time between repeats of the call-waiting tone.
+const CALL_WAITING_TONE_INTERVAL: Duration = Duration::from_secs(10);
+/// Number of phone channels given to each agent on a conference call.
+const CONFERENCE_CHANNELS_PER_PARTY: usize = 3;
+/// Maximum number of agents on a conference call.
+const MAX_CONFERENCE_PARTIES: usize = PHONE_CHANNELS.len() / CONFERENCE_CHANNELS_PER_PARTY;
+
+/// Where a dialed number leads.
+enum DialDestination<'lua> {
+    /// Another host phone.
+    Line(LineId),
+    /// An agent.
+    Agent(Rc<AgentModule<'lua>>),
+    /// Nothing; the call goes to the intercept agent.
+    Unassigned,
+}
+
+/// Tracks consecutive errors raised by an agent.
+struct AgentFailure {
+    /// Number of consecutive errors.
+    count: u32,
+    /// Time of the last error.
+    last_error: Instant,
+    /// Time of the next recovery attempt, if one is scheduled.
+    retry_at: Option<Instant>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
 pub enum PhoneLineState {
     /// The phone is on-hook and the line is idle.
     Idle,
@@ -200,66 +250,58 @@ pub struct CursedEngine<'lua> {
     scripts_root: VfsPath,
     /// The root directory to load agent scripts from.
     agents_root: VfsPath,
+    /// The time source of the engine.
+    clock: Arc<dyn Clock>,
     /// The starting time of the engine.
     start_time: Instant,
     /// The numbered agents associated with the engine.
     phone_book: RefCell<HashMap<String, AgentId>>,
-    /// The last agent who called the host
-    last_caller_id: Cell<Option<AgentId>>,
+    /// The agents whose numbers are patterns, in load order.
+    agent_number_patterns: RefCell<Vec<(DialPattern, AgentId)>>,
+    /// Rules applied to dialed numbers before they are looked up.
+    dial_plan: RefCell<DialPlan>,
+    /// Rules that pick ring patterns for incoming calls.
+    ring_rules: RingRules,
+    /// Vertical service codes handled by the engine.
+    service_codes: RefCell<VscRegistry<'lua>>,
     /// The agents (both numbered and otherwise) associated with the engine.
     agents: RefCell<IndexMap<String, Rc<AgentModule<'lua>>>>,
-    /// The sound engine associated with the engine.
-    sound_engine: RcRefCell<SoundEngine>,
+    /// The sound resources shared by all lines.
+    sound_library: RcRefCell<SoundLibrary>,
     /// The intercept agent.
     intercept_agent: RefCell<Orc<AgentModule<'lua>>>,
-    /// Channel for sending output signals to the host phone.
-    phone_output: RefCell<Option<mpsc::Sender<PhoneOutputSignal>>>,
-    /// Channel for receiving input signals from the host phone.
-    phone_input: RefCell<Option<mpsc::Receiver<PhoneInputSignal>>>,
-    /// The agent to which the engine is connecting/has connected the host.
-    other_party: RefCell<Orc<AgentModule<'lua>>>,
-    /// The current state of the engine.
-    state: RefCell<PhoneLineState>,
-    /// Time when PDD last started.
-    pdd_start: RefCell<Instant>,
-    /// Time when the current state started.
-    state_start: RefCell<Instant>,
+    /// Hashes of agent scripts as of their last load, by path.
+    agent_script_hashes: RefCell<HashMap<String, u64>>,
+    /// Changed agent scripts waiting for their agents to go idle.
+    pending_agent_reloads: RefCell<Vec<VfsPath>>,
+    /// Interval between checks for changed agent scripts, if hot-reloading is enabled.
+    agent_reload_interval: Option<Duration>,
+    /// Time of the last check for changed agent scripts.
+    last_agent_scan: Cell<Instant>,
+    /// Log that finished calls are written to.
+    call_log: Option<CallRecordLog>,
+    /// Recent calls and per-agent call counts.
+    call_history: RefCell<CallHistory>,
+    /// Persistent key-value storage for agents.
+    agent_store: RefCell<AgentStore>,
+    /// Calls between agents that don't involve a host phone.
+    agent_calls: RefCell<AgentCalls>,
+    /// Messages left on the answering machine.
+    voicemail: RefCell<VoicemailBox>,
+    /// Error history of agents that have raised Lua errors.
+    agent_failures: RefCell<HashMap<AgentId, AgentFailure>>,
+    /// The host phone lines driven by the engine.
+    lines: Vec<PhoneLine<'lua>>,
+    /// The line that engine operations currently apply to.
+    current_line: Cell<LineId>,
+    /// The agent currently being ticked, if any.
+    ticking_agent: Cell<Option<AgentId>>,
     /// Phone configuration.
     config: Rc<CursedConfig>,
-    /// The currently queued digits dialed by the user.
-    dialed_digits: RefCell<String>,
-    /// The number currently being called.
-    called_number: RefCell<Option<String>>,
-    /// The last number dialed AND called by the user.
-    last_dialed_number: RefCell<Option<String>>,
     /// Enable switchhook dialing?
     switchhook_dialing_enabled: bool,
-    /// Amount of coins deposited for the next call.
-    deposit: RefCell<u32>,
-    /// Indicates whether the initial coin deposit for the call has been consumed.
-    deposit_consumed: RefCell<bool>,
-    /// Indicates whether the engine is waiting for a coin deposit to start the call.
-    deposit_needed: RefCell<bool>,
     /// Delay before coins get eaten after call is accepted
     deposit_consume_delay: Duration,
-    /// Max call length credited for the current (or next) call.
-    total_time_credit: RefCell<Unlimited<Duration>>,
-    /// Last known state of the host's switchhook.
-    switchhook_closed: Cell<bool>,
-    /// Locked status of the switchhook.
-    switchhook_locked: Cell<bool>,
-    /// Time of the last staet change of the host's switchhook.
-    switchhook_change_time: Cell<Instant>,
-    /// Number of host pulses since last dialed digit.
-    pending_pulse_count: Cell<usize>,
-    /// Is host rotary dial resting?
-    rotary_resting: Cell<bool>,
-    /// Time of the last lifting of the rotary dial rest switch.
-    rotary_dial_lift_time: Cell<Instant>,
-    /// Delay between rotary dial leaving resting state and first valid pulse.
-    rotary_first_pulse_delay: Duration,
-    /// Default ring pattern for agents
-    default_ring_pattern: Option<Arc<RingPattern>>,
     /// GPIO interface used by Lua.
     #[cfg(feature = "rpi")]
     gpio: RefCell<crate::gpio::GpioInterface>,
@@ -272,76 +314,169 @@ fn update_cell<T: Copy, F>(cell: &Cell<T>, update_fn: F) where F: FnOnce(T) -> T
     cell.replace(new);
 }
 
+fn hash_source(src: &str) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    src.hash(&mut hasher);
+    hasher.finish()
+}
+
 fn lua_hook_check_exec_limit(_lua: &Lua, _debug: LuaDebug) -> LuaResult<()> {
     Err(LuaError::external("execution limit reached")) 
 }
 
 #[allow(unused_must_use)]
 impl<'lua> CursedEngine<'lua> {
-    pub fn new(scripts_root: VfsPath, agents_root: VfsPath, config: &Rc<CursedConfig>, sound_engine: &Rc<RefCell<SoundEngine>>) -> Self {
+    pub fn new(scripts_root: VfsPath, agents_root: VfsPath, config: &Rc<CursedConfig>, clock: &Arc<dyn Clock>, sound_library: &Rc<RefCell<SoundLibrary>>, lines: Vec<PhoneLine<'lua>>) -> Self {
         let lua_stdlib_flags = LuaStdLib::MATH | LuaStdLib::STRING | LuaStdLib::TABLE | LuaStdLib::BIT;
 
         let lua = Lua::new_with(lua_stdlib_flags, Default::default()).expect("failed to create Lua context");
 
-        let now = Instant::now();
+        assert!(!lines.is_empty(), "engine requires at least one line");
         
         Self {
             lua,
-            start_time: now,
-            pdd_start: RefCell::new(now),
+            clock: Arc::clone(clock),
+            start_time: clock.now(),
             scripts_root,
             agents_root,
             config: Rc::clone(config),
-            sound_engine: Rc::clone(sound_engine),
+            sound_library: Rc::clone(sound_library),
             phone_book: Default::default(),
-            last_caller_id: Cell::new(None),
+            agent_number_patterns: Default::default(),
+            dial_plan: RefCell::new(DialPlan::from_config(&config.dial_rules)),
+            ring_rules: RingRules::from_config(&config.ring_rules),
+            service_codes: RefCell::new(VscRegistry::from_config(&config.vertical_service_codes)),
             agents: Default::default(),
             intercept_agent: Default::default(),
-            state: RefCell::new(PhoneLineState::Idle),
-            state_start: RefCell::new(now),
-            phone_input: Default::default(),
-            phone_output: Default::default(),
-            other_party: Default::default(),
-            dialed_digits: Default::default(),
-            called_number: Default::default(),
-            last_dialed_number: Default::default(),
+            agent_script_hashes: Default::default(),
+            pending_agent_reloads: Default::default(),
+            agent_reload_interval: config.agent_reload_interval.filter(|secs| *secs > 0.0).map(Duration::from_secs_f32),
+            last_agent_scan: Cell::new(clock.now()),
+            agent_calls: Default::default(),
+            agent_failures: Default::default(),
+            call_log: CallRecordLog::from_config(&config.cdr),
+            call_history: RefCell::new(CallHistory::load(config.data_dir.as_deref().map(std::path::Path::new), config.call_history_size)),
+            agent_store: RefCell::new(AgentStore::new(config.data_dir.as_deref().map(std::path::Path::new))),
+            voicemail: RefCell::new(VoicemailBox::load(config.data_dir.as_deref().map(std::path::Path::new), config.voicemail.capacity)),
+            lines,
+            current_line: Cell::new(0),
+            ticking_agent: Default::default(),
             switchhook_dialing_enabled: config.shd_enabled.unwrap_or(false),
-            deposit: RefCell::new(0),
             deposit_consume_delay: Duration::from_millis(config.payphone.coin_consume_delay_ms),
-            deposit_consumed: RefCell::new(false),
-            deposit_needed: RefCell::new(false),
-            total_time_credit: Default::default(),
-            switchhook_change_time: Cell::new(now),
-            switchhook_closed: Cell::new(true),
-            switchhook_locked: Cell::new(false),
-            pending_pulse_count: Default::default(),
-            rotary_resting: Cell::new(true),
-            rotary_dial_lift_time: Cell::new(now),
-            rotary_first_pulse_delay: Duration::from_millis(config.rotary.first_pulse_delay_ms.unwrap_or(DEFAULT_FIRST_PULSE_DELAY_MS)),
-            default_ring_pattern: RingPattern::try_parse(config.default_ring_pattern.as_str()).map(Arc::new),
             #[cfg(feature = "rpi")]
-            gpio: RefCell::new(GpioInterface::new().expect("Unable to initialize Lua GPIO interface")),
+            gpio: RefCell::new(GpioInterface::new(clock).expect("Unable to initialize Lua GPIO interface")),
         }
     }
 
+    /// Gets the current time according to the engine clock.
+    #[inline]
+    fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Gets the amount of time elapsed since the engine was created.
+    pub fn engine_time(&self) -> Duration {
+        self.now().saturating_duration_since(self.start_time)
+    }
+
     fn reset_execution_limit(&self) {
         if let Some(limit) = self.config.script_execution_limit {
             self.lua.set_hook(LuaHookTriggers::every_nth_instruction(limit), lua_hook_check_exec_limit);
         }
     }
 
-    pub fn gen_engine_output(&self) -> mpsc::Receiver<PhoneOutputSignal> {
+    pub fn gen_engine_output(&self, line_id: LineId) -> mpsc::Receiver<PhoneOutputSignal> {
         let (tx, rx) = mpsc::channel();
-        self.phone_output.replace(Some(tx));
+        self.lines[line_id].phone_output.replace(Some(tx));
         rx
     }
 
-    pub fn listen(&self, input_from_phone: mpsc::Receiver<PhoneInputSignal>) {
-        self.phone_input.replace(Some(input_from_phone));
+    pub fn listen(&self, line_id: LineId, input_from_phone: mpsc::Receiver<PhoneInputSignal>) {
+        self.lines[line_id].phone_input.replace(Some(input_from_phone));
+    }
+
+    /// Gets the lines driven by the engine.
+    pub fn lines(&self) -> &[PhoneLine<'lua>] {
+        &self.lines
+    }
+
+    /// Gets the line that engine operations currently apply to.
+    #[inline]
+    fn line(&self) -> &PhoneLine<'lua> {
+        &self.lines[self.current_line.get()]
+    }
+
+    /// Makes engine operations apply to the specified line and returns the previously selected line.
+    #[inline]
+    fn select_line(&self, line_id: LineId) -> LineId {
+        self.current_line.replace(line_id)
+    }
+
+    /// Runs the specified function with engine operations applied to another line, then restores the current line.
+    fn with_line<R>(&self, line_id: LineId, f: impl FnOnce() -> R) -> R {
+        let prev_line_id = self.select_line(line_id);
+        let result = f();
+        self.select_line(prev_line_id);
+        result
+    }
+
+    /// Gets the line that the specified agent is the other party of, if any.
+    fn find_agent_line(&self, agent: &AgentModule) -> Option<LineId> {
+        self.lines.iter().position(|line| line.is_other_party(agent) || line.is_waiting_party(agent) || line.is_conference_party(agent) || line.is_voicemail_party(agent))
+    }
+
+    /// Returns `true` if the specified agent is on a call, whether with the user or with another agent.
+    fn is_agent_busy(&self, agent: &AgentModule) -> bool {
+        self.find_agent_line(agent).is_some() || agent.id().is_some_and(|agent_id| self.agent_calls.borrow().partner(agent_id).is_some())
+    }
+
+    /// Connects the specified agent to another agent that isn't on a call. Returns `true` if the agents were connected.
+    fn connect_agent_call(&self, caller: &AgentModule, callee: &AgentModule) -> bool {
+        let (caller_id, callee_id) = match (caller.id(), callee.id()) {
+            (Some(caller_id), Some(callee_id)) => (caller_id, callee_id),
+            _ => return false
+        };
+        if callee.suspended() || self.is_agent_busy(caller) || self.is_agent_busy(callee) || !matches!(callee.state(), Ok(AgentState::Idle)) {
+            info!("Agent '{}' called agent '{}': line busy", caller.name(), callee.name());
+            return false
+        }
+        if !self.agent_calls.borrow_mut().connect(caller_id, callee_id) {
+            return false
+        }
+        info!("Agent '{}' is now on a call with agent '{}'", caller.name(), callee.name());
+        caller.send_message(callee.name(), AGENT_CALL_MESSAGE);
+        true
+    }
+
+    /// Ends the agent-to-agent call that the specified agent is on, if any, and tells the agent on the other end.
+    fn disconnect_agent_call(&self, agent: &AgentModule) -> bool {
+        let partner_id = match agent.id().and_then(|agent_id| self.agent_calls.borrow_mut().disconnect(agent_id)) {
+            Some(partner_id) => partner_id,
+            None => return false
+        };
+        if let Some(partner) = self.lookup_agent_id(partner_id) {
+            info!("Agent '{}' ended its call with agent '{}'", agent.name(), partner.name());
+            agent.send_message(partner.name(), AGENT_CALL_ENDED_MESSAGE);
+        }
+        true
+    }
+
+    /// Maps a phone channel requested by the agent being ticked onto the channels of its conference slot, if it's on a conference call.
+    /// Other channels are passed through unchanged.
+    fn agent_channel(&self, channel: Channel) -> Channel {
+        let phone_index = match PHONE_CHANNELS.iter().position(|ch| *ch == channel) {
+            Some(phone_index) => phone_index,
+            None => return channel
+        };
+        match self.ticking_agent.get().and_then(|agent_id| self.line().conference_slot(agent_id)) {
+            Some(slot) => PHONE_CHANNELS[slot * CONFERENCE_CHANNELS_PER_PARTY + phone_index % CONFERENCE_CHANNELS_PER_PARTY],
+            None => channel
+        }
     }
 
     fn send_output(&self, signal: PhoneOutputSignal) -> bool {
-        if let Some(tx) = self.phone_output.borrow().as_ref() {
+        if let Some(tx) = self.line().phone_output.borrow().as_ref() {
             tx.send(signal).is_ok();
         }
         false
@@ -356,40 +491,544 @@ impl<'lua> CursedEngine<'lua> {
     }
 
     /// Searches the phone directory for the specified number and returns the agent associated with it, or `None` if the number is unassigned.
+    /// Exact numbers take priority over agent number patterns.
     fn lookup_agent_phone_number(&self, phone_number: &str) -> Orc<AgentModule> {
         if let Some(id) = self.phone_book.borrow().get(phone_number) {
             return self.lookup_agent_id(*id);
         }
-        None
+        let id = self.agent_number_patterns.borrow().iter()
+            .find(|(pattern, _)| pattern.matches(phone_number))
+            .map(|(_, id)| *id);
+        id.and_then(|id| self.lookup_agent_id(id))
+    }
+
+    /// Searches the lines for the specified extension and returns the matching line, if any.
+    fn lookup_line_extension(&self, extension: &str) -> Option<LineId> {
+        self.lines.iter().position(|line| line.extension() == Some(extension))
+    }
+
+    /// Finds the agent that a phone number or agent handle (`@name`) leads to.
+    fn lookup_agent_destination(&'lua self, destination: &str) -> Orc<AgentModule<'lua>> {
+        if let Some(agent_name) = destination.strip_prefix('@') {
+            return self.lookup_agent_name(agent_name.trim())
+        }
+        match self.resolve_number(destination).1 {
+            DialDestination::Agent(agent) => Some(agent),
+            _ => None
+        }
+    }
+
+    /// Passes a number through the dial plan and finds out where it leads.
+    /// Returns the rewritten number along with its destination.
+    fn resolve_number(&'lua self, number: &str) -> (String, DialDestination<'lua>) {
+        let result = self.dial_plan.borrow().resolve(number);
+        let destination = if let Some(agent_name) = &result.agent {
+            match self.lookup_agent_name(agent_name) {
+                Some(agent) => DialDestination::Agent(agent),
+                None => {
+                    warn!("Dial plan routed '{}' to nonexistent agent '{}'", number, agent_name);
+                    DialDestination::Unassigned
+                }
+            }
+        } else if let Some(line_id) = self.lookup_line_extension(result.number.as_str()) {
+            DialDestination::Line(line_id)
+        } else if let Some(agent) = self.lookup_agent_phone_number(result.number.as_str()) {
+            DialDestination::Agent(agent)
+        } else {
+            DialDestination::Unassigned
+        };
+        (result.number, destination)
     }
 
     /// Calls the specified phone number.
     fn call_number(&'lua self, number: &str) -> bool {
         info!("Calling: {}", number);
-        self.called_number.replace(Some((*self.dialed_digits.borrow()).clone()));
-        if let Some(agent) = self.lookup_agent_phone_number(number) {
-            self.call_agent(agent);
-            return true;
-        } else {
-            self.call_intercept(CallReason::NumberDisconnected);
-            return false;
+        self.update_call_record(|record| { record.dialed.get_or_insert_with(|| number.to_owned()); });
+        let (resolved_number, destination) = self.resolve_number(number);
+        if resolved_number != number {
+            info!("Dial plan rewrote '{}' to '{}'", number, resolved_number);
+        }
+        self.line().called_number.replace(Some(resolved_number));
+        match destination {
+            DialDestination::Line(line_id) => {
+                self.call_line(line_id);
+                true
+            },
+            DialDestination::Agent(agent) => {
+                self.call_agent(agent);
+                true
+            },
+            DialDestination::Unassigned => {
+                self.call_intercept(CallReason::NumberDisconnected);
+                false
+            }
+        }
+    }
+
+    /// Returns `true` if the digits dialed so far complete a number in the dial plan or a built-in service code.
+    fn is_dialed_number_complete(&self) -> bool {
+        let dialed_digits = self.line().dialed_digits.borrow();
+        self.dial_plan.borrow().is_complete(dialed_digits.as_str())
+            || self.service_codes.borrow().is_complete(dialed_digits.as_str(), self.line().config.rotary.enabled)
+            || (self.config.voicemail.enabled && *dialed_digits == self.config.voicemail.access_number)
+    }
+
+    /// Handles the dialed number if it starts with a registered vertical service code.
+    /// Returns `true` if the number was a service code.
+    fn handle_service_code(&'lua self, number: &str) -> bool {
+        let service_codes = self.service_codes.borrow();
+        let call = match service_codes.parse_number(number, self.line().config.rotary.enabled) {
+            Some(call) => call,
+            None => return false
+        };
+        info!("Service code: {} {}", call.code, call.args);
+        self.update_call_record(|record| { record.dialed.get_or_insert_with(|| number.to_owned()); });
+        match service_codes.get(call.code) {
+            Some(VscHandler::Action(action)) => {
+                let action = *action;
+                let args = call.args.to_owned();
+                drop(service_codes);
+                self.run_service_code_action(action, args.as_str());
+            },
+            Some(VscHandler::Agent(agent_name)) => {
+                match self.lookup_agent_name(agent_name) {
+                    Some(agent) => {
+                        drop(service_codes);
+                        self.line().called_number.replace(Some(number.to_owned()));
+                        self.call_agent(agent);
+                    },
+                    None => {
+                        warn!("Service code *{} is bound to nonexistent agent '{}'", call.code, agent_name);
+                        drop(service_codes);
+                        self.call_intercept(CallReason::NumberDisconnected);
+                    }
+                }
+            },
+            Some(VscHandler::Function(handler)) => {
+                let handler = handler.clone();
+                let code = call.code.to_owned();
+                let args = call.args.to_owned();
+                drop(service_codes);
+                self.reset_execution_limit();
+                match handler.call::<_, Option<String>>(args) {
+                    Ok(Some(number)) => self.call_service_code_number(number.as_str()),
+                    Ok(None) => self.confirm_service_code(),
+                    Err(err) => {
+                        error!("Service code *{} handler encountered an error: {}", code, err);
+                        self.set_state(PhoneLineState::Busy);
+                    }
+                }
+            },
+            None => unreachable!()
         }
+        true
+    }
+
+    /// Runs a built-in service code feature.
+    fn run_service_code_action(&'lua self, action: VscAction, args: &str) {
+        match action {
+            VscAction::LastCallReturn => {
+                match self.line().last_caller_id.get().and_then(|id| self.lookup_agent_id(id)) {
+                    Some(agent) => {
+                        info!("Returning last call to agent '{}'", agent.name());
+                        self.line().dialed_digits.replace(agent.phone_number().unwrap_or_default());
+                        self.call_agent(agent);
+                    },
+                    None => {
+                        info!("No previous caller available for last-call return.");
+                        self.call_intercept(CallReason::NumberDisconnected);
+                    }
+                }
+            },
+            VscAction::Redial => {
+                let last_number = self.line().last_dialed_number.borrow().clone();
+                match last_number {
+                    Some(number) if !number.is_empty() => self.call_service_code_number(number.as_str()),
+                    _ => {
+                        info!("No previous number available to redial.");
+                        self.call_intercept(CallReason::NumberDisconnected);
+                    }
+                }
+            },
+            VscAction::Volume => {
+                match args.chars().next().and_then(|digit| digit.to_digit(10)) {
+                    Some(level) => {
+                        let volume = level as f32 / 9.0;
+                        info!("Setting line volume to {}%", (volume * 100.0).round());
+                        self.line().sound_engine.borrow_mut().set_master_volume(volume);
+                        self.confirm_service_code();
+                    },
+                    None => self.call_intercept(CallReason::NumberDisconnected)
+                }
+            },
+            VscAction::CallForward => {
+                if args.is_empty() {
+                    self.call_intercept(CallReason::NumberDisconnected);
+                } else {
+                    info!("Forwarding calls on line '{}' to: {}", self.line().name(), args);
+                    self.line().call_forward_number.replace(Some(args.to_owned()));
+                    self.confirm_service_code();
+                }
+            },
+            VscAction::CancelCallForward => {
+                info!("Cancelled call forwarding on line '{}'", self.line().name());
+                self.line().call_forward_number.replace(None);
+                self.confirm_service_code();
+            },
+        }
+    }
+
+    /// Calls a number on behalf of a service code, as though the user had dialed it.
+    fn call_service_code_number(&'lua self, number: &str) {
+        self.line().dialed_digits.replace(number.to_owned());
+        self.call_number(number);
+    }
+
+    /// Returns the line to dial tone after a service code feature is set, playing a confirmation tone first.
+    fn confirm_service_code(&'lua self) {
+        self.clear_dialed_digits();
+        self.set_state(PhoneLineState::DialTone);
+        self.line().sound_engine.borrow().play_confirmation_tone();
     }
 
     /// Calls the specified agent.
     fn call_agent(&'lua self, agent: Rc<AgentModule>) {
         if self.state().can_place_call() {
             info!("Calling agent '{}' ({:?})", agent.name(), agent.phone_number());
+            // Agents talking to each other can't take the call
+            if self.is_agent_busy(&agent) {
+                info!("Agent '{}' is busy", agent.name());
+                self.set_state(PhoneLineState::Busy);
+                return;
+            }
             // Inform the agent state machine that the user initiated the call
             agent.set_call_reason(CallReason::UserInit);
             // Set other_party to requested agent
             let agent = self.lookup_agent_id(agent.id().unwrap()).unwrap();
-            self.load_other_party(Rc::clone(&agent));
+            self.load_other_party(OtherParty::Agent(Rc::clone(&agent)));
             // Set PBX to call-out state
             self.set_state(PhoneLineState::CallingOut);
         }
     }
 
+    /// Calls the host phone on the specified line.
+    fn call_line(&'lua self, line_id: LineId) {
+        if self.state().can_place_call() {
+            let line = &self.lines[line_id];
+            info!("Calling line '{}' ({:?})", line.name(), line.extension());
+            // Follow call forwarding on the called line (one hop only)
+            let forward_number = line.call_forward_number.borrow().clone();
+            if let Some(forward_number) = forward_number {
+                info!("Line '{}' is forwarded to: {}", line.name(), forward_number);
+                match self.resolve_number(forward_number.as_str()).1 {
+                    DialDestination::Line(forward_line_id) if forward_line_id != line_id && self.lines[forward_line_id].call_forward_number.borrow().is_none() => {
+                        self.call_line(forward_line_id);
+                    },
+                    DialDestination::Agent(agent) => self.call_agent(agent),
+                    _ => self.set_state(PhoneLineState::Busy)
+                }
+                return;
+            }
+            // A line can't call itself, and only one call can ring a line at a time
+            if line_id == self.current_line.get() || !line.is_vacant() {
+                self.set_state(PhoneLineState::Busy);
+                return;
+            }
+            self.load_other_party(OtherParty::Line(line_id));
+            self.set_state(PhoneLineState::CallingOut);
+        }
+    }
+
+    /// Finds a line that can take an incoming call from an agent, following call forwarding between lines.
+    fn find_line_for_incoming_call(&'lua self) -> Option<LineId> {
+        for (line_id, line) in self.lines.iter().enumerate() {
+            if !line.is_vacant() {
+                continue
+            }
+            let forward_number = line.call_forward_number.borrow().clone();
+            match forward_number {
+                None => return Some(line_id),
+                // Agents can only be forwarded to other lines
+                Some(forward_number) => {
+                    if let DialDestination::Line(forward_line_id) = self.resolve_number(forward_number.as_str()).1 {
+                        let forward_line = &self.lines[forward_line_id];
+                        if forward_line.is_vacant() && forward_line.call_forward_number.borrow().is_none() {
+                            info!("Forwarding incoming call from line '{}' to line '{}'", line.name(), forward_line.name());
+                            return Some(forward_line_id)
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Finds a line where the specified agent can wait for the user to finish a call with another agent.
+    fn find_line_for_call_waiting(&self, agent: &AgentModule) -> Option<LineId> {
+        if !self.config.call_waiting_enabled.unwrap_or(false) {
+            return None
+        }
+        self.lines.iter().position(|line| {
+            line.state() == PhoneLineState::Connected
+                && line.waiting_party.borrow().is_none()
+                && line.conference.borrow().is_empty()
+                && !line.three_way_dialing.get()
+                && matches!(line.other_party.borrow().as_ref(), Some(OtherParty::Agent(other_party)) if other_party.id() != agent.id())
+        })
+    }
+
+    /// Plays the call-waiting tone on the current line.
+    fn play_call_waiting_tone(&self) {
+        self.line().sound_engine.borrow().play_call_waiting_tone();
+        self.line().call_waiting_tone_time.set(self.now());
+    }
+
+    /// Ends the call of the agent waiting or on hold on the current line.
+    fn drop_waiting_party(&'lua self) {
+        if let Some(waiting_party) = self.line().waiting_party.replace(None) {
+            waiting_party.agent.transition_state(AgentState::Idle);
+            // Nobody left to wait for
+            if self.state() == PhoneLineState::Busy {
+                self.play_busy_or_hold_music();
+            }
+        }
+    }
+
+    /// Plays the busy tone, or the hold music if there's still a call on hold.
+    fn play_busy_or_hold_music(&self) {
+        let sound_engine = self.line().sound_engine.borrow();
+        let is_call_held = self.line().waiting_party.borrow().as_ref().is_some_and(|waiting_party| waiting_party.on_hold);
+        match &self.config.hold_music {
+            Some(hold_music) if is_call_held => {
+                sound_engine.play(hold_music.as_str(), Channel::SignalIn, false, true, SoundPlayOptions {
+                    looping: true,
+                    .. Default::default()
+                });
+            },
+            _ => sound_engine.play_busy_tone()
+        }
+    }
+
+    /// Resets the speed, volume, line filter and effects of the line's channels after a call.
+    fn reset_channel_mix(&self) {
+        let mut sound_engine = self.line().sound_engine.borrow_mut();
+        for ch in NON_SOUL_CHANNELS.iter() {
+            let ch = *ch;
+            sound_engine.set_channel_speed(ch, 1.0);
+            sound_engine.set_channel_volume(ch, 1.0);
+            sound_engine.set_channel_fade_volume(ch, 1.0);
+            sound_engine.reset_channel_filter(ch);
+            sound_engine.clear_channel_effects(ch);
+        }
+    }
+
+    /// Handles a hookflash on the current line.
+    /// 
+    /// The hookflash switches to the waiting or held call if there is one; otherwise, it is passed on to the connected agent.
+    fn handle_hookflash(&'lua self) {
+        info!("Hookflash on line '{}'", self.line().name());
+        if self.switch_waiting_call() || self.handle_three_way_hookflash() {
+            return
+        }
+        if self.state() == PhoneLineState::Connected && self.get_other_party_agent().is_some() {
+            self.line().pending_hookflash.set(true);
+        }
+    }
+
+    /// Switches to the waiting or held call on the current line, if there is one.
+    /// Returns `true` if the calls were switched.
+    fn switch_waiting_call(&'lua self) -> bool {
+        use PhoneLineState::*;
+        if self.line().waiting_party.borrow().is_none() {
+            return false
+        }
+        match self.state() {
+            // Swap the active call with the waiting one
+            Connected => {
+                let current_agent = match self.get_other_party_agent() {
+                    Some(agent) => agent,
+                    None => return false
+                };
+                let waiting_party = self.line().waiting_party.replace(None).unwrap();
+                info!("Hookflash: putting '{}' on hold and switching to '{}'", current_agent.name(), waiting_party.agent.name());
+                {
+                    let sound_engine = self.line().sound_engine.borrow();
+                    sound_engine.stop_all_nonsignal();
+                    sound_engine.stop(Channel::SignalIn);
+                }
+                self.reset_channel_mix();
+                current_agent.transition_state(AgentState::Hold);
+                self.line().pending_hookflash.set(false);
+                self.line().waiting_party.replace(Some(WaitingParty { agent: current_agent, on_hold: true }));
+                waiting_party.agent.transition_state(AgentState::Call);
+                self.load_other_party(OtherParty::Agent(waiting_party.agent));
+                true
+            },
+            // The active caller hung up; pick the other call back up
+            Busy => {
+                let waiting_party = self.line().waiting_party.replace(None).unwrap();
+                info!("Hookflash: switching to '{}'", waiting_party.agent.name());
+                self.load_other_party(OtherParty::Agent(waiting_party.agent));
+                self.set_state(Connected);
+                true
+            },
+            _ => false
+        }
+    }
+
+    /// Bridges the specified agent into the current line's call. Returns `true` if the agent joined the call.
+    fn bridge_agent(&'lua self, agent: Rc<AgentModule<'lua>>) -> bool {
+        if self.state() != PhoneLineState::Connected || self.get_other_party_agent().is_none() {
+            return false
+        }
+        // Only agents that aren't busy with another call can be bridged in
+        if self.is_agent_busy(&agent) || !matches!(agent.state(), Ok(AgentState::Idle)) {
+            info!("Can't bridge agent '{}' into the call: agent is busy", agent.name());
+            return false
+        }
+        let slot = match self.line().free_conference_slot() {
+            Some(slot) => slot,
+            None => {
+                info!("Can't bridge agent '{}' into the call: conference is full", agent.name());
+                return false
+            }
+        };
+        info!("Bridging agent '{}' into the call on line '{}'", agent.name(), self.line().name());
+        agent.set_call_reason(CallReason::Conference);
+        agent.transition_state(AgentState::Call);
+        self.line().conference.borrow_mut().push(ConferenceParty { agent, slot });
+        true
+    }
+
+    /// Removes the specified agent from the current line's conference call and silences its channels.
+    fn remove_conference_party(&self, agent: &AgentModule) {
+        let slot = {
+            let mut conference = self.line().conference.borrow_mut();
+            match conference.iter().position(|party| party.agent.id() == agent.id()) {
+                Some(index) => conference.remove(index).slot,
+                None => return
+            }
+        };
+        info!("Agent '{}' has left the conference call on line '{}'", agent.name(), self.line().name());
+        self.stop_conference_slot(slot);
+    }
+
+    /// Stops the sounds on the phone channels of a conference slot.
+    fn stop_conference_slot(&self, slot: usize) {
+        let sound_engine = self.line().sound_engine.borrow();
+        for ch in PHONE_CHANNELS.iter().skip(slot * CONFERENCE_CHANNELS_PER_PARTY).take(CONFERENCE_CHANNELS_PER_PARTY) {
+            sound_engine.stop(*ch);
+        }
+    }
+
+    /// Makes the earliest agent bridged into the current line's call its other party, after the other party has left.
+    /// Returns `false` if there was nobody left on the call.
+    fn promote_conference_party(&self) -> bool {
+        let party = {
+            let mut conference = self.line().conference.borrow_mut();
+            if conference.is_empty() {
+                return false
+            }
+            conference.remove(0)
+        };
+        info!("Agent '{}' is now the other party of the call on line '{}'", party.agent.name(), self.line().name());
+        self.stop_conference_slot(self.line().other_party_slot.replace(party.slot));
+        self.line().other_party.replace(Some(OtherParty::Agent(party.agent)));
+        true
+    }
+
+    /// Ends the calls of all agents bridged into the current line's call.
+    fn end_conference(&self) {
+        let conference = self.line().conference.take();
+        for party in conference {
+            party.agent.transition_state(AgentState::Idle);
+        }
+        self.line().other_party_slot.set(0);
+        self.line().three_way_dialing.set(false);
+    }
+
+    /// Handles a hookflash for three-way calling, if enabled. Returns `true` if the hookflash was used.
+    fn handle_three_way_hookflash(&'lua self) -> bool {
+        if !self.config.three_way_calling_enabled.unwrap_or(false) || self.state() != PhoneLineState::Connected {
+            return false
+        }
+        let other_party = match self.get_other_party_agent() {
+            Some(agent) => agent,
+            None => return false
+        };
+        if self.line().three_way_dialing.get() {
+            // Give up dialing and go back to the call
+            info!("Three-way calling canceled");
+            self.resume_three_way_call();
+            return true
+        }
+        let last_party = self.line().conference.borrow().last().map(|party| Rc::clone(&party.agent));
+        if let Some(last_party) = last_party {
+            // Drop the last agent bridged in
+            self.remove_conference_party(&last_party);
+            last_party.transition_state(AgentState::Idle);
+            return true
+        }
+        if self.line().free_conference_slot().is_none() {
+            return false
+        }
+        info!("Three-way calling: putting '{}' on hold", other_party.name());
+        other_party.transition_state(AgentState::Hold);
+        self.line().three_way_dialing.set(true);
+        self.clear_dialed_digits();
+        self.update_pdd_start();
+        {
+            let sound_engine = self.line().sound_engine.borrow();
+            sound_engine.stop_all_nonsignal();
+            sound_engine.play_dial_tone();
+        }
+        true
+    }
+
+    /// Stops three-way dialing and takes the other party off hold.
+    fn resume_three_way_call(&'lua self) {
+        self.line().three_way_dialing.set(false);
+        self.clear_dialed_digits();
+        self.line().sound_engine.borrow().stop(Channel::SignalIn);
+        if let Some(other_party) = self.get_other_party_agent() {
+            other_party.transition_state(AgentState::Call);
+        }
+    }
+
+    /// Bridges the agent dialed during three-way dialing into the call once the number is complete.
+    fn update_three_way_dialing(&'lua self) {
+        let dialed_number = self.get_dialed_digits();
+        let dial_time = self.now().saturating_duration_since(*self.line().pdd_start.borrow());
+        if dialed_number.is_empty() {
+            if dial_time.as_secs_f32() >= self.config.off_hook_delay {
+                info!("Three-way calling timed out");
+                self.resume_three_way_call();
+            }
+            return
+        }
+        if dial_time.as_secs_f32() < self.config.pdd && !self.is_dialed_number_complete() {
+            return
+        }
+        info!("Three-way calling: dialed {}", dialed_number);
+        self.resume_three_way_call();
+        match self.lookup_agent_destination(dialed_number.as_str()) {
+            Some(agent) => { self.bridge_agent(agent); },
+            None => info!("Three-way calling: no agent at {}", dialed_number)
+        }
+    }
+
+    /// Rings the current line with the agent that was waiting or on hold when the user hung up.
+    fn recall_waiting_party(&'lua self) {
+        if let Some(waiting_party) = self.line().waiting_party.replace(None) {
+            info!("Recalling '{}' on line '{}'", waiting_party.agent.name(), self.line().name());
+            if waiting_party.on_hold {
+                waiting_party.agent.transition_state(AgentState::OutgoingCall);
+            }
+            self.load_other_party(OtherParty::Agent(waiting_party.agent));
+            self.set_state(PhoneLineState::IdleRinging);
+        }
+    }
+
     /// Calls the intercept agent, if available.
     fn call_intercept(&'lua self, reason: CallReason) {
         if let Some(intercept_agent) = self.intercept_agent.borrow().as_ref() {
@@ -404,39 +1043,40 @@ impl<'lua> CursedEngine<'lua> {
 
     #[inline]
     pub fn state(&self) -> PhoneLineState {
-        self.state.borrow().clone()
+        *self.line().state.borrow()
     }
 
+
     #[inline]
     fn update_pdd_start(&self) {
-        self.pdd_start.replace(Instant::now());
+        self.line().pdd_start.replace(self.now());
     }
 
     #[inline]
     fn pdd_time(&self) -> Duration {
         if self.state() == PhoneLineState::PDD {
-            return Instant::now().saturating_duration_since(*self.pdd_start.borrow());
+            return self.now().saturating_duration_since(*self.line().pdd_start.borrow());
         }
         Duration::default()
     }
 
     #[inline]
     fn clear_dialed_digits(&self) {
-        self.dialed_digits.borrow_mut().clear();
+        self.line().dialed_digits.borrow_mut().clear();
     }
 
     #[inline]
     fn clear_called_number(&self) {
-        self.called_number.replace(None);
+        self.line().called_number.replace(None);
     }
 
     #[inline]
     fn consume_dialed_digit(&self) -> Option<char> {
-        self.dialed_digits.borrow_mut().pop()
+        self.line().dialed_digits.borrow_mut().pop()
     }
 
     fn get_dialed_digits(&self) -> String {
-        let dialed: String = self.dialed_digits.borrow().clone();
+        let dialed: String = self.line().dialed_digits.borrow().clone();
         dialed
     }
 
@@ -466,92 +1106,606 @@ impl<'lua> CursedEngine<'lua> {
     pub fn load_agents(&'lua self) {
         info!("Loading agents...");
         self.phone_book.borrow_mut().clear();
-        let mut agents = self.agents.borrow_mut();
-        let mut agents_numbered = self.phone_book.borrow_mut();
+        self.agent_number_patterns.borrow_mut().clear();
+        for path in self.agent_script_paths() {
+            self.load_agent(&path);
+        }
+        self.last_agent_scan.set(self.now());
+        info!("Total agents loaded: {}", self.agents.borrow().len());
+    }
+
+    /// Gets the paths of all agent scripts.
+    fn agent_script_paths(&self) -> Vec<VfsPath> {
+        let mut paths = vec![];
         for entry in self.agents_root.walk_dir().unwrap() {
             if let Ok(path) = entry {
-                match path.extension() {
-                    Some(ext) => {
-                        if ext != "lua" {
-                            continue
-                        }
-                    },
-                    None => continue,
+                if path.extension().as_deref() == Some("lua") {
+                    paths.push(path);
                 }
+            }
+        }
+        paths
+    }
 
-                self.reset_execution_limit();
+    /// Loads the agent script at the specified path.
+    /// If an agent was already loaded from the same path, the new agent takes its place.
+    fn load_agent(&'lua self, path: &VfsPath) -> bool {
+        self.reset_execution_limit();
 
-                let agent = match AgentModule::from_file(&self.lua, &path) {
-                    Ok(agent) => agent,
-                    Err(err) => {
-                        error!("Failed to load agent module '{}': {}", path.as_str(), err);
-                        continue
-                    },
-                };
+        if let Ok(src) = path.read_to_string() {
+            self.agent_script_hashes.borrow_mut().insert(path.as_str().to_owned(), hash_source(&src));
+        }
+
+        let agent = match AgentModule::from_file(&self.lua, path) {
+            Ok(agent) => agent,
+            Err(err) => {
+                error!("Failed to load agent module '{}': {}", path.as_str(), err);
+                return false
+            },
+        };
+
+        let agent_name = agent.name().to_owned();
+        let agent_role = agent.role();
 
-                // Register agent
-                let agent_name = agent.name().to_owned();
-                let agent_role = agent.role();
+        // Don't load Tollmasters if this isn't a payphone
+        if agent_role == AgentRole::Tollmaster && !self.config.payphone.enabled {
+            return false
+        }
+
+        let agent_phone_number = agent.phone_number().clone();
+        if let Some(line_id) = agent_phone_number.as_deref().and_then(|number| self.lookup_line_extension(number)) {
+            warn!("Agent '{}' has the same number as line '{}'; the line takes priority.", agent_name, self.lines[line_id].name());
+        }
+        let agent = Rc::new(agent);
+
+        // Register agent, reusing the slot of the agent it replaces
+        let prev_agent = self.find_agent_by_script_path(path.as_str());
+        let agent_id = match &prev_agent {
+            Some(prev_agent) => {
+                let agent_id = prev_agent.id().unwrap();
+                let mut agents = self.agents.borrow_mut();
+                if agents.get_index_of(agent_name.as_str()).is_some_and(|id| id != agent_id) {
+                    error!("Failed to reload agent module '{}': another agent is already named '{}'", path.as_str(), agent_name);
+                    return false
+                }
+                if agent_name == prev_agent.name() {
+                    *agents.get_index_mut(agent_id).unwrap().1 = Rc::clone(&agent);
+                } else {
+                    // Re-key the slot without changing the index, since agent IDs are indices
+                    info!("Agent '{}' was renamed to '{}'.", prev_agent.name(), agent_name);
+                    let (new_id, _) = agents.insert_full(agent_name, Rc::clone(&agent));
+                    agents.swap_indices(agent_id, new_id);
+                    agents.pop();
+                }
+                agent_id
+            },
+            None => self.agents.borrow_mut().insert_full(agent_name, Rc::clone(&agent)).0
+        };
+        agent.register_id(agent_id);
+
+        // Unload the agent being replaced
+        if let Some(prev_agent) = &prev_agent {
+            self.unload_agent(prev_agent, agent_id);
+        }
+
+        // Register agent number
+        if let Some(phone_number) = agent_phone_number {
+            if DialPattern::is_pattern(phone_number.as_str()) {
+                match DialPattern::parse(phone_number.as_str()) {
+                    Ok(pattern) => self.agent_number_patterns.borrow_mut().push((pattern, agent_id)),
+                    Err(err) => error!("Agent '{}' has an invalid number: {}", agent.name(), err)
+                }
+            } else if !phone_number.is_empty() {
+                self.phone_book.borrow_mut().insert(phone_number, agent_id);
+            }
+        }
+
+        // Register intercept agent
+        if agent_role == AgentRole::Intercept {
+            self.intercept_agent.replace(Some(Rc::clone(&agent)));
+        }
+
+        // The agent being replaced is already gone, so an agent that fails to start waits for recovery instead
+        if let Err(err) = agent.start_state_machine().map(|_| ()).and_then(|_| agent.call_load_handler()) {
+            error!("Agent '{}' failed to start: {}", agent.name(), err);
+            agent.set_suspended(true);
+            self.schedule_agent_recovery(&agent);
+            return false
+        }
 
-                // Don't load Tollmasters if this isn't a payphone
-                if agent_role == AgentRole::Tollmaster && !self.config.payphone.enabled {
+        info!("Agent {}: {} (num = {}, id = {:?})", if prev_agent.is_some() { "reloaded" } else { "loaded" }, agent.name(), agent.phone_number().as_deref().unwrap_or("[RESTRICTED]"), agent.id());
+        true
+    }
+
+    /// Runs the unload handler of an agent and removes its sound banks, phone numbers and intercept role.
+    /// The agent keeps its slot, since agent IDs are indices.
+    fn unload_agent(&self, agent: &AgentModule, agent_id: AgentId) {
+        if let Err(err) = agent.call_unload_handler() {
+            error!("Agent module '{}' encountered error while unloading: {}", agent.name(), err);
+        }
+        agent.unload_sound_banks(&self.sound_library);
+        self.phone_book.borrow_mut().retain(|_, id| *id != agent_id);
+        self.agent_number_patterns.borrow_mut().retain(|(_, id)| *id != agent_id);
+        let is_intercept = self.intercept_agent.borrow().as_ref().is_some_and(|intercept_agent| intercept_agent.id() == Some(agent_id));
+        if is_intercept {
+            self.intercept_agent.replace(None);
+        }
+    }
+
+    fn find_agent_by_script_path(&self, script_path: &str) -> Orc<AgentModule<'lua>> {
+        self.agents.borrow().values().find(|agent| agent.script_path().as_str() == script_path).map(Rc::clone)
+    }
+
+    /// Queues changed and deleted agent scripts for reloading.
+    fn scan_agent_scripts(&self) {
+        let script_paths = self.agent_script_paths();
+        for path in script_paths.iter() {
+            let hash = match path.read_to_string() {
+                Ok(src) => hash_source(&src),
+                Err(_) => continue
+            };
+            if self.agent_script_hashes.borrow().get(path.as_str()) == Some(&hash) { continue }
+            self.agent_script_hashes.borrow_mut().insert(path.as_str().to_owned(), hash);
+            info!("Agent script changed: {}", path.as_str());
+            self.queue_agent_reload(path);
+        }
+
+        let deleted_paths: Vec<String> = self.agent_script_hashes.borrow().keys()
+            .filter(|hashed_path| !script_paths.iter().any(|path| path.as_str() == hashed_path.as_str()))
+            .cloned()
+            .collect();
+        for deleted_path in deleted_paths {
+            self.agent_script_hashes.borrow_mut().remove(&deleted_path);
+            info!("Agent script deleted: {}", deleted_path);
+            if let Some(agent) = self.find_agent_by_script_path(&deleted_path) {
+                self.queue_agent_reload(agent.script_path());
+            }
+        }
+    }
+
+    /// Queues an agent script for reloading, unless it's already queued.
+    fn queue_agent_reload(&self, path: &VfsPath) {
+        let mut pending_reloads = self.pending_agent_reloads.borrow_mut();
+        if !pending_reloads.iter().any(|pending_path| pending_path.as_str() == path.as_str()) {
+            pending_reloads.push(path.clone());
+        }
+    }
+
+    /// Reloads changed agents, deferring agents that are busy with a call until they go idle.
+    fn reload_pending_agents(&'lua self) {
+        if self.pending_agent_reloads.borrow().is_empty() { return }
+        let pending_reloads = self.pending_agent_reloads.take();
+        for path in pending_reloads {
+            let prev_agent = self.find_agent_by_script_path(path.as_str());
+            if let Some(prev_agent) = &prev_agent {
+                // Suspended agents can't finish their calls, so don't wait for them
+                if prev_agent.suspended() {
+                    self.end_agent_call(prev_agent);
+                } else if self.is_agent_busy(prev_agent) || !matches!(prev_agent.state(), Ok(AgentState::Idle)) {
+                    self.pending_agent_reloads.borrow_mut().push(path);
                     continue
                 }
+            }
+            if !path.exists().unwrap_or(true) {
+                // The agent can't be removed without changing the IDs of other agents, so it stays suspended in its slot
+                if let Some(prev_agent) = &prev_agent {
+                    let agent_id = prev_agent.id().unwrap();
+                    self.unload_agent(prev_agent, agent_id);
+                    prev_agent.set_suspended(true);
+                    self.agent_failures.borrow_mut().remove(&agent_id);
+                    info!("Agent unloaded: {}", prev_agent.name());
+                }
+                continue
+            }
+            if self.load_agent(&path) {
+                // A fixed script gets a clean slate
+                if let Some(agent_id) = prev_agent.and_then(|agent| agent.id()) {
+                    self.agent_failures.borrow_mut().remove(&agent_id);
+                }
+            }
+        }
+    }
 
-                let agent_phone_number = agent.phone_number().clone();
-                let agent = Rc::new(agent);
-                let (agent_id, _) = agents.insert_full(agent_name, Rc::clone(&agent));
-                agent.register_id(agent_id);
+    /// Ends the call that the specified agent is on, if any.
+    fn end_agent_call(&'lua self, agent: &AgentModule) {
+        self.disconnect_agent_call(agent);
+        if let Some(line_id) = self.find_agent_line(agent) {
+            self.with_line(line_id, || {
+                if self.line().is_waiting_party(agent) {
+                    self.drop_waiting_party();
+                } else if self.line().is_conference_party(agent) {
+                    self.remove_conference_party(agent);
+                    agent.transition_state(AgentState::Idle);
+                } else if self.line().is_voicemail_party(agent) {
+                    self.finish_voicemail_recording();
+                } else {
+                    self.end_call_remotely();
+                }
+            });
+        }
+    }
 
-                // Register agent number
-                if let Some(phone_number) = agent_phone_number {
-                    if !phone_number.is_empty() {
-                        agents_numbered.insert(phone_number, agent_id);
-                    }
+    /// Gets the ring pattern for the current line's incoming call.
+    /// A pattern chosen by the caller for this call comes first, then ring rules, the caller's custom pattern, and the line's default pattern.
+    fn current_ring_pattern(&self) -> Option<Arc<RingPattern>> {
+        if let Some(pattern) = self.line().call_ring_pattern.borrow().as_ref() {
+            return Some(Arc::clone(pattern))
+        }
+        let agent = self.get_other_party_agent();
+        let caller = match agent.as_ref() {
+            Some(agent) => RingCaller {
+                agent: Some(agent.name()),
+                role: Some(agent.role()),
+                reason: Some(agent.call_reason()),
+            },
+            None => Default::default()
+        };
+        self.ring_rules.select(&caller, self.clock.local_now())
+            .or_else(|| agent.as_ref().and_then(|agent| agent.custom_ring_pattern()))
+            .or_else(|| self.line().default_ring_pattern.clone())
+    }
+
+    /// Gets how long the current line's incoming call rings before the answering machine picks up.
+    fn voicemail_answer_delay(&self) -> Duration {
+        let ring_cycle = self.current_ring_pattern()
+            .map(|pattern| pattern.cycle_duration())
+            .filter(|cycle| !cycle.is_zero())
+            .unwrap_or(DEFAULT_RING_CYCLE_DURATION);
+        ring_cycle * self.config.voicemail.answer_after_rings
+    }
+
+    /// Returns `true` if the current line should show that a message is waiting,
+    /// either because an agent set the indicator or because there are voicemail messages.
+    fn is_message_waiting(&self) -> bool {
+        self.line().message_waiting.get() || (self.config.voicemail.enabled && !self.voicemail.borrow().is_empty())
+    }
+
+    /// Updates the stutter dial tone and message-waiting lamp of the current line when the indicator changes.
+    fn update_message_waiting(&self) {
+        let message_waiting = self.is_message_waiting();
+        if self.line().message_waiting_shown.replace(message_waiting) == message_waiting { return }
+        info!("Message waiting on line '{}': {}", self.line().name(), message_waiting);
+        self.line().sound_engine.borrow().set_message_waiting(message_waiting);
+        self.send_output(PhoneOutputSignal::MessageWaiting(message_waiting));
+    }
+
+    /// Sends caller ID for the current line's incoming call, if it's from an agent.
+    fn send_caller_id(&self) {
+        self.line().caller_id_sent.set(true);
+        let agent = match self.get_other_party_agent() {
+            Some(agent) => agent,
+            None => return
+        };
+        let info = CallerIdInfo {
+            name: Some(agent.name().to_owned()),
+            number: agent.phone_number(),
+            time: self.clock.local_now(),
+        };
+        let caller_id_config = &self.config.caller_id;
+        info!("Sending caller ID: {} ({})", agent.name(), info.number.as_deref().unwrap_or("unavailable"));
+        self.line().sound_engine.borrow().play_caller_id(&info, caller_id_config.format, caller_id_config.channel, caller_id_config.gain);
+    }
+
+    /// Answers the current line's incoming call with the answering machine, letting the calling agent leave a message.
+    fn start_voicemail_recording(&'lua self) {
+        let agent = match self.line().other_party.take() {
+            Some(OtherParty::Agent(agent)) => agent,
+            other_party => {
+                self.line().other_party.replace(other_party);
+                return
+            }
+        };
+        info!("Answering machine picked up call from agent '{}'", agent.name());
+        self.set_state(PhoneLineState::Idle);
+        self.line().voicemail_recording.replace(Some(VoicemailRecording {
+            agent: Rc::clone(&agent),
+            start: self.now(),
+            events: vec![],
+        }));
+        agent.set_call_reason(CallReason::Voicemail);
+        agent.transition_state(AgentState::Call);
+    }
+
+    /// Ends the message being left on the current line's answering machine and saves it, if anything was recorded.
+    fn finish_voicemail_recording(&'lua self) {
+        let recording = match self.line().voicemail_recording.take() {
+            Some(recording) => recording,
+            None => return
+        };
+        self.line().sound_engine.borrow().stop_all_except(Channel::SignalOut);
+        if matches!(recording.agent.state(), Ok(AgentState::Call)) {
+            recording.agent.transition_state(AgentState::Idle);
+        }
+        if recording.events.is_empty() {
+            info!("Agent '{}' hung up without leaving a message.", recording.agent.name());
+            return
+        }
+        let duration = self.now().saturating_duration_since(recording.start);
+        info!("Agent '{}' left a {:.1}s message on line '{}'", recording.agent.name(), duration.as_secs_f32(), self.line().name());
+        self.voicemail.borrow_mut().add(VoicemailMessage {
+            agent: recording.agent.name().to_owned(),
+            line: self.line().name().to_owned(),
+            time: self.clock.local_now().timestamp(),
+            duration: duration.as_secs_f64(),
+            events: recording.events,
+        });
+    }
+
+    /// Adds a sound event to the current line's voicemail recording if the ticking agent is the one leaving the message.
+    fn record_voicemail_event(&self, event: impl FnOnce(Duration) -> VoicemailEvent) {
+        let mut recording = self.line().voicemail_recording.borrow_mut();
+        if let Some(recording) = recording.as_mut() {
+            if self.ticking_agent.get().is_some() && recording.agent.id() == self.ticking_agent.get() {
+                let at = self.now().saturating_duration_since(recording.start);
+                recording.events.push(event(at));
+            }
+        }
+    }
+
+    /// Starts playing the stored messages to the current line if the specified number is the voicemail access number.
+    /// Returns `true` if the number was the access number.
+    fn handle_voicemail_access(&'lua self, number: &str) -> bool {
+        let voicemail_config = &self.config.voicemail;
+        if !voicemail_config.enabled || voicemail_config.access_number.is_empty() || number != voicemail_config.access_number {
+            return false
+        }
+        self.update_call_record(|record| { record.dialed.get_or_insert_with(|| number.to_owned()); });
+        let voicemail = self.voicemail.borrow();
+        if voicemail.is_empty() {
+            info!("No voicemail messages.");
+            drop(voicemail);
+            self.set_state(PhoneLineState::Busy);
+            return true
+        }
+        info!("Playing {} voicemail message(s).", voicemail.len());
+        let playback = VoicemailPlayback::new(voicemail.messages(), self.now(), VOICEMAIL_BEEP_DURATION);
+        drop(voicemail);
+        self.set_state(PhoneLineState::Connected);
+        self.line().voicemail_playback.replace(Some(playback));
+        true
+    }
+
+    /// Plays the due cues of the current line's voicemail playback, and hangs up once every message has been played.
+    fn update_voicemail_playback(&'lua self) {
+        let mut due_cues = vec![];
+        let finished = {
+            let mut playback = self.line().voicemail_playback.borrow_mut();
+            let playback = match playback.as_mut() {
+                Some(playback) => playback,
+                None => return
+            };
+            let elapsed = self.now().saturating_duration_since(playback.start);
+            while playback.cues.front().is_some_and(|(at, _)| *at <= elapsed) {
+                match playback.cues.pop_front() {
+                    Some((_, VoicemailCue::MessageEnd)) => playback.messages_played += 1,
+                    Some((_, cue)) => due_cues.push(cue),
+                    None => break
                 }
+            }
+            playback.cues.is_empty()
+        };
 
-                // Register intercept agent
-                match agent_role {
-                    AgentRole::Intercept => {
-                        self.intercept_agent.replace(Some(Rc::clone(&agent)));
+        {
+            let sound_engine = self.line().sound_engine.borrow();
+            for cue in due_cues {
+                match cue {
+                    VoicemailCue::Beep => {
+                        sound_engine.stop_all_nonsignal();
+                        sound_engine.play_voicemail_beep();
+                    },
+                    VoicemailCue::Event(event) => match event.sound.as_deref() {
+                        Some(sound) => { sound_engine.play(sound, event.channel, false, true, event.play_options()); },
+                        None => sound_engine.stop(event.channel),
                     },
-                    _ => {}
+                    VoicemailCue::MessageEnd => {}
                 }
+            }
+        }
+
+        if finished {
+            info!("End of voicemail messages.");
+            self.set_state(PhoneLineState::Busy);
+        }
+    }
+
+    /// Records an error raised by an agent and schedules its recovery according to the configured policy.
+    fn schedule_agent_recovery(&self, agent: &AgentModule) {
+        let agent_id = match agent.id() {
+            Some(agent_id) => agent_id,
+            None => return
+        };
+        let settings = self.config.agent_recovery.for_agent(agent.name());
+        if settings.action == AgentRecoveryAction::Suspend { return }
+
+        let now = self.now();
+        let mut failures = self.agent_failures.borrow_mut();
+        let failure = failures.entry(agent_id).or_insert(AgentFailure { count: 0, last_error: now, retry_at: None });
+        if now.saturating_duration_since(failure.last_error).as_secs_f32() >= settings.retry_reset {
+            failure.count = 0;
+        }
+        failure.count += 1;
+        failure.last_error = now;
 
-                agent.start_state_machine().expect(format!("Failed to start state machine for agent '{}'", agent.name()).as_str());
-                agent.call_load_handler().expect(format!("Failed to call load handler for agent '{}'", agent.name()).as_str());
+        if failure.count > settings.max_retries {
+            warn!("Agent '{}' has failed {} times in a row; leaving it suspended.", agent.name(), failure.count);
+            failure.retry_at = None;
+            return
+        }
 
-                info!("Agent loaded: {} (num = {}, id = {:?})", agent.name(), agent.phone_number().as_deref().unwrap_or("[RESTRICTED]"), agent.id());
+        let delay = (settings.retry_delay * 2f32.powi(failure.count as i32 - 1)).min(settings.max_retry_delay).max(0.0);
+        failure.retry_at = Some(now + Duration::from_secs_f32(delay));
+        info!("Agent '{}' will {:?} in {:.1}s (attempt {} of {}).", agent.name(), settings.action, delay, failure.count, settings.max_retries);
+    }
+
+    /// Recovers suspended agents whose recovery attempts are due.
+    fn update_agent_recovery(&'lua self) {
+        let now = self.now();
+        let due_agent_ids: Vec<AgentId> = self.agent_failures.borrow_mut().iter_mut()
+            .filter(|(_, failure)| failure.retry_at.is_some_and(|retry_at| retry_at <= now))
+            .map(|(agent_id, failure)| {
+                failure.retry_at = None;
+                *agent_id
+            })
+            .collect();
+
+        for agent_id in due_agent_ids {
+            let agent = match self.lookup_agent_id(agent_id) {
+                Some(agent) => agent,
+                None => continue
+            };
+            // The agent may have been replaced or resumed in the meantime
+            if !agent.suspended() { continue }
+
+            self.end_agent_call(&agent);
+
+            match self.config.agent_recovery.for_agent(agent.name()).action {
+                AgentRecoveryAction::Suspend => {},
+                AgentRecoveryAction::Restart => {
+                    info!("Restarting agent '{}'.", agent.name());
+                    self.reset_execution_limit();
+                    match agent.restart_state_machine() {
+                        Ok(()) => agent.set_suspended(false),
+                        Err(err) => {
+                            error!("Failed to restart agent '{}': {}", agent.name(), err);
+                            self.schedule_agent_recovery(&agent);
+                        }
+                    }
+                },
+                AgentRecoveryAction::Reload => {
+                    info!("Reloading agent '{}'.", agent.name());
+                    if !self.load_agent(agent.script_path()) {
+                        // A new agent that failed to start has already scheduled its own recovery
+                        let is_replaced = self.lookup_agent_id(agent_id).is_some_and(|current| !Rc::ptr_eq(&current, &agent));
+                        if !is_replaced {
+                            self.schedule_agent_recovery(&agent);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks for changed agent scripts if hot-reloading is enabled.
+    fn update_agent_reloads(&'lua self) {
+        if let Some(interval) = self.agent_reload_interval {
+            let now = self.now();
+            if now.saturating_duration_since(self.last_agent_scan.get()) >= interval {
+                self.last_agent_scan.set(now);
+                self.scan_agent_scripts();
             }
         }
-        info!("Total agents loaded: {}", agents.len());
+        self.reload_pending_agents();
     }
 
-    /// Gets the other party associated with the active or pending call.
+    /// Gets the agent associated with the active or pending call.
     fn get_other_party_agent(&self) -> Orc<AgentModule> {
-        if let Some(agent) = self.other_party.borrow().as_ref() {
+        if let Some(OtherParty::Agent(agent)) = self.line().other_party.borrow().as_ref() {
             return Some(Rc::clone(agent));
         }
         None
     }
 
     /// Unsets the current other party.
-    fn unload_other_party(&self) {
-        if let Some(agent) = self.other_party.borrow().as_ref() {
-            agent.transition_state(AgentState::Idle);
-            //agent.unload_sound_banks(&self.sound_engine);
+    fn unload_other_party(&'lua self) {
+        self.line().voice_input.replace(None);
+        self.end_conference();
+        match self.line().other_party.replace(None) {
+            Some(OtherParty::Agent(agent)) => {
+                agent.transition_state(AgentState::Idle);
+                //agent.unload_sound_banks(&self.line().sound_engine);
+            },
+            Some(OtherParty::Line(other_line_id)) => {
+                // Hang up the other line's end of the call, unless it has already moved on
+                let line_id = self.current_line.get();
+                if self.lines[other_line_id].other_line() == Some(line_id) {
+                    self.with_line(other_line_id, || self.end_call_remotely());
+                }
+            },
+            None => {}
+        }
+    }
+
+    /// Ends the current line's call as if the other party hung up.
+    fn end_call_remotely(&'lua self) {
+        match self.state() {
+            PhoneLineState::Idle => {},
+            PhoneLineState::IdleRinging => self.set_state(PhoneLineState::Idle),
+            _ => self.set_state(PhoneLineState::Busy),
+        }
+    }
+
+    /// Sets the other party of the current line.
+    fn load_other_party(&self, other_party: OtherParty<'lua>) {
+        self.update_call_record(|record| match &other_party {
+            OtherParty::Agent(agent) => record.agent = Some(agent.name().to_owned()),
+            OtherParty::Line(line_id) => record.other_line = Some(self.lines[*line_id].name().to_owned()),
+        });
+        self.line().other_party.replace(Some(other_party));
+    }
+
+    /// Modifies the record of the current line's call, if a call is in progress.
+    fn update_call_record(&self, f: impl FnOnce(&mut CallRecord)) {
+        if let Some(record) = self.line().call_record.borrow_mut().as_mut() {
+            f(record);
+        }
+    }
+
+    /// Updates the current line's call record for a state transition, and logs the record once the call is over.
+    fn update_call_record_state(&self, prev_state: PhoneLineState, state: PhoneLineState) {
+        use PhoneLineState::*;
+        let now = self.clock.local_now();
+        match (prev_state, state) {
+            (Idle, _) => {
+                let direction = if state == IdleRinging { CallDirection::Incoming } else { CallDirection::Outgoing };
+                let mut record = CallRecord::new(self.line().name(), direction, now);
+                // Incoming calls already have a caller
+                match self.line().other_party.borrow().as_ref() {
+                    Some(OtherParty::Agent(agent)) => record.agent = Some(agent.name().to_owned()),
+                    Some(OtherParty::Line(line_id)) => record.other_line = Some(self.lines[*line_id].name().to_owned()),
+                    None => {}
+                }
+                self.line().call_record.replace(Some(record));
+            },
+            (_, Idle) => {
+                if let Some(mut record) = self.line().call_record.take() {
+                    match prev_state {
+                        Connected => record.disconnect_cause = DisconnectCause::UserHangup,
+                        IdleRinging => record.disconnect_cause = DisconnectCause::Unanswered,
+                        DialTone | PDD | CallingOut => record.disconnect_cause = DisconnectCause::Abandoned,
+                        _ => {}
+                    }
+                    record.end_time = now;
+                    info!("Call ended: {}", record.to_json());
+                    if let Some(call_log) = &self.call_log {
+                        call_log.write(&record);
+                    }
+                    if let Some(call_record_log) = self.line().call_record_log.borrow_mut().as_mut() {
+                        call_record_log.push(record.clone());
+                    }
+                    self.call_history.borrow_mut().add(&record);
+                }
+            },
+            (Connected, Busy) => self.update_call_record(|record| record.disconnect_cause = DisconnectCause::RemoteHangup),
+            (_, Busy) => self.update_call_record(|record| record.disconnect_cause = DisconnectCause::Busy),
+            (_, Connected) => self.update_call_record(|record| { record.answer_time.get_or_insert(now); }),
+            _ => {}
         }
-        self.other_party.replace(None);
     }
 
-    /// Sets the other party to the specified agent.
-    fn load_other_party(&self, agent: Rc<AgentModule<'lua>>) {
-        self.other_party.replace(Some(agent));
+    /// Routes the current line's handset microphone to the handset of the specified line.
+    fn connect_voice(&self, to_line_id: LineId) {
+        let sound_config = &self.line().config.sound;
+        // Live capture only makes sense when the handsets are real devices
+        if sound_config.output != SoundOutputType::Device { return }
+        if let Some(voice_input) = sound_config.input_device.as_deref().and_then(VoiceInput::open) {
+            self.lines[to_line_id].sound_engine.borrow().play_source(voice_input.source(), Channel::Phone01);
+            self.line().voice_input.replace(Some(voice_input));
+        }
     }
 
     fn play_comfort_noise(&self) {
         if let Some(comfort_noise) = &self.config.sound.comfort_noise_name {
-            let sound_engine = self.sound_engine.borrow();
+            let sound_engine = self.line().sound_engine.borrow();
             if sound_engine.channel_busy(Channel::NoiseIn) { return }
             sound_engine.play(
                 comfort_noise.as_str(), 
@@ -570,35 +1724,41 @@ impl<'lua> CursedEngine<'lua> {
     /// Sets the current state of the engine.
     fn set_state(&'lua self, state: PhoneLineState) {
         use PhoneLineState::*;
-        if *self.state.borrow() == state {
+        if *self.line().state.borrow() == state {
             return;
         }
         
-        let prev_state = self.state.replace(state);
-        let state_start = Instant::now();
-        let last_state_start = self.state_start.replace(state_start);
+        let prev_state = self.line().state.replace(state);
+        if let Some(state_log) = self.line().state_log.borrow_mut().as_mut() {
+            state_log.push(state);
+        }
+        self.update_call_record_state(prev_state, state);
+        let state_start = self.now();
+        let last_state_start = self.line().state_start.replace(state_start);
         let state_time = state_start.saturating_duration_since(last_state_start);
 
         // Make sure that canceled unpaid call doesn't keep pinging Tollmaster
-        self.deposit_needed.replace(false);
+        self.line().deposit_needed.replace(false);
 
         // Run behavior for state we're leaving
         match prev_state {
             PhoneLineState::IdleRinging => {
                 self.send_output(PhoneOutputSignal::Ring(None));
+                self.line().call_ring_pattern.replace(None);
             },
             PhoneLineState::Connected => {
                 self.clear_called_number();
-                let mut sound_engine = self.sound_engine.borrow_mut();
-                for ch in NON_SOUL_CHANNELS.iter() {
-                    let ch = *ch;
-                    sound_engine.set_channel_speed(ch, 1.0);
-                    sound_engine.set_channel_volume(ch, 1.0);
-                    sound_engine.set_channel_fade_volume(ch, 1.0);
+                self.line().voice_input.replace(None);
+                if let Some(playback) = self.line().voicemail_playback.take() {
+                    // Messages that were played in full are deleted
+                    self.voicemail.borrow_mut().remove_oldest(playback.messages_played);
                 }
+                self.line().pending_hookflash.set(false);
+                self.line().three_way_dialing.set(false);
+                self.reset_channel_mix();
                 if self.config.payphone.enabled {
                     // When leaving the connected state, clear existing time credit
-                    self.deposit_consumed.replace(false);
+                    self.line().deposit_consumed.replace(false);
                     self.clear_time_credit();
                 }
             }
@@ -617,44 +1777,53 @@ impl<'lua> CursedEngine<'lua> {
         match (prev_state, state) {
             (_, Idle) => {
                 self.unload_other_party();
-                self.sound_engine.borrow().stop_all_except(Channel::SignalOut);
+                self.line().sound_engine.borrow().stop_all_except(Channel::SignalOut);
                 self.clear_dialed_digits();
                 self.clear_called_number();
             },
             (_, IdleRinging) => {
-                let ring_pattern = self.get_other_party_agent()
-                    .map(|agent| agent.custom_ring_pattern())
-                    .flatten()
-                    .or_else(|| self.default_ring_pattern.clone());
-                self.send_output(PhoneOutputSignal::Ring(ring_pattern));
+                self.line().caller_id_sent.set(false);
+                self.send_output(PhoneOutputSignal::Ring(self.current_ring_pattern()));
             },
             (_, DialTone) => {
-                self.sound_engine.borrow().play_dial_tone();
+                self.line().sound_engine.borrow().play_dial_tone();
             },
             (_, Busy) => {
                 self.unload_other_party();
-                let sound_engine = self.sound_engine.borrow();
-                sound_engine.stop_all_nonsignal();
-                sound_engine.play_busy_tone();
+                self.line().sound_engine.borrow().stop_all_nonsignal();
+                self.play_busy_or_hold_music();
             },
             (_, PDD) => {
                 // Stop any PBX signals
-                self.sound_engine.borrow().stop(Channel::SignalIn);
+                self.line().sound_engine.borrow().stop(Channel::SignalIn);
                 self.update_pdd_start();
             },
             (_, CallingOut) => {
-                self.last_dialed_number.replace(Some(self.dialed_digits.borrow().clone()));
+                self.line().last_dialed_number.replace(Some(self.line().dialed_digits.borrow().clone()));
                 if let Some(agent) = self.get_other_party_agent() {
                     self.clear_dialed_digits();
-                    self.sound_engine.borrow().stop(Channel::SignalIn);
+                    self.line().sound_engine.borrow().stop(Channel::SignalIn);
 
                     // Tell agent that we're calling it
                     agent.transition_state(AgentState::IncomingCall);
 
                     // Finally, play the ringback tone (if we're allowed to)
                     if agent.ringback_enabled() {
-                        self.sound_engine.borrow().play_ringback_tone();
+                        self.line().sound_engine.borrow().play_ringback_tone();
                     }
+                } else if let Some(other_line_id) = self.line().other_line() {
+                    self.clear_dialed_digits();
+                    self.line().sound_engine.borrow().stop(Channel::SignalIn);
+
+                    // Ring the other line
+                    let line_id = self.current_line.get();
+                    self.with_line(other_line_id, || {
+                        self.load_other_party(OtherParty::Line(line_id));
+                        self.set_state(IdleRinging);
+                        self.line().last_caller_id.replace(None);
+                    });
+
+                    self.line().sound_engine.borrow().play_ringback_tone();
                 } else {
                     warn!("No remote party specified when calling out.");
                     self.call_intercept(CallReason::NumberDisconnected);
@@ -663,17 +1832,20 @@ impl<'lua> CursedEngine<'lua> {
             (_, Connected) => {
                 self.clear_dialed_digits();
                 // Stop all existing sounds except for host signals
-                let sound_engine = self.sound_engine.borrow();
-                sound_engine.stop(Channel::SignalIn);
+                self.line().sound_engine.borrow().stop(Channel::SignalIn);
                 // Transition connecting agent to call state
-                if let Some(agent) = self.other_party.borrow().as_ref() {
+                if let Some(agent) = self.get_other_party_agent() {
                     agent.transition_state(AgentState::Call);
+                } else if let Some(other_line_id) = self.line().other_line() {
+                    // Connect the other end of the call and route the voice in both directions
+                    self.with_line(other_line_id, || self.set_state(Connected));
+                    self.connect_voice(other_line_id);
                 }
             }
             _ => {}
         }
 
-        info!("{:?} ({:?}) --> {:?}", prev_state, state_time, state);
+        info!("[{}] {:?} ({:?}) --> {:?}", self.line().name(), prev_state, state_time, state);
     }
 
     /// Called when an off-hook timeout occurs.
@@ -702,18 +1874,24 @@ impl<'lua> CursedEngine<'lua> {
             PDD => {
                 self.update_pdd_start();
             },
+
+            // Dialing a number to bridge into the call
+            Connected if self.line().three_way_dialing.get() => {
+                self.line().sound_engine.borrow().stop(Channel::SignalIn);
+                self.update_pdd_start();
+            },
             _ => {}
         }
 
         info!("Host dialed '{}'", digit);
 
         // Add digit to dialed number
-        self.dialed_digits.borrow_mut().push(digit);
+        self.line().dialed_digits.borrow_mut().push(digit);
     }
 
     #[inline]
     fn pulses_to_digit(&self, pulse_count: usize) -> Option<char> {
-        self.config.rotary.digit_layout.chars().nth(pulse_count.saturating_sub(1))
+        self.line().config.rotary.digit_layout.chars().nth(pulse_count.saturating_sub(1))
     }
 
     /// Called when the engine receives a pulse from the host's rotary dial.
@@ -722,15 +1900,15 @@ impl<'lua> CursedEngine<'lua> {
         match self.state() {
             PhoneLineState::Idle | PhoneLineState::IdleRinging => return,
             _ => {
-                let current_rest_state = self.rotary_resting.get();
+                let current_rest_state = self.line().rotary_resting.get();
                 if !current_rest_state {
                     // This is a fix for my noisy rotary dial randomly pulsing when I lift it from resting.
                     // Forcing a delay between the dial lift and the first pulse seems to resolve this issue.
-                    let rotary_rest_lifted_time = self.rotary_dial_lift_time.get().elapsed();
-                    if rotary_rest_lifted_time > self.rotary_first_pulse_delay {
+                    let rotary_rest_lifted_time = self.now().saturating_duration_since(self.line().rotary_dial_lift_time.get());
+                    if rotary_rest_lifted_time > self.line().rotary_first_pulse_delay {
                         // Increment pulse count
-                        update_cell(&self.pending_pulse_count, |old| old + 1);
-                        self.sound_engine.borrow().play("rotary/pulse", Channel::SignalOut, false, true, Default::default());
+                        update_cell(&self.line().pending_pulse_count, |old| old + 1);
+                        self.line().sound_engine.borrow().play("rotary/pulse", Channel::SignalOut, false, true, Default::default());
                     } else {
                         trace!("Discarded premature rotary dial pulse");
                     }
@@ -741,13 +1919,13 @@ impl<'lua> CursedEngine<'lua> {
 
     pub fn remaining_time_credit(&self) -> Unlimited<Duration> {
         match self.state() {
-            PhoneLineState::Connected => self.total_time_credit.borrow().map(|d| d.checked_sub(self.current_state_time()).unwrap_or_default()),
-            _ => *self.total_time_credit.borrow()
+            PhoneLineState::Connected => self.line().total_time_credit.borrow().map(|d| d.checked_sub(self.current_state_time()).unwrap_or_default()),
+            _ => *self.line().total_time_credit.borrow()
         }
     }
 
     fn clear_time_credit(&self) {
-        self.total_time_credit.replace(Default::default());
+        self.line().total_time_credit.replace(Default::default());
         info!("Time credit cleared.");
     }
 
@@ -761,6 +1939,12 @@ impl<'lua> CursedEngine<'lua> {
 
         if !payphone_config.enabled { return true }
 
+        // Calls between lines are always free
+        if self.line().other_line().is_some() { return true }
+
+        // So is listening to voicemail
+        if self.line().voicemail_playback.borrow().is_some() { return true }
+
         // If the payphone has a standard rate of 0 and custom rates are ignored, it's free
         if !payphone_config.enable_custom_agent_rates && payphone_config.standard_call_rate == 0 { return true }
         
@@ -792,13 +1976,14 @@ impl<'lua> CursedEngine<'lua> {
     }
 
     pub fn awaiting_initial_deposit(&self) -> bool {
-        *self.deposit_needed.borrow()
+        *self.line().deposit_needed.borrow()
     }
 
     fn add_deposit(&self, credits: u32) {
         let mut total = 0;
-        self.deposit.replace_with(|credits_old| { total = *credits_old + credits; total });
+        self.line().deposit.replace_with(|credits_old| { total = *credits_old + credits; total });
         info!("Credits deposited: {} (total = {})", credits, total);
+        self.update_call_record(|record| record.deposited += credits);
         self.consume_deposit();
     }
 
@@ -807,11 +1992,12 @@ impl<'lua> CursedEngine<'lua> {
     fn consume_deposit(&self) -> bool {
         if self.state() == PhoneLineState::Connected {
             // Check if time credit can be added to the call
-            let mut deposit = self.deposit.borrow_mut();
+            let mut deposit = self.line().deposit.borrow_mut();
             let rate = self.current_call_rate();
             if rate > 0 && *deposit >= rate {
                 let time_credit_multiplier = *deposit / rate;
                 *deposit %= rate;
+                self.update_call_record(|record| record.consumed += rate * time_credit_multiplier);
                 info!("Consuming {} credit(s).", deposit);
                 let time_credit = Duration::from_secs(self.config.payphone.time_credit_seconds.saturating_mul(time_credit_multiplier as u64));
                 self.add_time_credit(Finite(time_credit));
@@ -823,13 +2009,13 @@ impl<'lua> CursedEngine<'lua> {
 
     /// Adds the specified amount of time to the call (payphone only).
     fn add_time_credit(&self, credit: Unlimited<Duration>) {
-        self.total_time_credit.replace_with(|cur| *cur + credit);
+        self.line().total_time_credit.replace_with(|cur| *cur + credit);
         info!("Added call time: {:?}", credit);
     }
 
     /// Indicates whether the initial deposit for the current call was consumed.
     fn initial_deposit_consumed(&self) -> bool {
-        *self.deposit_consumed.borrow()
+        *self.line().deposit_consumed.borrow()
     }
 
     /// Called when the user deposits a coin.
@@ -841,7 +2027,7 @@ impl<'lua> CursedEngine<'lua> {
     /// Called when the resting state of the host's rotary dial changes.
     #[inline]
     fn handle_rotary_rest_state(&'lua self, resting: bool) {
-        if resting == self.rotary_resting.replace(resting) {return}
+        if resting == self.line().rotary_resting.replace(resting) {return}
 
         if resting {
             // Ignore 
@@ -849,7 +2035,7 @@ impl<'lua> CursedEngine<'lua> {
                 PhoneLineState::Idle | PhoneLineState::IdleRinging => {},
                 _ => {
                     // When dial moves to resting, dial digit according to pulse count
-                    let digit_num = self.pending_pulse_count.take();
+                    let digit_num = self.line().pending_pulse_count.take();
                     if let Some(digit) = self.pulses_to_digit(digit_num) {
                         self.handle_host_digit(digit);
                     }
@@ -858,13 +2044,13 @@ impl<'lua> CursedEngine<'lua> {
             
         } else {
             // When dial moves away from resting, reset pulse count
-            self.pending_pulse_count.replace(0);
-            self.rotary_dial_lift_time.replace(Instant::now());
+            self.line().pending_pulse_count.replace(0);
+            self.line().rotary_dial_lift_time.replace(self.now());
         }
     }
 
     fn set_line_muted(&'lua self, muted: bool) {
-        let mut sound_engine = self.sound_engine.borrow_mut();
+        let mut sound_engine = self.line().sound_engine.borrow_mut();
 
         for ch in crate::sound::NON_SOUL_CHANNELS {
             sound_engine.set_muted(*ch, muted);
@@ -872,25 +2058,49 @@ impl<'lua> CursedEngine<'lua> {
     }
 
     pub fn is_switchhook_locked(&'lua self) -> bool {
-        self.switchhook_locked.get()
+        self.line().switchhook_locked.get()
     }
 
     pub fn set_switchhook_locked(&'lua self, is_locked: bool) {
-        if self.switchhook_locked.replace(is_locked) != is_locked {
+        if self.line().switchhook_locked.replace(is_locked) != is_locked {
             info!("Switchhook {}", if is_locked { "LOCKED" } else { "UNLOCKED" });
             if !is_locked {
-                self.handle_hook_state_change(self.switchhook_closed.get(), true);
+                self.handle_hook_state_change(self.line().switchhook_closed.get(), true);
             }
         }
     }
 
+    /// Returns `true` if anything on the current line would use a hookflash: call waiting, three-way calling, or an agent waiting for one.
+    fn is_hookflash_handled(&self) -> bool {
+        self.config.call_waiting_enabled.unwrap_or(false)
+            || self.config.three_way_calling_enabled.unwrap_or(false)
+            || self.line().hookflash_awaited.get()
+    }
+
+    /// Returns `true` if the switchhook being held down for the specified time counts as a hookflash.
+    fn is_hookflash_duration(&self, duration: Duration) -> bool {
+        self.is_hookflash_handled() && duration.as_secs_f32() >= self.config.hookflash_min_duration && duration < self.hangup_delay()
+    }
+
+    /// Gets the length of time the switchhook must be held down to end the call.
+    /// Without switchhook dialing, hangups are only delayed to tell them apart from hookflashes that something would use.
+    fn hangup_delay(&self) -> Duration {
+        let delay = match (self.switchhook_dialing_enabled, self.is_hookflash_handled()) {
+            (true, true) => self.config.shd_hangup_delay.min(self.config.hookflash_max_duration),
+            (true, false) => self.config.shd_hangup_delay,
+            (false, true) => self.config.hookflash_max_duration,
+            (false, false) => 0.0,
+        };
+        Duration::from_secs_f32(delay)
+    }
+
     fn handle_hook_state_change(&'lua self, on_hook: bool, force: bool) {
         use PhoneLineState::*;
         let state = self.state();
-        let hook_change_time = Instant::now();
-        let is_locked = self.switchhook_locked.get();
-        if !force && self.switchhook_closed.replace(on_hook) == on_hook { return }
-        self.switchhook_change_time.replace(hook_change_time);
+        let hook_change_time = self.now();
+        let is_locked = self.line().switchhook_locked.get();
+        if !force && self.line().switchhook_closed.replace(on_hook) == on_hook { return }
+        let prev_hook_change_time = self.line().switchhook_change_time.replace(hook_change_time);
         
         if !is_locked {
             self.set_line_muted(on_hook);
@@ -898,14 +2108,9 @@ impl<'lua> CursedEngine<'lua> {
 
         if on_hook {
             info!("Switchhook CLOSED");
-            match state {
-                Idle | IdleRinging => {}
-                _ => {
-                    // Only hang up the call immediately if switchhook dialing is disabled
-                    if !is_locked && !self.switchhook_dialing_enabled {
-                        self.set_state(PhoneLineState::Idle);
-                    }
-                }
+            // Hang up right away unless the switchhook could be dialing or flashing; update_state waits to tell them apart
+            if !matches!(state, Idle | IdleRinging) && !is_locked && self.hangup_delay().is_zero() {
+                self.set_state(PhoneLineState::Idle);
             }
         } else {
             info!("Switchhook OPEN");
@@ -914,6 +2119,8 @@ impl<'lua> CursedEngine<'lua> {
                 // Picking up idle phone
                 Idle => {
                     if !is_locked {
+                        // Picking up cuts off the answering machine
+                        self.finish_voicemail_recording();
                         self.set_state(PhoneLineState::DialTone);
                     }
                 },
@@ -930,9 +2137,14 @@ impl<'lua> CursedEngine<'lua> {
                     }
                 },
                 _ => {
-                    if self.switchhook_dialing_enabled {
-                        update_cell(&self.pending_pulse_count, |p| p + 1);
-                        info!("SHD pulse (n = {})", self.pending_pulse_count.get());
+                    // Tell hookflashes apart from switchhook dialing pulses by how long the switchhook was down.
+                    // A hookflash can't happen in the middle of a dialed digit.
+                    let break_duration = hook_change_time.saturating_duration_since(prev_hook_change_time);
+                    if !is_locked && self.line().pending_pulse_count.get() == 0 && self.is_hookflash_duration(break_duration) {
+                        self.handle_hookflash();
+                    } else if self.switchhook_dialing_enabled {
+                        update_cell(&self.line().pending_pulse_count, |p| p + 1);
+                        info!("SHD pulse (n = {})", self.line().pending_pulse_count.get());
                     }
                 }
             }
@@ -941,7 +2153,7 @@ impl<'lua> CursedEngine<'lua> {
 
     /// Reads and handles pending input signals from the host device.
     fn process_input_signals(&'lua self) {
-        if let Some(phone_input) = self.phone_input.borrow().as_ref() {
+        if let Some(phone_input) = self.line().phone_input.borrow().as_ref() {
             while let Ok(signal) = phone_input.try_recv() {
                 use PhoneInputSignal::*;
                 match signal {
@@ -962,7 +2174,7 @@ impl<'lua> CursedEngine<'lua> {
     /// Gets the length of time for which the current state has been active.
     #[inline]
     pub fn current_state_time(&self) -> Duration {
-        Instant::now().saturating_duration_since(*self.state_start.borrow())
+        self.now().saturating_duration_since(*self.line().state_start.borrow())
     }
 
     /// Updates the state of the engine.
@@ -970,76 +2182,107 @@ impl<'lua> CursedEngine<'lua> {
     fn update_state(&'lua self) {
         use PhoneLineState::*;
         let state = self.state();
-        let now = Instant::now();
+        let now = self.now();
 
         // Handle switchhook dialing and delayed hangups
-        if self.switchhook_dialing_enabled {
-            let time_since_last_switchhook_change = now.duration_since(self.switchhook_change_time.get());
-            if  self.switchhook_closed.get() && !matches!(self.state(), PhoneLineState::Idle | PhoneLineState::IdleRinging) {
-                // If the phone is on the hook long enough, hang up the call
-                if !self.switchhook_locked.get() && time_since_last_switchhook_change.as_secs_f32() > self.config.shd_hangup_delay {
-                    self.pending_pulse_count.replace(0);
-                    self.set_state(PhoneLineState::Idle);
-                    return
-                }
-            } else {
-                if self.rotary_resting.get() && self.pending_pulse_count.get() > 0 && time_since_last_switchhook_change.as_secs_f32() > self.config.shd_manual_pulse_interval {
-                    // Dial the digit and clear the pulse counter
-                    if let Some(digit) = self.pulses_to_digit(self.pending_pulse_count.get()) {
-                        self.handle_host_digit(digit);
-                    }
-                    self.pending_pulse_count.replace(0);
-                }
+        let time_since_last_switchhook_change = now.saturating_duration_since(self.line().switchhook_change_time.get());
+        if self.line().switchhook_closed.get() && !matches!(state, Idle | IdleRinging) {
+            // If the phone is on the hook long enough, hang up the call
+            if !self.line().switchhook_locked.get() && time_since_last_switchhook_change > self.hangup_delay() {
+                self.line().pending_pulse_count.replace(0);
+                self.set_state(PhoneLineState::Idle);
+                return
             }
+        } else if self.switchhook_dialing_enabled && self.line().rotary_resting.get() && self.line().pending_pulse_count.get() > 0 && time_since_last_switchhook_change.as_secs_f32() > self.config.shd_manual_pulse_interval {
+            // Dial the digit and clear the pulse counter
+            if let Some(digit) = self.pulses_to_digit(self.line().pending_pulse_count.get()) {
+                self.handle_host_digit(digit);
+            }
+            self.line().pending_pulse_count.replace(0);
         }
 
         match state {
+            Idle => {
+                if self.line().waiting_party.borrow().is_some() {
+                    self.recall_waiting_party();
+                }
+                let recording_time = self.line().voicemail_recording.borrow().as_ref().map(|recording| now.saturating_duration_since(recording.start));
+                if recording_time.is_some_and(|time| time.as_secs_f32() >= self.config.voicemail.max_message_length) {
+                    info!("Voicemail message reached maximum length.");
+                    self.finish_voicemail_recording();
+                }
+            },
+            IdleRinging => {
+                if self.config.caller_id.enabled && !self.line().caller_id_sent.get() {
+                    let first_ring = self.current_ring_pattern().map_or(Duration::ZERO, |pattern| pattern.first_ring_duration());
+                    if self.current_state_time().as_secs_f32() >= first_ring.as_secs_f32() + self.config.caller_id.delay {
+                        self.send_caller_id();
+                    }
+                }
+                if self.config.voicemail.enabled && self.get_other_party_agent().is_some() && self.current_state_time() >= self.voicemail_answer_delay() {
+                    self.start_voicemail_recording();
+                }
+            },
             DialTone => {
                 let state_time = self.current_state_time();
                 if state_time.as_secs_f32() >= self.config.off_hook_delay {
                     self.handle_off_hook_timeout();
                 }
             }
-            PDD => {
-                if self.pdd_time().as_secs_f32() >= self.config.pdd {
-                    if self.config.payphone.enabled {
-                        let number_to_dial = self.get_dialed_digits();
-
-                        // Figure out how much the call costs
-                        let price = match self.lookup_agent_phone_number(number_to_dial.as_str()) {
-                            Some(agent_to_call) if self.config.payphone.enable_custom_agent_rates => 
-                            match agent_to_call.custom_price() {
-                                Some(cents) => cents,
-                                None => self.config.payphone.standard_call_rate
-                            },
-                            _ => self.config.payphone.standard_call_rate
-                        };
-
-                        // If the user has deposited enough money, call the number. Otherwise, do nothing.
-                        if *self.deposit.borrow() >= price {
-                            self.call_number(number_to_dial.as_str());
-                            self.deposit_needed.replace(false);
-                        } else {
-                            self.deposit_needed.replace(true);
-                        }
-                    } else {
-                        let number_to_dial = self.get_dialed_digits();
+            PDD if self.pdd_time().as_secs_f32() >= self.config.pdd || self.is_dialed_number_complete() => {
+                if self.handle_service_code(self.get_dialed_digits().as_str()) {
+                    // Service codes are handled by the exchange and are always free
+                } else if self.handle_voicemail_access(self.get_dialed_digits().as_str()) {
+                    // Voicemail is free as well
+                } else if self.config.payphone.enabled {
+                    let number_to_dial = self.get_dialed_digits();
+
+                    // Figure out how much the call costs
+                    let price = match self.resolve_number(number_to_dial.as_str()).1 {
+                        DialDestination::Line(_) => 0,
+                        DialDestination::Agent(agent_to_call) if self.config.payphone.enable_custom_agent_rates => 
+                        match agent_to_call.custom_price() {
+                            Some(cents) => cents,
+                            None => self.config.payphone.standard_call_rate
+                        },
+                        _ => self.config.payphone.standard_call_rate
+                    };
+
+                    // If the user has deposited enough money, call the number. Otherwise, do nothing.
+                    if *self.line().deposit.borrow() >= price {
                         self.call_number(number_to_dial.as_str());
+                        self.line().deposit_needed.replace(false);
+                    } else {
+                        self.line().deposit_needed.replace(true);
                     }
+                } else {
+                    let number_to_dial = self.get_dialed_digits();
+                    self.call_number(number_to_dial.as_str());
                 }
             },
             Connected => {
+                if self.line().three_way_dialing.get() {
+                    self.update_three_way_dialing();
+                }
+                if self.line().voicemail_playback.borrow().is_some() {
+                    self.update_voicemail_playback();
+                }
+                let is_call_waiting = self.line().waiting_party.borrow().as_ref().is_some_and(|waiting_party| !waiting_party.on_hold);
+                if is_call_waiting && now.saturating_duration_since(self.line().call_waiting_tone_time.get()) >= CALL_WAITING_TONE_INTERVAL {
+                    self.play_call_waiting_tone();
+                }
                 if !self.is_current_call_free() {
                     // Wait for user-configured delay and eat coin deposit
                     if !self.initial_deposit_consumed() {
                         if self.current_state_time() >= self.deposit_consume_delay && self.consume_deposit() {
-                            self.deposit_needed.replace(false);
-                            self.deposit_consumed.replace(true);
+                            self.line().deposit_needed.replace(false);
+                            self.line().deposit_consumed.replace(true);
                         }
                     } else if !self.has_time_credit() {
                         // Cut off call if time credit runs out
                         info!("Out of time credit; ending call.");
                         self.set_state(PhoneLineState::Busy);
+                        self.update_call_record(|record| record.disconnect_cause = DisconnectCause::CreditExhausted);
                     }
                 }
             }
@@ -1052,12 +2295,20 @@ impl<'lua> CursedEngine<'lua> {
     fn update_agents(&'lua self) {
         use AgentIntent::*;
         use PhoneLineState::*;
-        let state = self.state();
+        let line_states: Vec<PhoneLineState> = self.lines.iter().map(|line| line.state()).collect();
+        for line in self.lines.iter() {
+            line.hookflash_awaited.set(false);
+        }
         let agent_modules = self.agents.borrow();
         let agent_iter = agent_modules.iter();
         for (_, agent) in agent_iter {
             if agent.suspended() { continue }
+            // Agents that aren't on a call act on the primary line
+            let line_id = self.find_agent_line(agent).unwrap_or(0);
+            self.select_line(line_id);
+            let state = line_states[line_id];
             self.reset_execution_limit();
+            self.ticking_agent.set(agent.id());
             let mut tick_result = agent.tick(AgentIntentResponse::None);
             let mut call_attempted = false;
             'agent_next_intent: loop {
@@ -1071,18 +2322,61 @@ impl<'lua> CursedEngine<'lua> {
                                     continue;
                                 }
                             },
+                            // Agent wants to know if the user flashed the switchhook
+                            ReadHookflash if self.line().is_other_party(agent) => {
+                                if self.line().pending_hookflash.replace(false) {
+                                    tick_result = agent.tick(AgentIntentResponse::Hookflash);
+                                    continue;
+                                }
+                                self.line().hookflash_awaited.set(true);
+                            },
+                            // Agent wants to bridge another agent into its call
+                            BridgeCall(destination) => {
+                                let is_on_call = self.line().is_other_party(agent) || self.line().is_conference_party(agent);
+                                let bridged = state == Connected && is_on_call && match self.lookup_agent_destination(destination) {
+                                    Some(other_agent) if other_agent.id() != agent.id() => self.bridge_agent(other_agent),
+                                    _ => false
+                                };
+                                if !bridged {
+                                    tick_result = agent.tick(AgentIntentResponse::LineBusy);
+                                    continue;
+                                }
+                            },
+                            // Agent wants to call another agent
+                            CallAgent(destination) => {
+                                let connected = match self.lookup_agent_destination(destination) {
+                                    Some(other_agent) => self.connect_agent_call(agent, &other_agent),
+                                    None => false
+                                };
+                                if !connected {
+                                    tick_result = agent.tick(AgentIntentResponse::LineBusy);
+                                    continue;
+                                }
+                            },
                             // Agent wants to call the user
-                            CallUser => {
-                                // First, check that there's nobody on the line and the user's on-hook.
-                                // Also make sure that the config allows incoming calls.
-                                if self.config.allow_incoming_calls.unwrap_or(false) 
-                                && self.state() == PhoneLineState::Idle 
-                                && self.other_party.borrow().is_none() {
+                            CallUser(ring_pattern) => {
+                                // First, find a line with nobody on it and the user on-hook.
+                                // Also make sure that the config allows incoming calls, and that the agent isn't talking to another agent.
+                                let available_line = self.find_line_for_incoming_call();
+                                let call_waiting_line = self.find_line_for_call_waiting(agent);
+                                let can_call_user = self.config.allow_incoming_calls.unwrap_or(false) && !self.is_agent_busy(agent);
+                                if let Some(line_id) = available_line.filter(|_| can_call_user) {
+                                    self.select_line(line_id);
                                     agent.set_call_reason(CallReason::AgentInit);
                                     agent.transition_state(AgentState::OutgoingCall);
-                                    self.load_other_party(Rc::clone(agent));
+                                    self.load_other_party(OtherParty::Agent(Rc::clone(agent)));
+                                    self.line().call_ring_pattern.replace(ring_pattern.clone());
                                     self.set_state(PhoneLineState::IdleRinging);
-                                    self.last_caller_id.replace(agent.id());
+                                    self.line().last_caller_id.replace(agent.id());
+                                } else if let Some(line_id) = call_waiting_line.filter(|_| can_call_user) {
+                                    // Let the user know there's another call waiting
+                                    self.select_line(line_id);
+                                    info!("Agent '{}' is waiting on line '{}'", agent.name(), self.line().name());
+                                    agent.set_call_reason(CallReason::AgentInit);
+                                    agent.transition_state(AgentState::OutgoingCall);
+                                    self.line().waiting_party.replace(Some(WaitingParty { agent: Rc::clone(agent), on_hold: false }));
+                                    self.line().last_caller_id.replace(agent.id());
+                                    self.play_call_waiting_tone();
                                 } else {
                                     // Tell the agent they're busy
                                     tick_result = agent.tick(AgentIntentResponse::LineBusy);
@@ -1100,6 +2394,16 @@ impl<'lua> CursedEngine<'lua> {
                                     self.set_state(Connected);
                                 }
                             },
+                            // Agent is done leaving a message
+                            EndCall if self.line().is_voicemail_party(agent) => {
+                                info!("Agent '{}' has finished leaving a message.", agent.name());
+                                self.finish_voicemail_recording();
+                            },
+                            // Agent wants to end current call
+                            EndCall if self.line().is_waiting_party(agent) => {
+                                info!("Agent '{}' has disconnected the waiting call.", agent.name());
+                                self.drop_waiting_party();
+                            },
                             // Agent wants to end current call
                             EndCall => {
                                 match state {
@@ -1141,10 +2445,19 @@ impl<'lua> CursedEngine<'lua> {
                             StateEnded(AgentState::Call) => {
                                 // Don't affect PBX state if the call is already ended
                                 match state {
+                                    // Agent leaving a message on the answering machine
+                                    Idle if self.line().is_voicemail_party(agent) => {
+                                        self.finish_voicemail_recording();
+                                    },
+                                    // Agents on a conference call leave it one at a time
+                                    Connected if self.line().is_conference_party(agent) => {
+                                        self.remove_conference_party(agent);
+                                    },
                                     Connected => {
-                                        // Only disconnect the call if this agent is currently on the call
+                                        // Only disconnect the call if this agent is currently on the call (and not just put on hold),
+                                        // and nobody else is left on the call
                                         if let Some(other_party) = self.get_other_party_agent() {
-                                            if other_party.id() == agent.id() {
+                                            if other_party.id() == agent.id() && !matches!(agent.state(), Ok(AgentState::Hold)) && !self.promote_conference_party() {
                                                 self.set_state(Busy);
                                             }
                                         }
@@ -1163,20 +2476,30 @@ impl<'lua> CursedEngine<'lua> {
                         }
                     }
                     Err(err) => {
-                        self.sound_engine.borrow().play_panic_tone();
+                        if self.config.debug.as_ref().and_then(|debug| debug.enable_panic_tone).unwrap_or(true) {
+                            self.line().sound_engine.borrow().play_panic_tone();
+                        }
                         error!("LUA ERROR: {}", err);
                         agent.set_suspended(true);
+                        self.schedule_agent_recovery(agent);
                         break 'agent_next_intent
                     }
                 }
             }
         }
+        self.ticking_agent.set(None);
     }
 
     /// Processes pending inputs and updates state information associated with the engine.
     pub fn tick(&'lua self) {
-        self.process_input_signals();
-        self.update_state();
+        for line_id in 0..self.lines.len() {
+            self.select_line(line_id);
+            self.process_input_signals();
+            self.update_state();
+            self.update_message_waiting();
+        }
+        self.update_agent_reloads();
+        self.update_agent_recovery();
         self.update_agents();
     }
 }
\ No newline at end of file
@@ -1,19 +1,7 @@
 use mlua::prelude::*;
-
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum AgentRole {
-    Normal = 0,
-    Intercept = 1,
-    Tollmaster = 2
-}
-
-const ALL_AGENT_ROLES: &[AgentRole] = { use AgentRole::*; &[Normal, Intercept, Tollmaster] };
-
-impl From<usize> for AgentRole {
-    fn from(value: usize) -> AgentRole {
-        ALL_AGENT_ROLES[value]
-    }
-}
+use std::sync::Arc;
+use crate::phone::{RingPattern, LuaRingPattern};
+pub use crate::common::{AgentRole, CallReason};
 
 #[derive(Copy, Clone, Debug)]
 pub enum AgentState {
@@ -24,10 +12,12 @@ pub enum AgentState {
     /// Agent is receiving a call.
     IncomingCall = 2,
     /// Agent is in a call.
-    Call = 3
+    Call = 3,
+    /// Agent is in a call that the user has put on hold.
+    Hold = 4
 }
 
-const ALL_AGENT_STATES: &[AgentState] = { use AgentState::*; &[Idle, OutgoingCall, IncomingCall, Call] };
+const ALL_AGENT_STATES: &[AgentState] = { use AgentState::*; &[Idle, OutgoingCall, IncomingCall, Call, Hold] };
 
 impl From<usize> for AgentState {
     fn from(value: usize) -> AgentState {
@@ -41,40 +31,6 @@ impl AgentState {
     }
 }
 
-/// Provides reason codes to pass to an agent when connects to a call.
-pub enum CallReason {
-    /// No call reason given.
-    None = 0,
-    /// Call was placed because of an off-hook timeout.
-    OffHook = 1,
-    /// Call was placed because the originally dialed number was disconnected.
-    NumberDisconnected = 2,
-    /// Call was placed by the user.
-    UserInit = 3,
-    /// Call was placed by an agent.
-    AgentInit = 4
-}
-
-impl From<usize> for CallReason {
-    fn from(value: usize) -> Self {
-        use CallReason::*;
-        match value {
-            0 => None,
-            1 => OffHook,
-            2 => NumberDisconnected,
-            3 => UserInit,
-            4 => AgentInit,
-            _ => None
-        }
-    }
-}
-
-impl CallReason {
-    pub fn as_index(self) -> usize {
-        self as usize
-    }
-}
-
 #[derive(Clone, Debug)]
 pub enum AgentIntent {
     /// Agent performed no action.
@@ -83,8 +39,8 @@ pub enum AgentIntent {
     AcceptCall,
     /// Agent wants to end an ongoing call.
     EndCall,
-    /// Agent wants to call the host.
-    CallUser,
+    /// Agent wants to call the host, optionally with a ring pattern for this call only.
+    CallUser(Option<Arc<RingPattern>>),
     /// Agent is waiting for an operation to complete.
     Wait,
     /// Agent is requesting a digit from the host.
@@ -95,6 +51,12 @@ pub enum AgentIntent {
     StateEnded(AgentState),
     /// (Not Implemented)
     ReadPhrase,
+    /// Agent is waiting for the host to flash the switchhook.
+    ReadHookflash,
+    /// Agent wants to bridge the agent with a specified phone number or agent handle into its call.
+    BridgeCall(String),
+    /// Agent wants to call the agent with a specified phone number or agent handle, without involving the host.
+    CallAgent(String),
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -109,7 +71,11 @@ impl AgentIntent {
             0 => AgentIntent::Yield,
             1 => AgentIntent::AcceptCall,
             2 => AgentIntent::EndCall,
-            3 => AgentIntent::CallUser,
+            3 => match intent_data {
+                LuaValue::String(expr) => AgentIntent::CallUser(expr.to_str().ok().and_then(RingPattern::try_parse).map(Arc::new)),
+                LuaValue::UserData(userdata) => AgentIntent::CallUser(userdata.borrow::<LuaRingPattern>().ok().map(|pattern| Arc::clone(&pattern.0))),
+                _ => AgentIntent::CallUser(None)
+            },
             4 => AgentIntent::Wait,
             5 => AgentIntent::ReadDigit,
             6 => match intent_data {
@@ -120,6 +86,15 @@ impl AgentIntent {
                 LuaValue::Integer(n) => AgentIntent::StateEnded(AgentState::from(n as usize)),
                 _ => AgentIntent::Yield
             },
+            8 => AgentIntent::ReadHookflash,
+            9 => match intent_data {
+                LuaValue::String(s) => AgentIntent::BridgeCall(String::from(s.to_str().unwrap())),
+                _ => AgentIntent::Yield
+            },
+            10 => match intent_data {
+                LuaValue::String(s) => AgentIntent::CallAgent(String::from(s.to_str().unwrap())),
+                _ => AgentIntent::Yield
+            },
             _ => AgentIntent::Yield
         }
     }
@@ -129,7 +104,8 @@ impl AgentIntent {
 pub enum AgentIntentResponse {
     None,
     Digit(char),
-    LineBusy
+    LineBusy,
+    Hookflash
 }
 
 impl AgentIntentResponse {
@@ -137,7 +113,8 @@ impl AgentIntentResponse {
         match self {
             AgentIntentResponse::None => 0,
             AgentIntentResponse::Digit(_) => 1,
-            AgentIntentResponse::LineBusy => 2
+            AgentIntentResponse::LineBusy => 2,
+            AgentIntentResponse::Hookflash => 4
         }
     }
 }
\ No newline at end of file
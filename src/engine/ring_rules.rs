@@ -0,0 +1,90 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use chrono::prelude::*;
+use cron::Schedule;
+use log::{info, error};
+use crate::config::RingRuleConfig;
+use crate::phone::RingPattern;
+use super::{AgentRole, CallReason};
+
+/// The caller of an incoming call, as seen by ring rules.
+#[derive(Default)]
+pub struct RingCaller<'a> {
+    /// Name of the calling agent, or `None` if the caller isn't an agent.
+    pub agent: Option<&'a str>,
+    pub role: Option<AgentRole>,
+    pub reason: Option<CallReason>,
+}
+
+/// A rule that picks the ring pattern for incoming calls that meet all of its conditions.
+pub struct RingRule {
+    agent: Option<String>,
+    role: Option<AgentRole>,
+    /// Times at which the rule applies.
+    schedule: Option<Schedule>,
+    reason: Option<CallReason>,
+    pattern: Arc<RingPattern>,
+}
+
+impl RingRule {
+    pub fn from_config(config: &RingRuleConfig) -> Result<Self, String> {
+        let pattern = match RingPattern::try_parse(config.pattern.as_str()) {
+            Some(pattern) => Arc::new(pattern),
+            None => return Err(format!("invalid ring pattern '{}'", config.pattern))
+        };
+        let schedule = match config.schedule.as_deref() {
+            Some(expr) => Some(Schedule::from_str(expr).map_err(|err| format!("invalid schedule '{}': {}", expr, err))?),
+            None => None
+        };
+        Ok(Self {
+            agent: config.agent.clone(),
+            role: config.role,
+            schedule,
+            reason: config.reason,
+            pattern,
+        })
+    }
+
+    fn matches(&self, caller: &RingCaller, now: DateTime<Local>) -> bool {
+        if let Some(agent) = &self.agent {
+            if caller.agent != Some(agent.as_str()) { return false }
+        }
+        if self.role.is_some() && caller.role != self.role { return false }
+        if self.reason.is_some() && caller.reason != self.reason { return false }
+        match &self.schedule {
+            Some(schedule) => schedule.includes(now),
+            None => true
+        }
+    }
+}
+
+/// An ordered list of rules that pick ring patterns for incoming calls.
+///
+/// The first matching rule wins. Calls that no rule matches ring with the caller's custom pattern or the line's default pattern.
+#[derive(Default)]
+pub struct RingRules {
+    rules: Vec<RingRule>,
+}
+
+impl RingRules {
+    pub fn from_config(rules: &[RingRuleConfig]) -> Self {
+        let mut ring_rules = Self::default();
+        for rule in rules {
+            match RingRule::from_config(rule) {
+                Ok(rule) => ring_rules.rules.push(rule),
+                Err(err) => error!("Ignoring invalid ring rule: {}", err)
+            }
+        }
+        if !ring_rules.rules.is_empty() {
+            info!("Loaded {} ring rule(s).", ring_rules.rules.len());
+        }
+        ring_rules
+    }
+
+    /// Gets the ring pattern of the first rule matching the specified caller at the specified time.
+    pub fn select(&self, caller: &RingCaller, now: DateTime<Local>) -> Option<Arc<RingPattern>> {
+        self.rules.iter()
+            .find(|rule| rule.matches(caller, now))
+            .map(|rule| Arc::clone(&rule.pattern))
+    }
+}
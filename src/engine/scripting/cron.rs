@@ -8,14 +8,16 @@ use crate::engine::*;
 struct LuaCronSchedule {
     next_time: Rc<Cell<Option<DateTime<Local>>>>,
     cron: Schedule,
+    clock: Arc<dyn Clock>,
 }
 
 impl LuaCronSchedule {
-    fn new(expr: &str) -> Option<Self> {
+    fn new(expr: &str, clock: &Arc<dyn Clock>) -> Option<Self> {
         if let Ok(cron) = Schedule::from_str(expr) {
             Some(Self {
-                next_time: Rc::new(Cell::new(cron.after_owned(Local::now()).next())),
-                cron
+                next_time: Rc::new(Cell::new(cron.after_owned(clock.local_now()).next())),
+                cron,
+                clock: Arc::clone(clock),
             })
         } else {
             None
@@ -24,7 +26,7 @@ impl LuaCronSchedule {
 
     fn tick(&self) -> Option<bool> {
         if self.next_time.get().is_none() { return None }
-        let now = Local::now();
+        let now = self.clock.local_now();
         if let Some(next_time) = self.next_time.get() {
             if next_time <= now {
                 self.next_time.set(self.cron.after(&now).next());
@@ -52,8 +54,8 @@ impl<'lua> CursedEngine<'lua> {
         let lua = &self.lua;
         let globals = &lua.globals();
 
-        globals.set("CronSchedule", lua.create_function(|_, expr: String| {
-            Ok(LuaCronSchedule::new(expr.as_str()))
+        globals.set("CronSchedule", lua.create_function(move |_, expr: String| {
+            Ok(LuaCronSchedule::new(expr.as_str(), &self.clock))
         })?)?;
 
         Ok(())
@@ -11,6 +11,7 @@ mod phone;
 mod logging;
 mod random;
 mod sound;
+mod store;
 mod toll;
 
 #[derive(Debug)]
@@ -61,7 +62,7 @@ impl<'lua> CursedEngine<'lua> {
         // Global engine functions
         
         globals.set("engine_time", lua.create_function(move |_, ()| {
-            let run_time = self.start_time.elapsed().as_secs_f64();
+            let run_time = self.engine_time().as_secs_f64();
             Ok(run_time)
         })?)?;
 
@@ -76,12 +77,12 @@ impl<'lua> CursedEngine<'lua> {
         
         // set_agent_sounds_loaded(agent_id, loaded)
         globals.set("set_agent_sounds_loaded", lua.create_function(move |_, (agent_id, load): (AgentId, bool)| {
-            let sound_engine = &self.sound_engine;
+            let sound_library = &self.sound_library;
             if let Some(agent) = self.lookup_agent_id(agent_id) {
                 if load {
-                    agent.load_sound_banks(sound_engine)
+                    agent.load_sound_banks(sound_library)
                 } else {
-                    agent.unload_sound_banks(sound_engine)
+                    agent.unload_sound_banks(sound_library)
                 }
             }
             Ok(())
@@ -120,6 +121,7 @@ impl<'lua> CursedEngine<'lua> {
         self.load_lua_phone_lib()?;
         self.load_lua_sound_lib()?;
         self.load_lua_toll_lib()?;
+        self.load_lua_store_lib()?;
         self.load_lua_log_lib()?;
 
         // Run API scripts
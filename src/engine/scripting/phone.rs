@@ -8,11 +8,56 @@ impl<'lua> CursedEngine<'lua> {
         let tbl_phone = lua.create_table()?;
 
         tbl_phone.set("last_caller_id", lua.create_function(move |_, ()| {
-            Ok(self.last_caller_id.get())
+            Ok(self.line().last_caller_id.get())
         })?)?;
 
         tbl_phone.set("last_dialed_number", lua.create_function(move |_, ()| {
-            Ok(self.last_dialed_number.borrow().clone())
+            Ok(self.line().last_dialed_number.borrow().clone())
+        })?)?;
+
+        tbl_phone.set("line_name", lua.create_function(move |_, ()| {
+            Ok(self.line().name().to_owned())
+        })?)?;
+
+        tbl_phone.set("line_extension", lua.create_function(move |_, ()| {
+            Ok(self.line().extension().map(|extension| extension.to_owned()))
+        })?)?;
+
+        tbl_phone.set("line_count", lua.create_function(move |_, ()| {
+            Ok(self.lines().len())
+        })?)?;
+
+        tbl_phone.set("call_history", lua.create_function(move |lua, count: Option<usize>| {
+            let now = self.clock.local_now().timestamp();
+            let call_history = self.call_history.borrow();
+            let entries = lua.create_table()?;
+            for (i, entry) in call_history.recent().take(count.unwrap_or(usize::MAX)).enumerate() {
+                let tbl_entry = lua.create_table()?;
+                tbl_entry.set("line", entry.line.as_str())?;
+                tbl_entry.set("direction", if entry.outgoing { "outgoing" } else { "incoming" })?;
+                tbl_entry.set("agent", entry.agent.as_deref())?;
+                tbl_entry.set("other_line", entry.other_line.as_deref())?;
+                tbl_entry.set("dialed", entry.dialed.as_deref())?;
+                tbl_entry.set("time", entry.start_time)?;
+                tbl_entry.set("age", now.saturating_sub(entry.end_time).max(0))?;
+                tbl_entry.set("answered", entry.answered)?;
+                tbl_entry.set("duration", entry.duration)?;
+                entries.raw_set(i + 1, tbl_entry)?;
+            }
+            Ok(entries)
+        })?)?;
+
+        tbl_phone.set("agent_call_stats", lua.create_function(move |lua, agent_name: String| {
+            let now = self.clock.local_now().timestamp();
+            let stats = self.call_history.borrow().agent_stats(agent_name.as_str());
+            let tbl_stats = lua.create_table()?;
+            tbl_stats.set("times_called", stats.times_called)?;
+            tbl_stats.set("calls_placed", stats.calls_placed)?;
+            tbl_stats.set("missed_calls", stats.missed_calls)?;
+            tbl_stats.set("answered_calls", stats.answered_calls)?;
+            tbl_stats.set("last_call_time", stats.last_call_time)?;
+            tbl_stats.set("last_call_age", stats.last_call_time.map(|time| now.saturating_sub(time).max(0)))?;
+            Ok(tbl_stats)
         })?)?;
 
         tbl_phone.set("dial", lua.create_function(move |_, digits: String| {
@@ -23,19 +68,19 @@ impl<'lua> CursedEngine<'lua> {
         })?)?;
 
         tbl_phone.set("is_rotary", lua.create_function(move |_, ()| {
-            Ok(self.config.rotary.enabled)
+            Ok(self.line().config.rotary.enabled)
         })?)?;
 
         tbl_phone.set("is_rotary_dial_resting", lua.create_function(move |_, ()| {
-            Ok(if self.config.rotary.enabled {
-                Some(self.rotary_resting.get())
+            Ok(if self.line().config.rotary.enabled {
+                Some(self.line().rotary_resting.get())
             } else {
                 None
             })
         })?)?;
 
         tbl_phone.set("is_on_hook", lua.create_function(move |_, ()| {
-            Ok(self.switchhook_closed.get())
+            Ok(self.line().switchhook_closed.get())
         })?)?;
 
         tbl_phone.set("ring", lua.create_function(move |_, pattern: LuaValue| {
@@ -65,7 +110,90 @@ impl<'lua> CursedEngine<'lua> {
         })?)?;
 
         tbl_phone.set("call_dialed_number", lua.create_function(move |_, ()| {
-            return Ok(self.called_number.borrow().clone())
+            return Ok(self.line().called_number.borrow().clone())
+        })?)?;
+
+        tbl_phone.set("add_dial_rule", lua.create_function(move |_, rule_table: LuaTable| {
+            let rule_config = DialRuleConfig {
+                pattern: rule_table.get("pattern")?,
+                strip: rule_table.get::<_, Option<usize>>("strip")?.unwrap_or_default(),
+                prefix: rule_table.get("prefix")?,
+                agent: rule_table.get("agent")?,
+                complete: rule_table.get::<_, Option<bool>>("complete")?.unwrap_or_default(),
+            };
+            match DialRule::from_config(&rule_config) {
+                Ok(rule) => self.dial_plan.borrow_mut().add_rule(rule),
+                Err(err) => return Err(LuaError::RuntimeError(format!("invalid dial rule: {}", err)))
+            }
+            Ok(())
+        })?)?;
+
+        tbl_phone.set("remove_dial_rule", lua.create_function(move |_, pattern: String| {
+            Ok(self.dial_plan.borrow_mut().remove_rule(pattern.as_str()))
+        })?)?;
+
+        tbl_phone.set("register_service_code", lua.create_function(move |_, (code, handler): (String, LuaValue)| {
+            let handler = match handler {
+                LuaValue::Function(f) => VscHandler::Function(f),
+                LuaValue::String(name) => match VscHandler::parse(name.to_str()?) {
+                    Ok(handler) => handler,
+                    Err(err) => return Err(LuaError::RuntimeError(err))
+                },
+                other => return Err(LuaError::RuntimeError(format!("cannot use type '{}' as service code handler", other.type_name())))
+            };
+            self.service_codes.borrow_mut().register(code.as_str(), handler);
+            Ok(())
+        })?)?;
+
+        tbl_phone.set("unregister_service_code", lua.create_function(move |_, code: String| {
+            Ok(self.service_codes.borrow_mut().unregister(code.as_str()))
+        })?)?;
+
+        tbl_phone.set("call_parties", lua.create_function(move |_, ()| {
+            let line = self.line();
+            let mut names = vec![];
+            if let Some(name) = line.other_party_name() {
+                names.push(name);
+            }
+            names.extend(line.conference.borrow().iter().map(|party| party.agent.name().to_owned()));
+            Ok(names)
+        })?)?;
+
+        tbl_phone.set("agent_call_partner", lua.create_function(move |_, agent_name: String| {
+            let partner_id = self.lookup_agent_name(agent_name.as_str())
+                .and_then(|agent| agent.id())
+                .and_then(|agent_id| self.agent_calls.borrow().partner(agent_id));
+            Ok(partner_id.and_then(|partner_id| self.lookup_agent_id(partner_id)).map(|partner| partner.name().to_owned()))
+        })?)?;
+
+        tbl_phone.set("end_agent_call", lua.create_function(move |_, agent_name: String| {
+            Ok(match self.lookup_agent_name(agent_name.as_str()) {
+                Some(agent) => self.disconnect_agent_call(&agent),
+                None => false
+            })
+        })?)?;
+
+        tbl_phone.set("voicemail_count", lua.create_function(move |_, ()| {
+            Ok(self.voicemail.borrow().len())
+        })?)?;
+
+        tbl_phone.set("set_message_waiting", lua.create_function(move |_, message_waiting: bool| {
+            self.line().message_waiting.set(message_waiting);
+            self.update_message_waiting();
+            Ok(())
+        })?)?;
+
+        tbl_phone.set("is_message_waiting", lua.create_function(move |_, ()| {
+            Ok(self.is_message_waiting())
+        })?)?;
+
+        tbl_phone.set("call_forward_number", lua.create_function(move |_, ()| {
+            Ok(self.line().call_forward_number.borrow().clone())
+        })?)?;
+
+        tbl_phone.set("set_call_forward_number", lua.create_function(move |_, number: Option<String>| {
+            self.line().call_forward_number.replace(number.filter(|number| !number.is_empty()));
+            Ok(())
         })?)?;
 
         tbl_phone.set("set_switchhook_locked", lua.create_function(move |_, is_locked: bool| {
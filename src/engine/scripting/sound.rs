@@ -28,22 +28,28 @@ impl<'lua> CursedEngine<'lua> {
                 delay = opts_table.get::<&str, f32>("delay").ok().map(|secs| Duration::from_secs_f32(secs));
                 fadein = opts_table.get::<&str, f32>("fadein").ok().map(|secs| Duration::from_secs_f32(secs));
             }
-            let info = self.sound_engine.borrow().play(
+            let channel = self.agent_channel(Channel::from(channel));
+            let opts = SoundPlayOptions {
+                looping: looping.unwrap_or(false),
+                speed: speed.unwrap_or(1.0),
+                volume: volume.unwrap_or(1.0),
+                skip: skip.unwrap_or_default(),
+                take,
+                delay,
+                fadein: fadein.unwrap_or_default(),
+            };
+            let info = self.line().sound_engine.borrow().play(
                 path.as_str(), 
-                Channel::from(channel), 
+                channel, 
                 false, 
                 interrupt.unwrap_or(true),
-                SoundPlayOptions {
-                    looping: looping.unwrap_or(false),
-                    speed: speed.unwrap_or(1.0),
-                    volume: volume.unwrap_or(1.0),
-                    skip: skip.unwrap_or_default(),
-                    take,
-                    delay,
-                    fadein: fadein.unwrap_or_default(),
-                }
+                opts
             );
 
+            if let Some(info) = &info {
+                self.record_voicemail_event(|at| VoicemailEvent::play(at, channel, info.key.as_str(), &opts));
+            }
+
             Ok(match info {
                 Some(info) => (true, info.duration.map(|d| {
                     if let Some(take) = take {
@@ -59,121 +65,188 @@ impl<'lua> CursedEngine<'lua> {
     
         // sound.is_busy(channel)
         tbl_sound.set("is_busy", lua.create_function(move |_, channel: usize| {
-            let busy = self.sound_engine.borrow().channel_busy(Channel::from(channel));
+            let busy = self.line().sound_engine.borrow().channel_busy(self.agent_channel(Channel::from(channel)));
             Ok(busy)
         })?)?;
     
         // sound.stop(channel)
         tbl_sound.set("stop", lua.create_function(move |_, channel: usize| {
-            self.sound_engine.borrow().stop(Channel::from(channel));
+            let channel = self.agent_channel(Channel::from(channel));
+            self.line().sound_engine.borrow().stop(channel);
+            self.record_voicemail_event(|at| VoicemailEvent::stop(at, channel));
             Ok(())
         })?)?;
     
         // sound.stop_all()
         tbl_sound.set("stop_all", lua.create_function(move |_, ()| {
-            self.sound_engine.borrow().stop_all();
+            // Agents on a conference call can only stop their own channels
+            match self.ticking_agent.get().and_then(|agent_id| self.line().conference_slot(agent_id)) {
+                Some(slot) => self.stop_conference_slot(slot),
+                None => self.line().sound_engine.borrow().stop_all()
+            }
+            for channel in PHONE_CHANNELS.iter().chain(BG_CHANNELS) {
+                self.record_voicemail_event(|at| VoicemailEvent::stop(at, *channel));
+            }
             Ok(())
         })?)?;
     
         // sound.get_channel_volume(channel)
         tbl_sound.set("get_channel_volume", lua.create_function(move |_, channel: usize| {
-            let vol = self.sound_engine.borrow().channel_volume(Channel::from(channel));
+            let vol = self.line().sound_engine.borrow().channel_volume(self.agent_channel(Channel::from(channel)));
             Ok(vol)
         })?)?;
     
         // sound.set_channel_volume(channel, volume)
         tbl_sound.set("set_channel_volume", lua.create_function(move |_, (channel, volume): (usize, f32)| {
-            self.sound_engine.borrow_mut().set_channel_volume(Channel::from(channel), volume);
+            self.line().sound_engine.borrow_mut().set_channel_volume(self.agent_channel(Channel::from(channel)), volume);
             Ok(())
         })?)?;
 
         // sound.get_master_volume()
         tbl_sound.set("get_master_volume", lua.create_function(move |_, ()| {
-            Ok(self.sound_engine.borrow_mut().master_volume())
+            Ok(self.line().sound_engine.borrow_mut().master_volume())
         })?)?;
 
         // sound.set_master_volume(volume)
         tbl_sound.set("set_master_volume", lua.create_function(move |_, volume: f32| {
-            self.sound_engine.borrow_mut().set_master_volume(volume);
+            self.line().sound_engine.borrow_mut().set_master_volume(volume);
             Ok(())
         })?)?;
 
         // sound.get_channel_fade_volume(channel)
         tbl_sound.set("get_channel_fade_volume", lua.create_function(move |_, channel: usize| {
-            let vol = self.sound_engine.borrow().channel_fade_volume(Channel::from(channel));
+            let vol = self.line().sound_engine.borrow().channel_fade_volume(self.agent_channel(Channel::from(channel)));
             Ok(vol)
         })?)?;
     
         // sound.set_channel_fade_volume(channel, volume)
         tbl_sound.set("set_channel_fade_volume", lua.create_function(move |_, (channel, volume): (usize, f32)| {
-            self.sound_engine.borrow_mut().set_channel_fade_volume(Channel::from(channel), volume);
+            self.line().sound_engine.borrow_mut().set_channel_fade_volume(self.agent_channel(Channel::from(channel)), volume);
             Ok(())
         })?)?;
         
         // sound.get_channel_speed(channel)
         tbl_sound.set("get_channel_speed", lua.create_function(move |_, channel: usize| {
-            let vol = self.sound_engine.borrow().channel_speed(Channel::from(channel));
+            let vol = self.line().sound_engine.borrow().channel_speed(self.agent_channel(Channel::from(channel)));
             Ok(vol)
         })?)?;
     
         // sound.set_channel_speed(channel, speed)
         tbl_sound.set("set_channel_speed", lua.create_function(move |_, (channel, speed): (usize, f32)| {
-            self.sound_engine.borrow_mut().set_channel_speed(Channel::from(channel), speed);
+            self.line().sound_engine.borrow_mut().set_channel_speed(self.agent_channel(Channel::from(channel)), speed);
+            Ok(())
+        })?)?;
+
+        // sound.set_channel_filter(channel, filter)
+        tbl_sound.set("set_channel_filter", lua.create_function(move |_, (channel, filter): (usize, LuaValue)| {
+            let channel = self.agent_channel(Channel::from(channel));
+            let sound_engine = self.line().sound_engine.borrow();
+            let filter = match filter {
+                LuaValue::Nil | LuaValue::Boolean(false) => None,
+                LuaValue::Boolean(true) => Some(sound_engine.line_filter_config().clone()),
+                LuaValue::Table(opts) => Some(line_filter_from_table(sound_engine.line_filter_config(), &opts)?),
+                _ => lua_error!("filter must be a boolean or a table of filter settings")
+            };
+            sound_engine.set_channel_filter(channel, filter);
+            Ok(())
+        })?)?;
+
+        // sound.set_channel_effect(channel, effect, params)
+        tbl_sound.set("set_channel_effect", lua.create_function(move |_, (channel, effect, params): (usize, String, LuaValue)| {
+            let channel = self.agent_channel(Channel::from(channel));
+            let kind = effect_kind(effect.as_str())?;
+            let (enabled, params) = match params {
+                LuaValue::Boolean(enabled) => (enabled, vec![]),
+                LuaValue::Nil => (true, vec![]),
+                LuaValue::Table(params) => (true, params.pairs::<String, f32>().collect::<LuaResult<Vec<_>>>()?),
+                _ => lua_error!("params must be a boolean or a table of effect parameters")
+            };
+            // Parameters not in the table keep their current values
+            self.line().sound_engine.borrow().update_channel_effects(channel, |effects| {
+                if enabled {
+                    effects.set_params(kind, &params)
+                } else {
+                    effects.set_enabled(kind, false);
+                    Ok(())
+                }
+            }).map_err(LuaError::external)
+        })?)?;
+
+        // sound.get_channel_effect_param(channel, effect, param)
+        tbl_sound.set("get_channel_effect_param", lua.create_function(move |_, (channel, effect, param): (usize, String, String)| {
+            let channel = self.agent_channel(Channel::from(channel));
+            let kind = effect_kind(effect.as_str())?;
+            let effects = self.line().sound_engine.borrow().channel_effects(channel);
+            effects.param(kind, param.as_str()).map_err(LuaError::external)
+        })?)?;
+
+        // sound.set_channel_effect_param(channel, effect, param, value)
+        tbl_sound.set("set_channel_effect_param", lua.create_function(move |_, (channel, effect, param, value): (usize, String, String, f32)| {
+            let channel = self.agent_channel(Channel::from(channel));
+            let kind = effect_kind(effect.as_str())?;
+            self.line().sound_engine.borrow()
+                .update_channel_effects(channel, |effects| effects.set_param(kind, param.as_str(), value))
+                .map_err(LuaError::external)
+        })?)?;
+
+        // sound.clear_channel_effects(channel)
+        tbl_sound.set("clear_channel_effects", lua.create_function(move |_, channel: usize| {
+            self.line().sound_engine.borrow().clear_channel_effects(self.agent_channel(Channel::from(channel)));
             Ok(())
         })?)?;
 
         // sound.is_channel_muted(channel)
         tbl_sound.set("is_channel_muted", lua.create_function(move |_, channel: usize| {
-            Ok(self.sound_engine.borrow().is_muted(Channel::from(channel)))
+            Ok(self.line().sound_engine.borrow().is_muted(self.agent_channel(Channel::from(channel))))
         })?)?;
 
         // sound.set_channel_muted(channel, muted)
         tbl_sound.set("set_channel_muted", lua.create_function(move |_, (channel, muted): (usize, bool)| {
-            self.sound_engine.borrow_mut().set_muted(Channel::from(channel), muted);
+            self.line().sound_engine.borrow_mut().set_muted(self.agent_channel(Channel::from(channel)), muted);
             Ok(())
         })?)?;
     
         // sound.play_dial_tone()
         tbl_sound.set("play_dial_tone", lua.create_function(move |_, ()| {
-            self.sound_engine.borrow().play_dial_tone();
+            self.line().sound_engine.borrow().play_dial_tone();
             Ok(())
         })?)?;
     
         // sound.play_busy_tone()
         tbl_sound.set("play_busy_tone", lua.create_function(move |_, ()| {
-            self.sound_engine.borrow().play_busy_tone();
+            self.line().sound_engine.borrow().play_busy_tone();
             Ok(())
         })?)?;
     
         // sound.play_fast_busy_tone()
         tbl_sound.set("play_fast_busy_tone", lua.create_function(move |_, ()| {
-            self.sound_engine.borrow().play_fast_busy_tone();
+            self.line().sound_engine.borrow().play_fast_busy_tone();
             Ok(())
         })?)?;
     
         // sound.play_ringback_tone()
         tbl_sound.set("play_ringback_tone", lua.create_function(move |_, ()| {
-            self.sound_engine.borrow().play_ringback_tone();
+            self.line().sound_engine.borrow().play_ringback_tone();
             Ok(())
         })?)?;
 
         // sound.play_off_hook_tone()
         tbl_sound.set("play_off_hook_tone", lua.create_function(move |_, ()| {
-            self.sound_engine.borrow().play_off_hook_tone();
+            self.line().sound_engine.borrow().play_off_hook_tone();
             Ok(())
         })?)?;
 
         // sound.play_special_info_tone(sit_type)
         tbl_sound.set("play_special_info_tone", lua.create_function(move |_, sit_type: u8| {
             let sit = SpecialInfoTone::from(sit_type);
-            self.sound_engine.borrow().play_special_info_tone(sit);
+            self.line().sound_engine.borrow().play_special_info_tone(sit);
             Ok(())
         })?)?;
     
         // sound.play_dtmf_digit(digit, duration, volume)
         tbl_sound.set("play_dtmf_digit", lua.create_function(move |_, (digit_str, duration, volume): (String, f64, f32)| {
             if let Some(digit) = digit_str.chars().next() {
-                self.sound_engine.borrow().play_dtmf(digit, Duration::from_secs_f64(duration), volume);
+                self.line().sound_engine.borrow().play_dtmf(digit, Duration::from_secs_f64(duration), volume);
             } else {
                 lua_error!("digit string is empty");
             }
@@ -184,4 +257,32 @@ impl<'lua> CursedEngine<'lua> {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+/// Creates line filter settings from a Lua table, using the configured settings for any that the table leaves out.
+fn line_filter_from_table(defaults: &LineFilterConfig, opts: &LuaTable) -> LuaResult<LineFilterConfig> {
+    let mut filter = defaults.clone();
+    if let Some(band_pass) = opts.get("band_pass")? { filter.band_pass = band_pass }
+    if let Some(low_cutoff) = opts.get("low_cutoff")? { filter.low_cutoff = low_cutoff }
+    if let Some(high_cutoff) = opts.get("high_cutoff")? { filter.high_cutoff = high_cutoff }
+    if let Some(companding) = opts.get::<_, Option<String>>("companding")? {
+        filter.companding = match companding.as_str() {
+            "mu-law" => Companding::MuLaw,
+            "a-law" => Companding::ALaw,
+            "none" => Companding::None,
+            _ => lua_error!("unknown companding law '{}'", companding)
+        };
+    }
+    if let Some(soft_clip) = opts.get("soft_clip")? { filter.soft_clip = soft_clip }
+    if let Some(drive) = opts.get("drive")? { filter.drive = drive }
+    if let Some(hum_gain) = opts.get("hum_gain")? { filter.hum_gain = Some(hum_gain) }
+    if let Some(hum_frequency) = opts.get("hum_frequency")? { filter.hum_frequency = hum_frequency }
+    if let Some(crackle) = opts.get("crackle")? { filter.crackle = crackle }
+    Ok(filter)
+}
+
+fn effect_kind(name: &str) -> LuaResult<EffectKind> {
+    match EffectKind::from_name(name) {
+        Some(kind) => Ok(kind),
+        None => lua_error!("unknown effect '{}'", name)
+    }
+}
@@ -0,0 +1,87 @@
+use crate::engine::*;
+use super::lua_error;
+
+impl<'lua> CursedEngine<'lua> {
+    pub(super) fn load_lua_store_lib(&'static self) -> LuaResult<()> {
+        let lua = &self.lua;
+        let globals = &lua.globals();
+
+        let tbl_store = lua.create_table()?;
+
+        // store.get(namespace, key)
+        tbl_store.set("get", lua.create_function(move |lua, (namespace, key): (String, String)| {
+            let mut store = self.agent_store.borrow_mut();
+            match store.namespace(lua, namespace.as_str()) {
+                Ok(values) => match values.get(key.as_str()) {
+                    Some(value) => value.to_lua_value(lua),
+                    None => Ok(LuaValue::Nil)
+                },
+                Err(err) => lua_error!("{}", err)
+            }
+        })?)?;
+
+        // store.set(namespace, key, value)
+        tbl_store.set("set", lua.create_function(move |lua, (namespace, key, value): (String, String, LuaValue)| {
+            let value = match value {
+                LuaValue::Nil => None,
+                value => match StoreValue::from_lua_value(value, 0) {
+                    Ok(value) => Some(value),
+                    Err(err) => lua_error!("can't store '{}': {}", key, err)
+                }
+            };
+            let mut store = self.agent_store.borrow_mut();
+            match store.namespace(lua, namespace.as_str()) {
+                Ok(values) => match value {
+                    Some(value) => { values.insert(key, value); },
+                    None => { values.remove(key.as_str()); }
+                },
+                Err(err) => lua_error!("{}", err)
+            }
+            if let Err(err) = store.save(namespace.as_str()) {
+                lua_error!("{}", err)
+            }
+            Ok(())
+        })?)?;
+
+        // store.delete(namespace, key)
+        tbl_store.set("delete", lua.create_function(move |lua, (namespace, key): (String, String)| {
+            let mut store = self.agent_store.borrow_mut();
+            let removed = match store.namespace(lua, namespace.as_str()) {
+                Ok(values) => values.remove(key.as_str()).is_some(),
+                Err(err) => lua_error!("{}", err)
+            };
+            if removed {
+                if let Err(err) = store.save(namespace.as_str()) {
+                    lua_error!("{}", err)
+                }
+            }
+            Ok(removed)
+        })?)?;
+
+        // store.keys(namespace)
+        tbl_store.set("keys", lua.create_function(move |lua, namespace: String| {
+            let mut store = self.agent_store.borrow_mut();
+            match store.namespace(lua, namespace.as_str()) {
+                Ok(values) => lua.create_sequence_from(values.keys().cloned()),
+                Err(err) => lua_error!("{}", err)
+            }
+        })?)?;
+
+        // store.clear(namespace)
+        tbl_store.set("clear", lua.create_function(move |lua, namespace: String| {
+            let mut store = self.agent_store.borrow_mut();
+            match store.namespace(lua, namespace.as_str()) {
+                Ok(values) => values.clear(),
+                Err(err) => lua_error!("{}", err)
+            }
+            if let Err(err) = store.save(namespace.as_str()) {
+                lua_error!("{}", err)
+            }
+            Ok(())
+        })?)?;
+
+        globals.set("store", tbl_store)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use mlua::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+use log::{info, warn, error};
+
+/// Maximum nesting depth of stored tables. Also stops cyclic tables from being stored.
+const MAX_STORE_DEPTH: usize = 32;
+
+/// A table key that can be stored.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StoreKey {
+    Integer(i64),
+    String(Vec<u8>),
+}
+
+/// A Lua value that can be stored.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StoreValue {
+    Boolean(bool),
+    Integer(i64),
+    Number(f64),
+    String(Vec<u8>),
+    Table(BTreeMap<StoreKey, StoreValue>),
+}
+
+impl StoreValue {
+    /// Copies a Lua value into a storable value.
+    pub fn from_lua_value(value: LuaValue, depth: usize) -> Result<Self, String> {
+        Ok(match value {
+            LuaValue::Boolean(b) => Self::Boolean(b),
+            LuaValue::Integer(n) => Self::Integer(n),
+            LuaValue::Number(n) => Self::Number(n),
+            LuaValue::String(s) => Self::String(s.as_bytes().to_vec()),
+            LuaValue::Table(table) => {
+                if depth >= MAX_STORE_DEPTH {
+                    return Err("table is nested too deeply or contains a cycle".to_owned())
+                }
+                let mut entries = BTreeMap::new();
+                for pair in table.pairs::<LuaValue, LuaValue>() {
+                    let (key, value) = pair.map_err(|err| err.to_string())?;
+                    let key = match key {
+                        LuaValue::Integer(n) => StoreKey::Integer(n),
+                        LuaValue::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => StoreKey::Integer(n as i64),
+                        LuaValue::String(s) => StoreKey::String(s.as_bytes().to_vec()),
+                        other => return Err(format!("unsupported table key type: {}", other.type_name()))
+                    };
+                    entries.insert(key, Self::from_lua_value(value, depth + 1)?);
+                }
+                Self::Table(entries)
+            },
+            other => return Err(format!("unsupported value type: {}", other.type_name()))
+        })
+    }
+
+    /// Creates a new Lua value from the stored value.
+    pub fn to_lua_value<'lua>(&self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        Ok(match self {
+            Self::Boolean(b) => LuaValue::Boolean(*b),
+            Self::Integer(n) => LuaValue::Integer(*n),
+            Self::Number(n) => LuaValue::Number(*n),
+            Self::String(s) => LuaValue::String(lua.create_string(s)?),
+            Self::Table(entries) => {
+                let table = lua.create_table()?;
+                for (key, value) in entries {
+                    let value = value.to_lua_value(lua)?;
+                    match key {
+                        StoreKey::Integer(n) => table.raw_set(*n, value)?,
+                        StoreKey::String(s) => table.raw_set(lua.create_string(s)?, value)?,
+                    }
+                }
+                LuaValue::Table(table)
+            }
+        })
+    }
+
+    /// Writes the value as a Lua expression.
+    fn write_lua(&self, out: &mut String, indent: usize) {
+        match self {
+            Self::Boolean(b) => write!(out, "{}", b).unwrap(),
+            Self::Integer(n) => write!(out, "{}", n).unwrap(),
+            Self::Number(n) if n.is_nan() => out.push_str("0/0"),
+            Self::Number(n) if n.is_infinite() => out.push_str(if *n > 0.0 { "1/0" } else { "-1/0" }),
+            Self::Number(n) => write!(out, "{:?}", n).unwrap(),
+            Self::String(s) => write_lua_string(out, s),
+            Self::Table(entries) => write_lua_table(out, entries, indent),
+        }
+    }
+}
+
+fn write_lua_string(out: &mut String, s: &[u8]) {
+    out.push('"');
+    for &b in s {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(b as char),
+            _ => write!(out, "\\{:03}", b).unwrap(),
+        }
+    }
+    out.push('"');
+}
+
+fn write_lua_table(out: &mut String, entries: &BTreeMap<StoreKey, StoreValue>, indent: usize) {
+    if entries.is_empty() {
+        out.push_str("{}");
+        return
+    }
+    out.push_str("{\n");
+    for (key, value) in entries {
+        out.push_str(&"    ".repeat(indent + 1));
+        out.push('[');
+        match key {
+            StoreKey::Integer(n) => write!(out, "{}", n).unwrap(),
+            StoreKey::String(s) => write_lua_string(out, s),
+        }
+        out.push_str("] = ");
+        value.write_lua(out, indent + 1);
+        out.push_str(",\n");
+    }
+    out.push_str(&"    ".repeat(indent));
+    out.push('}');
+}
+
+/// Key-value storage for agents, split into namespaces that are each saved to their own file.
+pub struct AgentStore {
+    /// Directory that namespace files are saved to. Values are only kept in memory if unset.
+    dir: Option<PathBuf>,
+    /// Namespaces loaded so far.
+    namespaces: HashMap<String, BTreeMap<String, StoreValue>>,
+}
+
+impl AgentStore {
+    pub fn new(data_dir: Option<&Path>) -> Self {
+        let dir = data_dir.map(|dir| dir.join("store"));
+        match &dir {
+            Some(dir) => info!("Agent store directory: {}", dir.display()),
+            None => info!("No data directory configured; agent store values will not persist across restarts."),
+        }
+        Self {
+            dir,
+            namespaces: Default::default(),
+        }
+    }
+
+    fn namespace_path(&self, namespace: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(format!("{}.lua", namespace)))
+    }
+
+    /// Gets the values in a namespace, loading them from disk if needed.
+    pub fn namespace(&mut self, lua: &Lua, namespace: &str) -> Result<&mut BTreeMap<String, StoreValue>, String> {
+        if !is_valid_namespace(namespace) {
+            return Err(format!("invalid store namespace: '{}'", namespace))
+        }
+        if !self.namespaces.contains_key(namespace) {
+            let values = match self.namespace_path(namespace) {
+                Some(path) if path.exists() => load_namespace_file(lua, &path)?,
+                _ => Default::default()
+            };
+            self.namespaces.insert(namespace.to_owned(), values);
+        }
+        Ok(self.namespaces.get_mut(namespace).unwrap())
+    }
+
+    /// Saves a namespace to disk.
+    pub fn save(&self, namespace: &str) -> Result<(), String> {
+        let (path, values) = match (self.namespace_path(namespace), self.namespaces.get(namespace)) {
+            (Some(path), Some(values)) => (path, values),
+            _ => return Ok(())
+        };
+        let mut src = String::from("-- Agent store namespace. Written by the engine; edit only while the engine is stopped.\nreturn ");
+        let table = values.iter().map(|(key, value)| (StoreKey::String(key.as_bytes().to_vec()), value.clone())).collect();
+        write_lua_table(&mut src, &table, 0);
+        src.push('\n');
+        write_file_atomic(&path, &src).map_err(|err| format!("failed to save store namespace '{}': {}", namespace, err))
+    }
+}
+
+fn is_valid_namespace(namespace: &str) -> bool {
+    !namespace.is_empty() && namespace.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) && !namespace.starts_with('.')
+}
+
+fn load_namespace_file(lua: &Lua, path: &Path) -> Result<BTreeMap<String, StoreValue>, String> {
+    let src = fs::read_to_string(path).map_err(|err| format!("failed to read '{}': {}", path.display(), err))?;
+    // Evaluate the file without access to any globals
+    let value = lua.load(&src)
+        .set_name(path.to_string_lossy().as_ref())
+        .and_then(|chunk| chunk.set_environment(lua.create_table()?))
+        .and_then(|chunk| chunk.eval::<LuaValue>())
+        .map_err(|err| format!("failed to load '{}': {}", path.display(), err))?;
+    match StoreValue::from_lua_value(value, 0)? {
+        StoreValue::Table(entries) => Ok(entries.into_iter().filter_map(|(key, value)| match key {
+            StoreKey::String(key) => String::from_utf8(key).ok().map(|key| (key, value)),
+            StoreKey::Integer(_) => None,
+        }).collect()),
+        _ => Err(format!("'{}' does not contain a table", path.display()))
+    }
+}
+
+/// Writes a file by way of a temporary file, so that the file is never left half-written.
+pub(super) fn write_file_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Reads a value from a TOML file, or returns the default value if the file doesn't exist or can't be read.
+pub(super) fn load_toml_file<T: DeserializeOwned + Default>(path: &Path, description: &str) -> T {
+    if !path.exists() {
+        return T::default()
+    }
+    let result = fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|src| toml::from_str(&src).map_err(|err| err.to_string()));
+    match result {
+        Ok(value) => {
+            info!("Loaded {}: {}", description, path.display());
+            value
+        },
+        Err(err) => {
+            warn!("Failed to read {} '{}': {}", description, path.display(), err);
+            T::default()
+        }
+    }
+}
+
+/// Writes a value to a TOML file atomically.
+pub(super) fn save_toml_file<T: Serialize>(path: &Path, value: &T, description: &str) {
+    let result = toml::to_string(value)
+        .map_err(|err| err.to_string())
+        .and_then(|src| write_file_atomic(path, &src).map_err(|err| err.to_string()));
+    if let Err(err) = result {
+        error!("Failed to save {} '{}': {}", description, path.display(), err);
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::rc::Rc;
+use serde::{Deserialize, Serialize};
+use crate::sound::{Channel, SoundPlayOptions, SoundPlaySkip};
+use super::AgentModule;
+use super::store::{load_toml_file, save_toml_file};
+
+const VOICEMAIL_FILE_NAME: &str = "voicemail.toml";
+
+/// Something an agent did with a sound while leaving a message.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct VoicemailEvent {
+    /// Time (in seconds) from the start of the message.
+    pub at: f64,
+    pub channel: Channel,
+    /// Key of the sound that was played, or `None` if the channel was stopped.
+    pub sound: Option<String>,
+    #[serde(default = "default_event_level")]
+    pub volume: f32,
+    #[serde(default = "default_event_level")]
+    pub speed: f32,
+    #[serde(default)]
+    pub looping: bool,
+    /// Seconds skipped at the start of the sound. A random position is used if unset.
+    pub skip: Option<f64>,
+    pub take: Option<f64>,
+    pub delay: Option<f64>,
+    #[serde(default)]
+    pub fadein: f64,
+}
+
+fn default_event_level() -> f32 {
+    1.0
+}
+
+impl VoicemailEvent {
+    pub fn play(at: Duration, channel: Channel, sound: &str, opts: &SoundPlayOptions) -> Self {
+        Self {
+            at: at.as_secs_f64(),
+            channel,
+            sound: Some(sound.to_owned()),
+            volume: opts.volume,
+            speed: opts.speed,
+            looping: opts.looping,
+            skip: match opts.skip {
+                SoundPlaySkip::By(skip) => Some(skip.as_secs_f64()),
+                SoundPlaySkip::Random => None,
+            },
+            take: opts.take.map(|take| take.as_secs_f64()),
+            delay: opts.delay.map(|delay| delay.as_secs_f64()),
+            fadein: opts.fadein.as_secs_f64(),
+        }
+    }
+
+    pub fn stop(at: Duration, channel: Channel) -> Self {
+        Self {
+            at: at.as_secs_f64(),
+            channel,
+            sound: None,
+            volume: 1.0,
+            speed: 1.0,
+            looping: false,
+            skip: Some(0.0),
+            take: None,
+            delay: None,
+            fadein: 0.0,
+        }
+    }
+
+    /// Gets the options to replay the sound with.
+    pub fn play_options(&self) -> SoundPlayOptions {
+        SoundPlayOptions {
+            volume: self.volume,
+            speed: self.speed,
+            looping: self.looping,
+            skip: match self.skip {
+                Some(skip) => SoundPlaySkip::By(Duration::from_secs_f64(skip.max(0.0))),
+                None => SoundPlaySkip::Random,
+            },
+            take: self.take.map(|take| Duration::from_secs_f64(take.max(0.0))),
+            delay: self.delay.map(|delay| Duration::from_secs_f64(delay.max(0.0))),
+            fadein: Duration::from_secs_f64(self.fadein.max(0.0)),
+        }
+    }
+}
+
+/// A message left on the answering machine.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct VoicemailMessage {
+    /// Name of the agent that left the message.
+    pub agent: String,
+    /// Name of the line the message was left on.
+    pub line: String,
+    /// Unix time (in seconds) at which the message was left.
+    pub time: i64,
+    /// Length of the message (in seconds).
+    pub duration: f64,
+    /// Sounds played by the agent, in order.
+    #[serde(default, rename = "event")]
+    pub events: Vec<VoicemailEvent>,
+}
+
+/// A message that an agent is currently leaving on a line.
+pub struct VoicemailRecording<'lua> {
+    pub agent: Rc<AgentModule<'lua>>,
+    pub start: Instant,
+    pub events: Vec<VoicemailEvent>,
+}
+
+/// A step of message playback.
+pub enum VoicemailCue {
+    /// Beep before a message.
+    Beep,
+    /// Replays something the agent did.
+    Event(VoicemailEvent),
+    /// A message has been played in full.
+    MessageEnd,
+}
+
+/// Messages being played back to the user, as a timeline of cues.
+pub struct VoicemailPlayback {
+    pub start: Instant,
+    /// Cues and their times from the start of playback, in order.
+    pub cues: VecDeque<(Duration, VoicemailCue)>,
+    /// Number of messages played in full so far.
+    pub messages_played: usize,
+}
+
+impl VoicemailPlayback {
+    /// Lays out the specified messages one after another, each preceded by a beep.
+    pub fn new(messages: &VecDeque<VoicemailMessage>, start: Instant, beep_length: Duration) -> Self {
+        let mut cues = VecDeque::new();
+        let mut offset = Duration::ZERO;
+        for message in messages {
+            cues.push_back((offset, VoicemailCue::Beep));
+            offset += beep_length;
+            for event in message.events.iter() {
+                cues.push_back((offset + Duration::from_secs_f64(event.at.max(0.0)), VoicemailCue::Event(event.clone())));
+            }
+            offset += Duration::from_secs_f64(message.duration.max(0.0));
+            cues.push_back((offset, VoicemailCue::MessageEnd));
+        }
+        Self {
+            start,
+            cues,
+            messages_played: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct VoicemailFile {
+    message: Vec<VoicemailMessage>,
+}
+
+/// Messages left on the answering machine, optionally persisted to the data directory.
+pub struct VoicemailBox {
+    /// Stored messages, oldest first.
+    messages: VecDeque<VoicemailMessage>,
+    /// Maximum number of messages to keep.
+    capacity: usize,
+    /// File the messages are saved to, if any.
+    path: Option<PathBuf>,
+}
+
+impl VoicemailBox {
+    /// Loads the messages from the specified data directory, or starts an empty mailbox if there is no directory or file.
+    pub fn load(data_dir: Option<&Path>, capacity: usize) -> Self {
+        let path = data_dir.map(|dir| dir.join(VOICEMAIL_FILE_NAME));
+        let file: VoicemailFile = match &path {
+            Some(path) => load_toml_file(path, "voicemail"),
+            None => Default::default()
+        };
+
+        let mut messages: VecDeque<_> = file.message.into();
+        while messages.len() > capacity {
+            messages.pop_front();
+        }
+
+        Self {
+            messages,
+            capacity,
+            path,
+        }
+    }
+
+    pub fn messages(&self) -> &VecDeque<VoicemailMessage> {
+        &self.messages
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Adds a message and saves the mailbox. The oldest message is dropped if the mailbox is full.
+    pub fn add(&mut self, message: VoicemailMessage) {
+        if self.capacity == 0 {
+            return
+        }
+        if self.messages.len() >= self.capacity {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message);
+        self.save();
+    }
+
+    /// Removes the specified number of oldest messages and saves the mailbox.
+    pub fn remove_oldest(&mut self, count: usize) {
+        if count == 0 {
+            return
+        }
+        self.messages.drain(..count.min(self.messages.len()));
+        self.save();
+    }
+
+    fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return
+        };
+        let file = VoicemailFile {
+            message: self.messages.iter().cloned().collect(),
+        };
+        save_toml_file(path, &file, "voicemail");
+    }
+}
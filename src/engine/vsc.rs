@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use mlua::prelude::*;
+use log::{info, error};
+use serde::Deserialize;
+
+/// Prefix of vertical service codes dialed on a touch-tone phone.
+const VSC_PREFIX_TONE: &str = "*";
+/// Prefix of vertical service codes dialed on a rotary phone.
+const VSC_PREFIX_PULSE: &str = "11";
+/// Number of digits in a vertical service code, not counting the prefix.
+const VSC_CODE_LEN: usize = 2;
+
+/// A vertical service code feature built into the engine.
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum VscAction {
+    /// Calls back the last agent that called the line.
+    LastCallReturn,
+    /// Calls the last number dialed on the line.
+    Redial,
+    /// Sets the volume of the line to the digit dialed after the code (0-9).
+    Volume,
+    /// Forwards all calls to the line to the number dialed after the code.
+    CallForward,
+    /// Cancels call forwarding on the line.
+    CancelCallForward,
+}
+
+impl VscAction {
+    /// Gets the number of digits the action expects after the code, if fixed.
+    fn argument_len(&self) -> Option<usize> {
+        match self {
+            Self::LastCallReturn | Self::Redial | Self::CancelCallForward => Some(0),
+            Self::Volume => Some(1),
+            Self::CallForward => None,
+        }
+    }
+}
+
+/// What happens when a vertical service code is dialed.
+pub enum VscHandler<'lua> {
+    /// Runs a built-in feature.
+    Action(VscAction),
+    /// Connects the call to the named agent, which can read the dialed digits with `phone.call_dialed_number()`.
+    Agent(String),
+    /// Calls a Lua function with the digits dialed after the code.
+    /// If the function returns a number, it is called; otherwise the feature is confirmed.
+    Function(LuaFunction<'lua>),
+}
+
+impl<'lua> VscHandler<'lua> {
+    /// Parses a handler name: either a built-in action name or `@` followed by an agent name.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        if let Some(agent_name) = name.strip_prefix('@') {
+            return Ok(Self::Agent(agent_name.trim().to_owned()))
+        }
+        VscAction::deserialize(toml::Value::String(name.to_owned()))
+            .map(Self::Action)
+            .map_err(|_| format!("unknown service code action: '{}'", name))
+    }
+}
+
+/// A dialed number split into a vertical service code and the digits after it.
+pub struct VscCall<'a> {
+    pub code: &'a str,
+    pub args: &'a str,
+}
+
+/// Vertical service codes and the handlers bound to them.
+#[derive(Default)]
+pub struct VscRegistry<'lua> {
+    handlers: HashMap<String, VscHandler<'lua>>,
+}
+
+impl<'lua> VscRegistry<'lua> {
+    pub fn from_config(codes: &HashMap<String, String>) -> Self {
+        let mut registry = Self::default();
+        for (code, handler_name) in codes {
+            match VscHandler::parse(handler_name) {
+                Ok(handler) => registry.register(code.as_str(), handler),
+                Err(err) => error!("Ignoring vertical service code *{}: {}", code, err)
+            }
+        }
+        if !registry.handlers.is_empty() {
+            info!("Registered {} vertical service code(s).", registry.handlers.len());
+        }
+        registry
+    }
+
+    /// Binds a code to a handler, replacing any existing binding.
+    pub fn register(&mut self, code: &str, handler: VscHandler<'lua>) {
+        self.handlers.insert(code.to_owned(), handler);
+    }
+
+    /// Removes the binding of a code. Returns `true` if the code was bound.
+    pub fn unregister(&mut self, code: &str) -> bool {
+        self.handlers.remove(code).is_some()
+    }
+
+    pub fn get(&self, code: &str) -> Option<&VscHandler<'lua>> {
+        self.handlers.get(code)
+    }
+
+    /// Splits a dialed number into a registered code and its arguments, if it starts with one.
+    pub fn parse_number<'a>(&self, number: &'a str, is_rotary: bool) -> Option<VscCall<'a>> {
+        let rest = number.strip_prefix(VSC_PREFIX_TONE)
+            .or_else(|| if is_rotary { number.strip_prefix(VSC_PREFIX_PULSE) } else { None })?;
+        if rest.len() < VSC_CODE_LEN || !rest.is_char_boundary(VSC_CODE_LEN) {
+            return None
+        }
+        let (code, args) = rest.split_at(VSC_CODE_LEN);
+        if !self.handlers.contains_key(code) {
+            return None
+        }
+        Some(VscCall { code, args })
+    }
+
+    /// Returns `true` if the dialed number is a built-in service code with all of its digits, so it can be handled without waiting out the post-dial delay.
+    pub fn is_complete(&self, number: &str, is_rotary: bool) -> bool {
+        let call = match self.parse_number(number, is_rotary) {
+            Some(call) => call,
+            None => return false
+        };
+        match self.handlers.get(call.code) {
+            Some(VscHandler::Action(action)) => action.argument_len() == Some(call.args.len()),
+            _ => false
+        }
+    }
+}
@@ -3,10 +3,11 @@ use std::sync::{Mutex, Arc, Condvar};
 use std::time::{Instant, Duration};
 use take_mut;
 use rppal::gpio::*;
+use crate::clock::Clock;
 
 /// Enables a digital input to be wrapped into a debounced input.
 pub trait Debounce<T> where T: Debounced {
-    fn debounce(self, time: Duration) -> T;
+    fn debounce(self, time: Duration, clock: &Arc<dyn Clock>) -> T;
 }
 
 /// Represents a debounced digital input.
@@ -33,20 +34,21 @@ struct SoftInputState {
     bounce_time: Duration,
     last_changed: Instant,
     last_value: bool,
-    change_callback: Option<Box<dyn FnMut(bool) + Send + 'static>>
+    change_callback: Option<Box<dyn FnMut(bool) + Send + 'static>>,
+    clock: Arc<dyn Clock>,
 }
 
 impl SoftInputState {
     fn change_last_value(&mut self, new_value: bool) {
-        self.last_changed = Instant::now();
+        self.last_changed = self.clock.now();
         self.last_value = new_value;
     }
 }
 
 impl SoftInputPin {
-    fn new(mut pin: InputPin, bounce_time: Duration) -> Self {
+    fn new(mut pin: InputPin, bounce_time: Duration, clock: &Arc<dyn Clock>) -> Self {
         pin.set_interrupt(Trigger::Both).unwrap();
-        let last_changed = Instant::now();
+        let last_changed = clock.now();
         let last_value = pin.is_high();
         let pin_status = Arc::new((Mutex::new(last_value), Condvar::new()));
 
@@ -54,7 +56,8 @@ impl SoftInputPin {
             last_changed,
             bounce_time,
             last_value,
-            change_callback: None
+            change_callback: None,
+            clock: Arc::clone(clock),
         };
 
         let mut s = Self {
@@ -122,8 +125,8 @@ impl SoftInputPin {
 }
 
 impl Debounce<SoftInputPin> for InputPin {
-    fn debounce(self, time: Duration) -> SoftInputPin {
-        SoftInputPin::new(self, time)
+    fn debounce(self, time: Duration, clock: &Arc<dyn Clock>) -> SoftInputPin {
+        SoftInputPin::new(self, time, clock)
     }
 }
 
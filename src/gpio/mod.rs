@@ -9,10 +9,11 @@ pub use pull::*;
 use rppal::gpio::{Gpio, OutputPin, InputPin, Pin};
 
 use std::{sync::{mpsc, Mutex, Arc, atomic::{AtomicBool, Ordering}}, collections::HashMap};
-use std::time::{Instant, Duration};
+use std::time::Duration;
 use std::thread;
 use std::rc::Rc;
 use log::{info, warn};
+use crate::clock::Clock;
 use crate::config::*;
 use crate::phone::*;
 
@@ -29,20 +30,22 @@ pub struct GpioInterface {
     pub gpio: Gpio,
     input_pins: HashMap<u8, SoftInputPin>,
     output_pins: HashMap<u8, OutputPin>,
+    clock: Arc<dyn Clock>,
 }
 
 impl GpioInterface {
-    pub fn new() -> Result<Self, rppal::gpio::Error> {
+    pub fn new(clock: &Arc<dyn Clock>) -> Result<Self, rppal::gpio::Error> {
         let gpio = Gpio::new()?;
         Ok(Self {
             gpio,
             input_pins: Default::default(),
             output_pins: Default::default(),
+            clock: Arc::clone(clock),
         })
     }
 
     pub fn register_input(&mut self, pin_id: u8, pull: Pull, bounce_time: Option<Duration>) -> Result<(), rppal::gpio::Error> {
-        let pin = gen_input_pin(self.gpio.get(pin_id)?, pull).debounce(bounce_time.unwrap_or_default());
+        let pin = gen_input_pin(self.gpio.get(pin_id)?, pull).debounce(bounce_time.unwrap_or_default(), &self.clock);
         self.input_pins.insert(pin_id, pin);
         Ok(())
     }
@@ -117,8 +120,12 @@ pub struct PhoneGpioInterface {
     out_ringer: Option<Arc<Mutex<OutputPin>>>,
     /// Transmission channel for ringer control
     tx_ringer: Option<mpsc::Sender<Option<Arc<RingPattern>>>>,
+    /// Pin for message-waiting lamp output.
+    out_mwi: Option<Mutex<OutputPin>>,
     /// Copy of config used to initialize pins.
-    config: Rc<CursedConfig>
+    config: Rc<CursedConfig>,
+    /// Time source for input timing.
+    clock: Arc<dyn Clock>,
 }
 
 pub fn gen_input_pin(pin: Pin, pull: Pull) -> InputPin {
@@ -132,39 +139,39 @@ pub fn gen_input_pin(pin: Pin, pull: Pull) -> InputPin {
     }
 }
 
-fn gen_optional_soft_input_from(gpio: &Gpio, enable: Option<bool>, input_config: &Option<InputPinConfig>) -> Option<SoftInputPin> {
+fn gen_optional_soft_input_from(gpio: &Gpio, clock: &Arc<dyn Clock>, enable: Option<bool>, input_config: &Option<InputPinConfig>) -> Option<SoftInputPin> {
     if enable.unwrap_or(false) {
         if let Some(input_config) = input_config {
             let pin = gpio.get(input_config.pin).unwrap();
             let input = gen_input_pin(pin, Pull::from(&input_config.pull));
-            let soft_input = input.debounce(Duration::from_millis(input_config.bounce_ms.unwrap_or(0)));
+            let soft_input = input.debounce(Duration::from_millis(input_config.bounce_ms.unwrap_or(0)), clock);
             return Some(soft_input);
         }
     }
     None
 }
 
-fn gen_required_soft_input_from(gpio: &Gpio, input_config: &InputPinConfig) -> SoftInputPin {
+fn gen_required_soft_input_from(gpio: &Gpio, clock: &Arc<dyn Clock>, input_config: &InputPinConfig) -> SoftInputPin {
     let pin = gpio.get(input_config.pin).unwrap();
     let raw_input = gen_input_pin(pin, Pull::from(&input_config.pull));
-    let soft_input = raw_input.debounce(Duration::from_millis(input_config.bounce_ms.unwrap_or(0)));
+    let soft_input = raw_input.debounce(Duration::from_millis(input_config.bounce_ms.unwrap_or(0)), clock);
     soft_input
 }
 
-fn gen_optional_soft_input(gpio: &Gpio, enable: Option<bool>, pin: Option<u8>, debounce: Option<Duration>) -> Option<SoftInputPin> {
+fn gen_optional_soft_input(gpio: &Gpio, clock: &Arc<dyn Clock>, enable: Option<bool>, pin: Option<u8>, debounce: Option<Duration>) -> Option<SoftInputPin> {
     if enable.unwrap_or(false) {
         if let Some(pin) = pin {
             let input = gpio.get(pin).unwrap()
                 .into_input_pullup()
-                .debounce(debounce.unwrap_or_default());
+                .debounce(debounce.unwrap_or_default(), clock);
             return Some(input);
         }
     }
     None
 }
 
-fn gen_required_soft_input(gpio: &Gpio, pin: u8, debounce: Option<Duration>, pull: Pull) -> SoftInputPin {
-    gen_input_pin(gpio.get(pin).unwrap(), pull).debounce(debounce.unwrap_or_default())
+fn gen_required_soft_input(gpio: &Gpio, clock: &Arc<dyn Clock>, pin: u8, debounce: Option<Duration>, pull: Pull) -> SoftInputPin {
+    gen_input_pin(gpio.get(pin).unwrap(), pull).debounce(debounce.unwrap_or_default(), clock)
 }
 
 fn gen_optional_output(gpio: &Gpio, enable: Option<bool>, pin: Option<u8>) -> Option<OutputPin> {
@@ -181,24 +188,27 @@ fn gen_required_output(gpio: &Gpio, pin: u8) -> OutputPin {
 }
 
 impl PhoneGpioInterface {
-    pub fn new(config: &Rc<CursedConfig>) -> PhoneGpioInterface {
+    pub fn new(config: &Rc<CursedConfig>, clock: &Arc<dyn Clock>) -> PhoneGpioInterface {
         let gpio = Gpio::new().expect("Unable to initialize GPIO interface");
         let inputs = &config.gpio.inputs;
         let outputs = &config.gpio.outputs;
         let mut tx_ringer = None;
 
         // Register standard GPIO pins
-        let in_hook = gen_required_soft_input_from(&gpio, &inputs.switchhook);
+        let in_hook = gen_required_soft_input_from(&gpio, clock, &inputs.switchhook);
 
         let out_ringer = gen_optional_output(&gpio, config.ringer_enabled, outputs.pin_ringer)
             .map(|o| Arc::new(Mutex::new(o)));
 
+        let out_mwi = gen_optional_output(&gpio, Some(true), outputs.pin_mwi)
+            .map(Mutex::new);
+
         // Register pulse-dialing pins
         let (in_dial_switch, in_dial_pulse) = if config.rotary.enabled {
             let dial_pulse = config.rotary.input_pulse.as_ref().expect("missing configuration for rotary pulse input");
             let dial_switch = config.rotary.input_rest.as_ref().expect("missing configuration for rotary rest input");
-            let in_dial_pulse = gen_required_soft_input_from(&gpio, dial_pulse);
-            let in_dial_switch = gen_required_soft_input_from(&gpio, dial_switch);
+            let in_dial_pulse = gen_required_soft_input_from(&gpio, clock, dial_pulse);
+            let in_dial_switch = gen_required_soft_input_from(&gpio, clock, dial_switch);
             (Some(in_dial_switch), Some(in_dial_pulse))
         } else {
             (None, None)
@@ -209,10 +219,10 @@ impl PhoneGpioInterface {
             let pins_keypad_rows = config.keypad.input_rows.expect("missing configuration for keypad row inputs");
                 let pins_keypad_cols = config.keypad.output_cols.expect("missing configuration for keypad column outputs");
                 let in_keypad_rows = [
-                    Arc::new(Mutex::new(gen_required_soft_input(&gpio, pins_keypad_rows[0], Some(KEYPAD_ROW_BOUNCE), Pull::Down))),
-                    Arc::new(Mutex::new(gen_required_soft_input(&gpio, pins_keypad_rows[1], Some(KEYPAD_ROW_BOUNCE), Pull::Down))),
-                    Arc::new(Mutex::new(gen_required_soft_input(&gpio, pins_keypad_rows[2], Some(KEYPAD_ROW_BOUNCE), Pull::Down))),
-                    Arc::new(Mutex::new(gen_required_soft_input(&gpio, pins_keypad_rows[3], Some(KEYPAD_ROW_BOUNCE), Pull::Down))),
+                    Arc::new(Mutex::new(gen_required_soft_input(&gpio, clock, pins_keypad_rows[0], Some(KEYPAD_ROW_BOUNCE), Pull::Down))),
+                    Arc::new(Mutex::new(gen_required_soft_input(&gpio, clock, pins_keypad_rows[1], Some(KEYPAD_ROW_BOUNCE), Pull::Down))),
+                    Arc::new(Mutex::new(gen_required_soft_input(&gpio, clock, pins_keypad_rows[2], Some(KEYPAD_ROW_BOUNCE), Pull::Down))),
+                    Arc::new(Mutex::new(gen_required_soft_input(&gpio, clock, pins_keypad_rows[3], Some(KEYPAD_ROW_BOUNCE), Pull::Down))),
                 ];
 
                 let out_keypad_cols = Arc::new(Mutex::new([
@@ -335,7 +345,7 @@ impl PhoneGpioInterface {
                     .iter()
                     .zip(coin_trigger_bounce_ms.iter().map(|ms| Duration::from_millis(*ms)))
                     .zip(coin_values.iter())
-                    .map(|((pin, bounce), cents)| (*cents, gen_required_soft_input(&gpio, *pin, Some(bounce), pull)))
+                    .map(|((pin, bounce), cents)| (*cents, gen_required_soft_input(&gpio, clock, *pin, Some(bounce), pull)))
                     .collect();
 
                 info!("Coin triggers initialized ({}).", in_coin_triggers.len());
@@ -357,7 +367,9 @@ impl PhoneGpioInterface {
             out_keypad_cols,
             out_ringer,
             tx_ringer,
-            config: Rc::clone(config)
+            out_mwi,
+            config: Rc::clone(config),
+            clock: Arc::clone(clock),
         }
     }
 }
@@ -403,17 +415,18 @@ impl PhoneGpioInterface {
 
             let (tx_keypad, rx_keypad) = mpsc::channel();
             let cols = Arc::clone(cols);
+            let clock = Arc::clone(&self.clock);
             let sender = tx.clone();
             let suppress_row_events = Arc::new(AtomicBool::new(false));            
             let suppress_row_events_cl = Arc::clone(&suppress_row_events);
 
             // Create keypad input handler thread
             thread::spawn(move || {
-                let mut last_digit_time = Instant::now();
+                let mut last_digit_time = clock.now();
 
                 while let Ok((row_index, row_high)) = rx_keypad.recv() {
                     let mut cols = cols.lock().unwrap();
-                    let current_press_time = Instant::now();
+                    let current_press_time = clock.now();
                     if row_high && current_press_time.checked_duration_since(last_digit_time).unwrap_or_default() >= KEYPAD_MIN_DIGIT_INTERVAL {
                         // Turn off each col until row turns off
                         for col_index in 0..KEYPAD_COL_COUNT {
@@ -473,6 +486,18 @@ impl PhoneGpioInterface {
         Ok(rx)
     }
 
+    /// Turns the message-waiting lamp on or off, if there is one.
+    pub fn set_mwi_lamp(&self, lit: bool) {
+        if let Some(out_mwi) = &self.out_mwi {
+            let mut out_mwi = out_mwi.lock().unwrap();
+            if lit {
+                out_mwi.set_high();
+            } else {
+                out_mwi.set_low();
+            }
+        }
+    }
+
     pub fn tx_ringer(&self) -> Option<mpsc::Sender<Option<Arc<RingPattern>>>> {
         if let Some(tx) = &self.tx_ringer {
             return Some(tx.clone())
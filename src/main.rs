@@ -1,11 +1,15 @@
+mod clock;
+mod common;
 mod config;
 mod engine;
 mod phone;
 mod sound;
 mod gpio;
+mod scenario;
 
-use crate::engine::CursedEngine;
-use crate::sound::SoundEngine;
+use crate::clock::*;
+use crate::engine::{CursedEngine, PhoneLine};
+use crate::sound::{SoundEngine, SoundLibrary, SoundStreamThresholds};
 use crate::phone::PhoneEngine;
 use crate::config::*;
 use std::boxed::Box;
@@ -26,12 +30,21 @@ const VFS_AGENTS_PATH: &str = "./agents";
 const VFS_SCRIPTS_PATH: &str = "./scripts";
 const VFS_SOUNDS_PATH: &str = "./sounds";
 const VFS_SOUNDBANKS_PATH: &str = "./soundbanks";
+const VFS_TONE_PLANS_PATH: &str = "./tone_plans";
 
 const ENV_CONFIG_PATH: &str = "CURSED_CONFIG_PATH";
 const ENV_RESOURCES_PATH: &str = "CURSED_RESOURCES_PATH";
 
 #[allow(unreachable_code)]
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Run scenario tests instead of the phone if requested
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(|arg| arg.as_str()) == Some("test") {
+        TermLogger::init(LevelFilter::Warn, Default::default(), TerminalMode::Stderr, ColorChoice::Auto).unwrap();
+        let all_passed = scenario::run_scenarios(&args[1..]);
+        std::process::exit(if all_passed { 0 } else { 1 });
+    }
+
     // Set up logger
     TermLogger::init(LevelFilter::Info, Default::default(), TerminalMode::Stderr, ColorChoice::Auto).unwrap();
 
@@ -41,17 +54,34 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     // Load engine
-    let env_config_path = env::var(ENV_CONFIG_PATH);
-    let config_path = env_config_path.as_deref().unwrap_or(CONFIG_PATH);
+    let config_path = get_config_path();
     info!("Loading config: {}", config_path);
-    let config = Rc::new(load_config(config_path));
+    let config = Rc::new(load_config(&config_path, &Default::default()));
     info!("Config loaded: {:#?}", config);
+    let simulated_clock = match config.clock {
+        ClockMode::Realtime => None,
+        ClockMode::Simulated => {
+            info!("Using simulated clock.");
+            if config.sound.output == SoundOutputType::Device {
+                warn!("Sound device output does not follow the simulated clock; consider using the null or WAV output.");
+            }
+            Some(Arc::new(SimulatedClock::new()))
+        }
+    };
+    let clock: Arc<dyn Clock> = match &simulated_clock {
+        Some(simulated_clock) => Arc::clone(simulated_clock) as Arc<dyn Clock>,
+        None => Arc::new(SystemClock),
+    };
     let vfs_root = create_virtual_filesystem(&config);
-    let sound_engine = create_sound_engine(&config, &vfs_root);
-    let phone = create_phone(&config, sound_engine);
-    let engine = create_cursed_engine(&config, sound_engine, &vfs_root);
-    engine.listen(phone.gen_phone_output());
-    phone.listen(engine.gen_engine_output());
+    let sound_library = create_sound_library(&config, &vfs_root);
+    let line_configs = create_line_configs(&config);
+    let sound_engines: Vec<_> = line_configs.iter().map(|(_, line_config)| create_sound_engine(line_config, &clock, &sound_library)).collect();
+    let phones: Vec<_> = line_configs.iter().zip(sound_engines.iter()).map(|((_, line_config), sound_engine)| create_phone(line_config, &clock, sound_engine)).collect();
+    let engine = create_cursed_engine(&config, &clock, &sound_library, &line_configs, &sound_engines, &vfs_root);
+    for (line_id, phone) in phones.iter().enumerate() {
+        engine.listen(line_id, phone.gen_phone_output());
+        phone.listen(engine.gen_engine_output(line_id));
+    }
     engine.load_lua_api()?;
     engine.load_agents();
     
@@ -70,8 +100,20 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     while is_running.load(Ordering::SeqCst) {
         // Update engine state
         let tick_start = time::Instant::now();
-        phone.tick();
+        for phone in phones.iter() {
+            phone.tick();
+        }
         engine.tick();
+        for sound_engine in sound_engines.iter() {
+            sound_engine.borrow().tick();
+        }
+
+        // Simulated time moves on immediately
+        if let Some(simulated_clock) = &simulated_clock {
+            simulated_clock.advance(tick_interval);
+            continue
+        }
+
         let tick_end = time::Instant::now();
 
         // Lock tickrate at configured value
@@ -79,11 +121,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let remaining_tick_time = tick_interval.saturating_sub(frame_time);
         spin_sleep::sleep(remaining_tick_time);
     }
+    for sound_engine in sound_engines.iter() {
+        sound_engine.borrow().flush_output();
+    }
     Ok(())
 }
 
-fn load_config(path: &str) -> CursedConfig {
-    let mut config = config::load_config(path);
+fn get_config_path() -> String {
+    env::var(ENV_CONFIG_PATH).unwrap_or_else(|_| CONFIG_PATH.to_owned())
+}
+
+fn load_config(path: &str, overrides: &toml::Table) -> CursedConfig {
+    let mut config = config::load_config(path, overrides);
     if let Ok(env_resources) = env::var(ENV_RESOURCES_PATH) {
         config.include_resources.extend(env_resources.split(';').map(|p| p.trim().to_owned()));
     }
@@ -108,25 +157,40 @@ fn create_virtual_filesystem(config: &CursedConfig) -> VfsPath {
     return vfs.into()
 }
 
-fn create_cursed_engine<'a>(config: &Rc<CursedConfig>, sound_engine: &Rc<RefCell<SoundEngine>>, vfs_root: &VfsPath) -> &'static mut CursedEngine<'a> {
+fn create_line_configs(config: &CursedConfig) -> Vec<(LineConfig, Rc<CursedConfig>)> {
+    let line_configs: Vec<_> = config.line_configs().into_iter().map(|(line, line_config)| (line, Rc::new(line_config))).collect();
+    info!("Lines: {}", line_configs.iter().map(|(line, _)| line.name.as_str()).collect::<Vec<_>>().join(", "));
+    line_configs
+}
+
+fn create_cursed_engine<'a>(config: &Rc<CursedConfig>, clock: &Arc<dyn Clock>, sound_library: &Rc<RefCell<SoundLibrary>>, line_configs: &[(LineConfig, Rc<CursedConfig>)], sound_engines: &[&'static mut Rc<RefCell<SoundEngine>>], vfs_root: &VfsPath) -> &'static mut CursedEngine<'a> {
     let scripts_root = vfs_root.join(VFS_SCRIPTS_PATH).unwrap();
     let agents_root = vfs_root.join(VFS_AGENTS_PATH).unwrap();
-    let engine = Box::new(CursedEngine::new(scripts_root, agents_root, config, sound_engine));
+    let lines = line_configs.iter()
+        .zip(sound_engines.iter())
+        .map(|((line, line_config), sound_engine)| PhoneLine::new(line, line_config, clock, sound_engine))
+        .collect();
+    let engine = Box::new(CursedEngine::new(scripts_root, agents_root, config, clock, sound_library, lines));
     let engine: &'static mut CursedEngine = Box::leak(engine);
     engine
 }
 
-fn create_sound_engine(config: &Rc<CursedConfig>, vfs_root: &VfsPath) -> &'static mut Rc<RefCell<SoundEngine>> {
+fn create_sound_library(config: &CursedConfig, vfs_root: &VfsPath) -> Rc<RefCell<SoundLibrary>> {
+    info!("Loading sound library... ");
+    let stream_thresholds = SoundStreamThresholds::from_config(&config.sound);
+    Rc::new(RefCell::new(SoundLibrary::new(vfs_root.join(VFS_SOUNDS_PATH).unwrap(), vfs_root.join(VFS_SOUNDBANKS_PATH).unwrap(), vfs_root.join(VFS_TONE_PLANS_PATH).unwrap(), stream_thresholds)))
+}
+
+fn create_sound_engine(config: &Rc<CursedConfig>, clock: &Arc<dyn Clock>, sound_library: &Rc<RefCell<SoundLibrary>>) -> &'static mut Rc<RefCell<SoundEngine>> {
     info!("Loading sound engine... ");
-    let sound_engine = Box::new(Rc::new(RefCell::new(
-        SoundEngine::new(vfs_root.join(VFS_SOUNDS_PATH).unwrap(), vfs_root.join(VFS_SOUNDBANKS_PATH).unwrap(), config))));
+    let sound_engine = Box::new(Rc::new(RefCell::new(SoundEngine::new(sound_library, config, clock))));
     let sound_engine: &'static mut Rc<RefCell<SoundEngine>> = Box::leak(sound_engine);
     sound_engine
 }
 
-fn create_phone(config: &Rc<CursedConfig>, sound_engine: &Rc<RefCell<SoundEngine>>) -> &'static mut PhoneEngine {
+fn create_phone(config: &Rc<CursedConfig>, clock: &Arc<dyn Clock>, sound_engine: &Rc<RefCell<SoundEngine>>) -> &'static mut PhoneEngine {
     info!("Loading phone engine... ");
-    let phone_engine = Box::new(PhoneEngine::new(config, sound_engine));
+    let phone_engine = Box::new(PhoneEngine::new(config, clock, sound_engine));
     let phone_engine: &'static mut PhoneEngine = Box::leak(phone_engine);
     phone_engine
-}
\ No newline at end of file
+}
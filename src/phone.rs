@@ -3,11 +3,12 @@
 
 use std::rc::Rc;
 use std::cell::RefCell;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::{time, sync::mpsc, thread, io::{stdin, Read}};
 use log::{info, trace, warn};
 use logos::{Logos, Lexer};
 use mlua::prelude::LuaUserData;
+use crate::clock::Clock;
 use crate::config::*;
 use crate::sound::*;
 
@@ -29,6 +30,8 @@ pub enum PhoneInputSignal {
 /// Represents signals produced by the phone.
 pub enum PhoneOutputSignal {
     Ring(Option<Arc<RingPattern>>),
+    /// Turns the message-waiting lamp on or off.
+    MessageWaiting(bool),
 }
 
 pub enum PhoneState {
@@ -110,6 +113,32 @@ impl RingPattern {
             components
         })
     }
+
+    /// Gets the length of the first ring, up to the first time the ringer is set low.
+    pub fn first_ring_duration(&self) -> Duration {
+        self.components.iter()
+            .take_while(|component| !matches!(component, RingPatternComponent::Low(_) | RingPatternComponent::End))
+            .map(|component| match component {
+                RingPatternComponent::RingWithCycle { duration, .. } => *duration,
+                RingPatternComponent::RingWithFrequency { duration, .. } => *duration,
+                RingPatternComponent::Ring(duration) | RingPatternComponent::High(duration) => *duration,
+                _ => Duration::ZERO,
+            })
+            .sum()
+    }
+
+    /// Gets the length of one repetition of the pattern.
+    pub fn cycle_duration(&self) -> Duration {
+        self.components.iter()
+            .take_while(|component| **component != RingPatternComponent::End)
+            .map(|component| match component {
+                RingPatternComponent::RingWithCycle { duration, .. } => *duration,
+                RingPatternComponent::RingWithFrequency { duration, .. } => *duration,
+                RingPatternComponent::Ring(duration) | RingPatternComponent::Low(duration) | RingPatternComponent::High(duration) => *duration,
+                RingPatternComponent::End => Duration::ZERO,
+            })
+            .sum()
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -151,6 +180,10 @@ fn parse_ring_pattern_duration(lex: &mut Lexer<RingPatternToken>) -> Option<f64>
     Some(n)
 }
 
+/// Mock input senders for each line, in the order the lines were created.
+#[cfg(not(feature = "rpi"))]
+static MOCK_INPUT_SENDERS: Mutex<Vec<mpsc::Sender<PhoneInputSignal>>> = Mutex::new(Vec::new());
+
 /// Provides I/O handling and state management for host phone peripherals.
 pub struct PhoneEngine {
     dtmf_tone_duration: Duration,
@@ -171,9 +204,9 @@ pub struct PhoneEngine {
 impl PhoneEngine {
     /// Constructor for Phone on Raspberry Pi platforms.
     #[cfg(feature = "rpi")]
-    pub fn new(config: &Rc<CursedConfig>, sound_engine: &Rc<RefCell<SoundEngine>>) -> Self {
+    pub fn new(config: &Rc<CursedConfig>, clock: &Arc<dyn Clock>, sound_engine: &Rc<RefCell<SoundEngine>>) -> Self {
         let sound_engine = sound_engine.clone();
-        let mut gpio = PhoneGpioInterface::new(&config);
+        let mut gpio = PhoneGpioInterface::new(config, clock);
         let listener = gpio.listen().expect("Unable to initialize GPIO listener.");
         let tx_ringer = gpio.tx_ringer();
         Self {
@@ -199,19 +232,22 @@ impl PhoneEngine {
 
     /// Constructor for Phone on non-Pi platforms.
     #[cfg(not(feature = "rpi"))]
-    pub fn new(config: &Rc<CursedConfig>, sound_engine: &Rc<RefCell<SoundEngine>>) -> Self {
+    pub fn new(config: &Rc<CursedConfig>, _clock: &Arc<dyn Clock>, sound_engine: &Rc<RefCell<SoundEngine>>) -> Self {
         use log::warn;
         let sound_engine = sound_engine.clone();
-        // We won't use the JoinHandle here since it's frankly pretty useless in this case
-        let (_, listener) = PhoneEngine::create_mock_input_thread();
+        // The JoinHandle is only returned to the first line, which prints the instructions
+        let (mock_thread, listener) = PhoneEngine::create_mock_input_thread();
 
-        info!("Mock input is enabled. To send inputs, type a sequence of the following characters and press Enter:");
-        info!("  - i: Off-hook signal");
-        info!("  - o: On-hook signal");
-        info!("  - w/r: Dial rest open/close");
-        info!("  - e: Rotary dial pulse (full cycle)");
-        info!("  - f/g/h/j: Insert 1¢/5¢/10¢/25¢");
-        info!("  - 0-9, A-D, #, *: Dial digit");
+        if mock_thread.is_some() {
+            info!("Mock input is enabled. To send inputs, type a sequence of the following characters and press Enter:");
+            info!("  - i: Off-hook signal");
+            info!("  - o: On-hook signal");
+            info!("  - w/r: Dial rest open/close");
+            info!("  - e: Rotary dial pulse (full cycle)");
+            info!("  - f/g/h/j: Insert 1¢/5¢/10¢/25¢");
+            info!("  - 0-9, A-D, #, *: Dial digit");
+            info!("  - ~1-9: Send the following inputs to the specified line");
+        }
 
         Self {
             sound_engine,
@@ -234,33 +270,55 @@ impl PhoneEngine {
     }
 
     #[cfg(not(feature = "rpi"))]
-    fn create_mock_input_thread() -> (thread::JoinHandle<()>, mpsc::Receiver<PhoneInputSignal>) {
+    fn create_mock_input_thread() -> (Option<thread::JoinHandle<()>>, mpsc::Receiver<PhoneInputSignal>) {
         let (tx, rx) = mpsc::channel();
+        let mut senders = MOCK_INPUT_SENDERS.lock().unwrap();
+        senders.push(tx);
+        // All lines share a single stdin reader
+        if senders.len() > 1 {
+            return (None, rx)
+        }
         let thread = thread::spawn(move || {
             let input = stdin();
             let mut reader = input.lock();
             let mut cbuf = [0u8];
+            let mut line_index = 0;
+            let mut selecting_line = false;
+            let send = |line_index: usize, signal: PhoneInputSignal| {
+                if let Some(tx) = MOCK_INPUT_SENDERS.lock().unwrap().get(line_index) {
+                    tx.send(signal).unwrap();
+                }
+            };
             while let Ok(_) = reader.read(&mut cbuf) {
-                match (cbuf[0] as char).to_ascii_lowercase() {
-                    'i' => tx.send(PhoneInputSignal::HookState(false)).unwrap(),
-                    'o' => tx.send(PhoneInputSignal::HookState(true)).unwrap(),
+                let c = (cbuf[0] as char).to_ascii_lowercase();
+                if selecting_line {
+                    selecting_line = false;
+                    if let Some(line_number) = c.to_digit(10).filter(|n| *n > 0) {
+                        line_index = line_number as usize - 1;
+                        continue
+                    }
+                }
+                match c {
+                    '~' => selecting_line = true,
+                    'i' => send(line_index, PhoneInputSignal::HookState(false)),
+                    'o' => send(line_index, PhoneInputSignal::HookState(true)),
                     'w' => {                        
-                        tx.send(PhoneInputSignal::RotaryDialRest(false)).unwrap();
+                        send(line_index, PhoneInputSignal::RotaryDialRest(false));
                         thread::sleep(time::Duration::from_millis(350));
                     },
                     'e' => {
-                        tx.send(PhoneInputSignal::RotaryDialPulse).unwrap();
+                        send(line_index, PhoneInputSignal::RotaryDialPulse);
                         thread::sleep(time::Duration::from_millis(80));
                     }
-                    'r' => tx.send(PhoneInputSignal::RotaryDialRest(true)).unwrap(),
+                    'r' => send(line_index, PhoneInputSignal::RotaryDialRest(true)),
                     digit @ '0'..='9' | digit @ 'a'..='d' | digit @ '*' | digit @ '#' => {
                         thread::sleep(time::Duration::from_millis(200));
-                        tx.send(PhoneInputSignal::Digit(digit.to_ascii_uppercase())).unwrap();
+                        send(line_index, PhoneInputSignal::Digit(digit.to_ascii_uppercase()));
                     },
-                    'f' => tx.send(PhoneInputSignal::Coin(1)).unwrap(),
-                    'g' => tx.send(PhoneInputSignal::Coin(5)).unwrap(),
-                    'h' => tx.send(PhoneInputSignal::Coin(10)).unwrap(),
-                    'j' => tx.send(PhoneInputSignal::Coin(25)).unwrap(),
+                    'f' => send(line_index, PhoneInputSignal::Coin(1)),
+                    'g' => send(line_index, PhoneInputSignal::Coin(5)),
+                    'h' => send(line_index, PhoneInputSignal::Coin(10)),
+                    'j' => send(line_index, PhoneInputSignal::Coin(25)),
                     '-' => thread::sleep(time::Duration::from_millis(250)),
                     '_' => thread::sleep(time::Duration::from_millis(500)),
                     '.' => thread::sleep(time::Duration::from_millis(1000)),
@@ -268,7 +326,7 @@ impl PhoneEngine {
                 };
             }
         });
-        (thread, rx)
+        (Some(thread), rx)
     }
 }
 
@@ -319,6 +377,12 @@ impl PhoneEngine {
                             }
                         }
                     },
+                    MessageWaiting(lit) => {
+                        trace!("Message-waiting lamp: {}", lit);
+
+                        #[cfg(feature = "rpi")]
+                        self.gpio.set_mwi_lamp(lit);
+                    },
                 }
             }
         }
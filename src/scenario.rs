@@ -0,0 +1,469 @@
+use crate::clock::*;
+use crate::config::*;
+use crate::engine::{PhoneLineState, LineId, CallRecord};
+use crate::phone::*;
+use crate::sound::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::time::Duration;
+use serde::Deserialize;
+use vfs::{MemoryFS, OverlayFS, VfsPath};
+
+/// Time between the dial leaving rest and its first pulse, on top of the configured first pulse delay.
+const PULSE_LEAD_MS: u64 = 100;
+/// Time between pulses of a rotary dial running at 10 PPS.
+const PULSE_INTERVAL_MS: u64 = 100;
+/// Time between the dial returning to rest and being lifted for the next digit.
+const PULSE_INTERDIGIT_MS: u64 = 500;
+
+/// A scripted call made against the engine using simulated time.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Scenario {
+    /// Optional description printed alongside the result.
+    description: Option<String>,
+    /// Path to the config the scenario is based on. Defaults to the regular config path.
+    config: Option<String>,
+    /// Config values that replace those in the base config.
+    #[serde(default)]
+    overrides: toml::Table,
+    /// Path to record the mixed audio output of the scenario to.
+    record: Option<String>,
+    /// The timeline of the scenario.
+    #[serde(default, rename = "step")]
+    steps: Vec<ScenarioStep>,
+}
+
+/// A point on the scenario timeline.
+///
+/// Expectations are checked before the inputs of the step are sent to the engine.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ScenarioStep {
+    /// Time of the step (in seconds since the start of the scenario).
+    at: f64,
+    /// Name of the line that the step applies to. Defaults to the first line.
+    line: Option<String>,
+    /// Moves the handset on or off the hook.
+    hook: Option<HookAction>,
+    /// Touch-tone digits to dial.
+    dial: Option<String>,
+    /// Digits to dial on the rotary dial. The pulses are spread out after the step.
+    pulse: Option<String>,
+    /// Deposits a coin of the specified value (in cents).
+    coin: Option<u32>,
+    /// Expected line state.
+    state: Option<PhoneLineState>,
+    /// Expected states entered since the previous step, in order.
+    transitions: Option<Vec<PhoneLineState>>,
+    /// Expected other party name (`line:<name>` for another line), or an empty string for no other party.
+    other_party: Option<String>,
+    /// Expected ringer state: `true`/`false`, or a ring pattern expression.
+    ring: Option<RingExpectation>,
+    /// Expected state of the message-waiting lamp.
+    message_waiting: Option<bool>,
+    /// Sounds that are expected to have been played since the previous step.
+    #[serde(default)]
+    sounds: Vec<SoundExpectation>,
+    /// Calls that are expected to have ended since the previous step.
+    #[serde(default)]
+    call_records: Vec<CallRecordExpectation>,
+    /// Resource files to write, by path. Written files are kept in memory and hide resources with the same path.
+    #[serde(default)]
+    write_files: BTreeMap<String, String>,
+    /// Paths of resource files to delete.
+    #[serde(default)]
+    delete_files: Vec<String>,
+}
+
+#[derive(Deserialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum HookAction {
+    On,
+    Off,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RingExpectation {
+    Ringing(bool),
+    Pattern(String),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct SoundExpectation {
+    channel: Channel,
+    /// Sound key or glob. Generated tones are named with an `@` prefix (e.g. `@dial`, `@busy`).
+    name: String,
+}
+
+/// Fields of a call detail record. Fields that aren't specified match any value, and an empty string matches a missing value.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct CallRecordExpectation {
+    direction: Option<String>,
+    agent: Option<String>,
+    dialed: Option<String>,
+    disconnect_cause: Option<String>,
+}
+
+impl CallRecordExpectation {
+    fn matches(&self, record: &CallRecord) -> bool {
+        let field_matches = |expected: &Option<String>, value: Option<&str>| match expected {
+            Some(expected) => expected == value.unwrap_or_default(),
+            None => true
+        };
+        field_matches(&self.direction, Some(record.direction.as_str()))
+            && field_matches(&self.agent, record.agent.as_deref())
+            && field_matches(&self.dialed, record.dialed.as_deref())
+            && field_matches(&self.disconnect_cause, Some(record.disconnect_cause.as_str()))
+    }
+}
+
+enum TimelineEvent {
+    Check(usize),
+    Input(LineId, PhoneInputSignal),
+    EditFiles(usize),
+}
+
+/// Everything observed from a line since its last step.
+#[derive(Default)]
+struct Observations {
+    transitions: Vec<PhoneLineState>,
+    sounds: Vec<SoundPlayRecord>,
+    call_records: Vec<CallRecord>,
+}
+
+/// Stands in for the host phone of a line.
+struct LineHarness {
+    input_tx: mpsc::Sender<PhoneInputSignal>,
+    output_rx: mpsc::Receiver<PhoneOutputSignal>,
+    sound_engine: Rc<RefCell<SoundEngine>>,
+    ring_pattern: Option<Arc<RingPattern>>,
+    message_waiting: bool,
+    observations: Observations,
+}
+
+/// Runs all scenarios matching the specified paths or glob patterns and prints the results.
+///
+/// Returns `true` if every scenario passed.
+pub fn run_scenarios(patterns: &[String]) -> bool {
+    let mut paths = vec![];
+    for pattern in patterns {
+        match glob::glob(pattern) {
+            Ok(walker) => paths.extend(walker.filter_map(|entry| entry.ok()).map(|path| path.to_string_lossy().into_owned())),
+            Err(_) => paths.push(pattern.clone()),
+        }
+    }
+
+    if paths.is_empty() {
+        println!("No scenarios found.");
+        return false
+    }
+
+    let mut passed_count = 0;
+    for path in paths.iter() {
+        let failures = match load_scenario(path) {
+            Ok(scenario) => {
+                if let Some(description) = &scenario.description {
+                    println!("{}: {}", path, description);
+                }
+                run_scenario(&scenario)
+            },
+            Err(err) => vec![err],
+        };
+
+        if failures.is_empty() {
+            passed_count += 1;
+            println!("PASS {}", path);
+        } else {
+            println!("FAIL {}", path);
+            for failure in failures.iter() {
+                println!("    {}", failure);
+            }
+        }
+    }
+
+    println!("{} passed, {} failed", passed_count, paths.len() - passed_count);
+    passed_count == paths.len()
+}
+
+fn load_scenario(path: &str) -> Result<Scenario, String> {
+    let scenario_str = fs::read_to_string(path).map_err(|err| format!("unable to read scenario: {}", err))?;
+    let scenario: Scenario = toml::from_str(&scenario_str).map_err(|err| format!("unable to parse scenario: {}", err))?;
+    for (i, step) in scenario.steps.iter().enumerate() {
+        if !step.at.is_finite() || step.at < 0.0 {
+            return Err(format!("step {}: invalid time", i + 1))
+        }
+        if let Some(RingExpectation::Pattern(expr)) = &step.ring {
+            if RingPattern::try_parse(expr).is_none() {
+                return Err(format!("step {}: invalid ring pattern: '{}'", i + 1, expr))
+            }
+        }
+    }
+    Ok(scenario)
+}
+
+/// Runs a scenario and returns a list of failed expectations.
+fn run_scenario(scenario: &Scenario) -> Vec<String> {
+    let config_path = scenario.config.clone().unwrap_or_else(crate::get_config_path);
+    let mut config = crate::load_config(&config_path, &scenario.overrides);
+    config.clock = ClockMode::Simulated;
+    // Keep scenarios from reading or overwriting the data of the real installation
+    if !scenario.overrides.contains_key("data-dir") {
+        config.data_dir = None;
+    }
+    match &scenario.record {
+        Some(record_path) => {
+            config.sound.output = SoundOutputType::Wav;
+            config.sound.output_path = Some(record_path.clone());
+        },
+        None => config.sound.output = SoundOutputType::Null,
+    }
+    let config = Rc::new(config);
+
+    let simulated_clock = Arc::new(SimulatedClock::new());
+    let clock = Arc::clone(&simulated_clock) as Arc<dyn Clock>;
+    // Files written by the scenario go to memory, in front of the resources
+    let vfs_root: VfsPath = OverlayFS::new(&[MemoryFS::new().into(), crate::create_virtual_filesystem(&config)]).into();
+    let sound_library = crate::create_sound_library(&config, &vfs_root);
+    let line_configs = crate::create_line_configs(&config);
+    let sound_engines: Vec<_> = line_configs.iter().map(|(_, line_config)| crate::create_sound_engine(line_config, &clock, &sound_library)).collect();
+    let engine = crate::create_cursed_engine(&config, &clock, &sound_library, &line_configs, &sound_engines, &vfs_root);
+    let mut harnesses: Vec<LineHarness> = sound_engines.iter().enumerate().map(|(line_id, sound_engine)| {
+        let (input_tx, input_rx) = mpsc::channel();
+        engine.listen(line_id, input_rx);
+        sound_engine.borrow().set_play_logging(true);
+        engine.lines()[line_id].set_state_logging(true);
+        engine.lines()[line_id].set_call_record_logging(true);
+        LineHarness {
+            input_tx,
+            output_rx: engine.gen_engine_output(line_id),
+            sound_engine: Rc::clone(sound_engine),
+            ring_pattern: None,
+            message_waiting: false,
+            observations: Default::default(),
+        }
+    }).collect();
+    if let Err(err) = engine.load_lua_api() {
+        return vec![format!("failed to load Lua API: {}", err)]
+    }
+    engine.load_agents();
+
+    let timeline = match build_timeline(scenario, &line_configs) {
+        Ok(timeline) => timeline,
+        Err(err) => return vec![err]
+    };
+    let tick_interval = Duration::from_secs_f64(1.0f64 / config.tick_rate);
+    let mut failures = vec![];
+    let mut next_event_index = 0;
+
+    while next_event_index < timeline.len() {
+        // Run all events that are due
+        let now = simulated_clock.elapsed();
+        while let Some((event_time, event)) = timeline.get(next_event_index) {
+            if *event_time > now { break }
+            match event {
+                TimelineEvent::Check(step_index) => {
+                    let step = &scenario.steps[*step_index];
+                    let step_name = format!("step {} (t = {}s)", step_index + 1, step.at);
+                    let line_id = find_line(&line_configs, step.line.as_deref()).unwrap_or_default();
+                    let line = &engine.lines()[line_id];
+                    let harness = &mut harnesses[line_id];
+                    // Other lines are named with a `line:` prefix so that they can't be mistaken for agents
+                    let other_party_name = line.other_party_name()
+                        .or_else(|| line.other_line().map(|other_line_id| format!("line:{}", engine.lines()[other_line_id].name())));
+                    check_step(step, &harness.observations, line.state(), other_party_name, harness.ring_pattern.as_deref(), harness.message_waiting)
+                        .into_iter()
+                        .for_each(|failure| failures.push(format!("{}: {}", step_name, failure)));
+                    harness.observations = Observations::default();
+                },
+                TimelineEvent::Input(line_id, signal) => {
+                    harnesses[*line_id].input_tx.send(*signal).ok();
+                },
+                TimelineEvent::EditFiles(step_index) => {
+                    if let Err(err) = edit_files(&vfs_root, &scenario.steps[*step_index]) {
+                        failures.push(format!("step {} (t = {}s): {}", step_index + 1, scenario.steps[*step_index].at, err));
+                    }
+                }
+            }
+            next_event_index += 1;
+        }
+
+        engine.tick();
+
+        // Collect everything the engine did during the tick
+        for (line, harness) in engine.lines().iter().zip(harnesses.iter_mut()) {
+            harness.sound_engine.borrow().tick();
+            harness.observations.transitions.extend(line.drain_state_log());
+            harness.observations.sounds.extend(harness.sound_engine.borrow().drain_play_log());
+            harness.observations.call_records.extend(line.drain_call_record_log());
+            while let Ok(signal) = harness.output_rx.try_recv() {
+                match signal {
+                    PhoneOutputSignal::Ring(pattern) => harness.ring_pattern = pattern,
+                    PhoneOutputSignal::MessageWaiting(lit) => harness.message_waiting = lit,
+                }
+            }
+        }
+
+        simulated_clock.advance(tick_interval);
+    }
+
+    for harness in harnesses.iter() {
+        harness.sound_engine.borrow().flush_output();
+    }
+    failures
+}
+
+/// Finds the index of the line with the specified name, or the first line if no name is specified.
+fn find_line(line_configs: &[(LineConfig, Rc<CursedConfig>)], name: Option<&str>) -> Option<LineId> {
+    match name {
+        Some(name) => line_configs.iter().position(|(line, _)| line.name == name),
+        None => Some(0)
+    }
+}
+
+/// Converts the steps of a scenario into a list of timed events.
+fn build_timeline(scenario: &Scenario, line_configs: &[(LineConfig, Rc<CursedConfig>)]) -> Result<Vec<(Duration, TimelineEvent)>, String> {
+    let pulse_interval = Duration::from_millis(PULSE_INTERVAL_MS);
+    let interdigit_delay = Duration::from_millis(PULSE_INTERDIGIT_MS);
+    let mut timeline = vec![];
+
+    for (step_index, step) in scenario.steps.iter().enumerate() {
+        let step_time = Duration::from_secs_f64(step.at);
+        let line_id = find_line(line_configs, step.line.as_deref())
+            .ok_or_else(|| format!("step {}: no line named '{}'", step_index + 1, step.line.as_deref().unwrap_or_default()))?;
+        let config = &line_configs[line_id].1;
+        let first_pulse_delay = Duration::from_millis(config.rotary.first_pulse_delay_ms.unwrap_or_default() + PULSE_LEAD_MS);
+        timeline.push((step_time, TimelineEvent::Check(step_index)));
+
+        if !step.write_files.is_empty() || !step.delete_files.is_empty() {
+            timeline.push((step_time, TimelineEvent::EditFiles(step_index)));
+        }
+
+        if let Some(hook) = step.hook {
+            timeline.push((step_time, TimelineEvent::Input(line_id, PhoneInputSignal::HookState(hook == HookAction::On))));
+        }
+
+        if let Some(cents) = step.coin {
+            timeline.push((step_time, TimelineEvent::Input(line_id, PhoneInputSignal::Coin(cents))));
+        }
+
+        if let Some(digits) = &step.dial {
+            for digit in digits.chars() {
+                timeline.push((step_time, TimelineEvent::Input(line_id, PhoneInputSignal::Digit(digit))));
+            }
+        }
+
+        if let Some(digits) = &step.pulse {
+            let mut time = step_time;
+            for digit in digits.chars() {
+                let pulse_count = config.rotary.digit_layout.chars().position(|c| c == digit).map_or(0, |i| i + 1);
+                timeline.push((time, TimelineEvent::Input(line_id, PhoneInputSignal::RotaryDialRest(false))));
+                time += first_pulse_delay;
+                for _ in 0..pulse_count {
+                    timeline.push((time, TimelineEvent::Input(line_id, PhoneInputSignal::RotaryDialPulse)));
+                    time += pulse_interval;
+                }
+                timeline.push((time, TimelineEvent::Input(line_id, PhoneInputSignal::RotaryDialRest(true))));
+                time += interdigit_delay;
+            }
+        }
+    }
+
+    // Stable sort keeps each step's check ahead of its inputs
+    timeline.sort_by_key(|(time, _)| *time);
+    Ok(timeline)
+}
+
+/// Writes and deletes the resource files listed in a step.
+fn edit_files(vfs_root: &VfsPath, step: &ScenarioStep) -> Result<(), String> {
+    for (path, contents) in step.write_files.iter() {
+        vfs_root.join(path)
+            .and_then(|file_path| file_path.create_file())
+            .and_then(|mut file| file.write_all(contents.as_bytes()).map_err(Into::into))
+            .map_err(|err| format!("unable to write '{}': {}", path, err))?;
+    }
+    for path in step.delete_files.iter() {
+        vfs_root.join(path)
+            .and_then(|file_path| file_path.remove_file())
+            .map_err(|err| format!("unable to delete '{}': {}", path, err))?;
+    }
+    Ok(())
+}
+
+fn check_step(step: &ScenarioStep, observations: &Observations, state: PhoneLineState, other_party: Option<String>, ring_pattern: Option<&RingPattern>, message_waiting: bool) -> Vec<String> {
+    let mut failures = vec![];
+
+    if let Some(expected_state) = step.state {
+        if state != expected_state {
+            failures.push(format!("expected state {:?}, found {:?}", expected_state, state));
+        }
+    }
+
+    if let Some(expected_transitions) = &step.transitions {
+        if *expected_transitions != observations.transitions {
+            failures.push(format!("expected transitions {:?}, found {:?}", expected_transitions, observations.transitions));
+        }
+    }
+
+    if let Some(expected_other_party) = &step.other_party {
+        let other_party = other_party.unwrap_or_default();
+        if *expected_other_party != other_party {
+            failures.push(format!("expected other party '{}', found '{}'", expected_other_party, other_party));
+        }
+    }
+
+    match (&step.ring, ring_pattern) {
+        (Some(RingExpectation::Ringing(true)), None) => failures.push("expected ringer to be on".to_owned()),
+        (Some(RingExpectation::Ringing(false)), Some(_)) => failures.push("expected ringer to be off".to_owned()),
+        (Some(RingExpectation::Pattern(expr)), ring_pattern) => {
+            let expected_components = RingPattern::try_parse(expr).map(|pattern| pattern.components);
+            if expected_components.as_ref() != ring_pattern.map(|pattern| &pattern.components) {
+                failures.push(format!("expected ring pattern '{}', found {:?}", expr, ring_pattern.map(|pattern| &pattern.components)));
+            }
+        },
+        _ => {}
+    }
+
+    if let Some(expected_message_waiting) = step.message_waiting {
+        if expected_message_waiting != message_waiting {
+            failures.push(format!("expected message-waiting lamp to be {}", if expected_message_waiting { "on" } else { "off" }));
+        }
+    }
+
+    for expected_sound in step.sounds.iter() {
+        let matcher = globset::GlobBuilder::new(&expected_sound.name)
+            .literal_separator(true)
+            .build()
+            .map(|glob| glob.compile_matcher());
+        let was_played = observations.sounds.iter().any(|record| {
+            record.channel == expected_sound.channel && match &matcher {
+                Ok(matcher) => matcher.is_match(&record.name),
+                Err(_) => record.name == expected_sound.name,
+            }
+        });
+        if !was_played {
+            let played_on_channel: Vec<&str> = observations.sounds.iter()
+                .filter(|record| record.channel == expected_sound.channel)
+                .map(|record| record.name.as_str())
+                .collect();
+            failures.push(format!("expected sound '{}' on {:?}, found {:?}", expected_sound.name, expected_sound.channel, played_on_channel));
+        }
+    }
+
+    for expected_record in step.call_records.iter() {
+        if !observations.call_records.iter().any(|record| expected_record.matches(record)) {
+            let records: Vec<String> = observations.call_records.iter().map(|record| record.to_json()).collect();
+            failures.push(format!("expected call record {:?}, found {:?}", expected_record, records));
+        }
+    }
+
+    failures
+}
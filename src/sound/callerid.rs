@@ -0,0 +1,263 @@
+use std::f32::consts::TAU;
+use std::time::Duration;
+use chrono::prelude::*;
+use rodio::buffer::SamplesBuffer;
+use crate::config::CallerIdFormat;
+use super::{DTMF_DIGITS, DTMF_ROW_FREQUENCIES, DTMF_COLUMN_FREQUENCIES};
+
+/// Sample rate of generated caller ID audio.
+const CALLER_ID_SAMPLE_RATE: u32 = 48000;
+
+/// Bell 202 bit rate.
+const FSK_BAUD: f32 = 1200.0;
+/// Bell 202 mark (binary 1) frequency.
+const FSK_MARK_FREQ: f32 = 1200.0;
+/// Bell 202 space (binary 0) frequency.
+const FSK_SPACE_FREQ: f32 = 2200.0;
+/// Number of alternating bits in the channel seizure signal.
+const FSK_SEIZURE_BITS: usize = 300;
+/// Number of mark bits between the channel seizure signal and the message.
+const FSK_MARK_BITS: usize = 180;
+/// Number of mark bits sent after the message so the last byte isn't cut off.
+const FSK_TRAILING_MARK_BITS: usize = 10;
+
+const SDMF_MESSAGE_TYPE: u8 = 0x04;
+const MDMF_MESSAGE_TYPE: u8 = 0x80;
+const MDMF_PARAM_DATE_TIME: u8 = 0x01;
+const MDMF_PARAM_NUMBER: u8 = 0x02;
+const MDMF_PARAM_NUMBER_ABSENT: u8 = 0x04;
+const MDMF_PARAM_NAME: u8 = 0x07;
+const MDMF_PARAM_NAME_ABSENT: u8 = 0x08;
+/// Reason code for a number or name that isn't available.
+const CALLER_ID_UNAVAILABLE: u8 = b'O';
+const MAX_NUMBER_LENGTH: usize = 10;
+const MAX_NAME_LENGTH: usize = 15;
+
+/// Length of each DTMF caller ID tone, and of the gaps between them.
+const DTMF_CALLER_ID_TONE_DURATION: Duration = Duration::from_millis(70);
+/// Digit sent before the number in DTMF caller ID.
+const DTMF_CALLER_ID_START: char = 'A';
+/// Digit sent after the number in DTMF caller ID.
+const DTMF_CALLER_ID_END: char = 'C';
+
+/// Information about a caller, sent to the phone between rings.
+pub struct CallerIdInfo {
+    pub name: Option<String>,
+    pub number: Option<String>,
+    pub time: DateTime<Local>,
+}
+
+impl CallerIdInfo {
+    /// Gets the number's digits, up to the maximum length that caller ID displays.
+    fn number_digits(&self) -> Option<String> {
+        let digits: String = self.number.as_deref()?.chars().filter(|c| c.is_ascii_digit()).take(MAX_NUMBER_LENGTH).collect();
+        if digits.is_empty() { None } else { Some(digits) }
+    }
+
+    /// Gets the name as printable ASCII, up to the maximum length that caller ID displays.
+    fn display_name(&self) -> Option<String> {
+        let name: String = self.name.as_deref()?.chars().filter(|c| c.is_ascii_graphic() || *c == ' ').take(MAX_NAME_LENGTH).collect();
+        if name.is_empty() { None } else { Some(name) }
+    }
+
+    fn date_time(&self) -> String {
+        self.time.format("%m%d%H%M").to_string()
+    }
+}
+
+/// Encodes a Single Data Message Format (SDMF) message, including its checksum.
+pub fn encode_sdmf(info: &CallerIdInfo) -> Vec<u8> {
+    let mut body = info.date_time().into_bytes();
+    match info.number_digits() {
+        Some(number) => body.extend(number.bytes()),
+        None => body.push(CALLER_ID_UNAVAILABLE)
+    }
+    frame_message(SDMF_MESSAGE_TYPE, body)
+}
+
+/// Encodes a Multiple Data Message Format (MDMF) message, including its checksum.
+pub fn encode_mdmf(info: &CallerIdInfo) -> Vec<u8> {
+    let mut body = vec![];
+    let mut push_param = |param: u8, data: &[u8]| {
+        body.push(param);
+        body.push(data.len() as u8);
+        body.extend_from_slice(data);
+    };
+    push_param(MDMF_PARAM_DATE_TIME, info.date_time().as_bytes());
+    match info.number_digits() {
+        Some(number) => push_param(MDMF_PARAM_NUMBER, number.as_bytes()),
+        None => push_param(MDMF_PARAM_NUMBER_ABSENT, &[CALLER_ID_UNAVAILABLE])
+    }
+    match info.display_name() {
+        Some(name) => push_param(MDMF_PARAM_NAME, name.as_bytes()),
+        None => push_param(MDMF_PARAM_NAME_ABSENT, &[CALLER_ID_UNAVAILABLE])
+    }
+    frame_message(MDMF_MESSAGE_TYPE, body)
+}
+
+/// Adds the message type, length and checksum (the two's complement of the sum of all other bytes) to a message body.
+fn frame_message(message_type: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut message = Vec::with_capacity(body.len() + 3);
+    message.push(message_type);
+    message.push(body.len() as u8);
+    message.extend(body);
+    let sum = message.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    message.push(sum.wrapping_neg());
+    message
+}
+
+/// Gets the bits sent for a message: the channel seizure signal, the mark signal, then each byte framed by a start and stop bit.
+fn fsk_bits(message: &[u8]) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(FSK_SEIZURE_BITS + FSK_MARK_BITS + message.len() * 10 + FSK_TRAILING_MARK_BITS);
+    bits.extend((0..FSK_SEIZURE_BITS).map(|i| i % 2 == 1));
+    bits.resize(bits.len() + FSK_MARK_BITS, true);
+    for byte in message {
+        bits.push(false);
+        bits.extend((0..8).map(|i| (byte >> i) & 1 == 1));
+        bits.push(true);
+    }
+    bits.resize(bits.len() + FSK_TRAILING_MARK_BITS, true);
+    bits
+}
+
+/// Renders a message as phase-continuous Bell 202 FSK.
+pub fn render_fsk(message: &[u8], sample_rate: u32) -> Vec<f32> {
+    let bits = fsk_bits(message);
+    let samples_per_bit = sample_rate as f32 / FSK_BAUD;
+    let sample_count = (bits.len() as f32 * samples_per_bit).ceil() as usize;
+    let mut samples = Vec::with_capacity(sample_count);
+    let mut phase = 0.0f32;
+    for i in 0..sample_count {
+        let bit = bits[((i as f32 / samples_per_bit) as usize).min(bits.len() - 1)];
+        let freq = if bit { FSK_MARK_FREQ } else { FSK_SPACE_FREQ };
+        phase = (phase + TAU * freq / sample_rate as f32) % TAU;
+        samples.push(phase.sin());
+    }
+    samples
+}
+
+/// Renders the caller's number as DTMF digits, framed by the start and end digits.
+pub fn render_dtmf(info: &CallerIdInfo, sample_rate: u32) -> Vec<f32> {
+    let digits = format!("{}{}{}", DTMF_CALLER_ID_START, info.number_digits().unwrap_or_default(), DTMF_CALLER_ID_END);
+    let tone_samples = (DTMF_CALLER_ID_TONE_DURATION.as_secs_f32() * sample_rate as f32) as usize;
+    let mut samples = Vec::with_capacity(digits.len() * tone_samples * 2);
+    for digit in digits.chars() {
+        let index = match DTMF_DIGITS.iter().position(|d| *d == digit) {
+            Some(index) => index,
+            None => continue
+        };
+        let row_freq = DTMF_ROW_FREQUENCIES[index / 4];
+        let col_freq = DTMF_COLUMN_FREQUENCIES[index % 4];
+        samples.extend((0..tone_samples).map(|i| {
+            let t = i as f32 / sample_rate as f32;
+            ((TAU * row_freq * t).sin() + (TAU * col_freq * t).sin()) * 0.5
+        }));
+        samples.resize(samples.len() + tone_samples, 0.0);
+    }
+    samples
+}
+
+/// Renders caller ID in the specified format.
+pub fn render_caller_id(info: &CallerIdInfo, format: CallerIdFormat, sample_rate: u32) -> Vec<f32> {
+    match format {
+        CallerIdFormat::Sdmf => render_fsk(&encode_sdmf(info), sample_rate),
+        CallerIdFormat::Mdmf => render_fsk(&encode_mdmf(info), sample_rate),
+        CallerIdFormat::Dtmf => render_dtmf(info, sample_rate),
+    }
+}
+
+/// Creates a playable source of caller ID in the specified format, at the specified amplitude.
+pub fn caller_id_source(info: &CallerIdInfo, format: CallerIdFormat, volume: f32) -> SamplesBuffer<f32> {
+    let samples: Vec<f32> = render_caller_id(info, format, CALLER_ID_SAMPLE_RATE).into_iter().map(|sample| sample * volume).collect();
+    SamplesBuffer::new(1, CALLER_ID_SAMPLE_RATE, samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use rodio::Source;
+
+    fn test_info() -> CallerIdInfo {
+        CallerIdInfo {
+            name: Some("Mr. Telephone".to_owned()),
+            number: Some("(555) 012-3456".to_owned()),
+            time: Local.with_ymd_and_hms(2024, 3, 5, 14, 7, 0).unwrap(),
+        }
+    }
+
+    /// Measures the power of a frequency in a block of samples.
+    fn tone_power(samples: &[f32], freq: f32, sample_rate: u32) -> f32 {
+        let (re, im) = samples.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, sample)| {
+            let phase = TAU * freq * i as f32 / sample_rate as f32;
+            (re + sample * phase.cos(), im + sample * phase.sin())
+        });
+        re * re + im * im
+    }
+
+    /// Demodulates Bell 202 FSK into bits by comparing the mark and space tones in each bit period.
+    fn demodulate(samples: &[f32], sample_rate: u32) -> Vec<bool> {
+        let samples_per_bit = sample_rate as f32 / FSK_BAUD;
+        let bit_count = (samples.len() as f32 / samples_per_bit) as usize;
+        (0..bit_count).map(|bit| {
+            let start = (bit as f32 * samples_per_bit).round() as usize;
+            let end = (((bit + 1) as f32 * samples_per_bit).round() as usize).min(samples.len());
+            let block = &samples[start..end];
+            tone_power(block, FSK_MARK_FREQ, sample_rate) > tone_power(block, FSK_SPACE_FREQ, sample_rate)
+        }).collect()
+    }
+
+    /// Decodes the bytes after the channel seizure and mark signals, checking that both are intact.
+    fn decode_message(bits: &[bool]) -> Vec<u8> {
+        let (seizure, rest) = bits.split_at(FSK_SEIZURE_BITS);
+        assert!(seizure.iter().enumerate().all(|(i, bit)| *bit == (i % 2 == 1)), "channel seizure signal isn't alternating");
+        let (marks, mut rest) = rest.split_at(FSK_MARK_BITS);
+        assert!(marks.iter().all(|bit| *bit), "mark signal contains a space");
+        let mut bytes = vec![];
+        // Each byte is a start bit, eight data bits (LSB first) and a stop bit
+        while let Some(start) = rest.iter().position(|bit| !*bit) {
+            if rest.len() < start + 10 { break }
+            let frame = &rest[start..start + 10];
+            assert!(frame[9], "missing stop bit after byte {}", bytes.len());
+            bytes.push(frame[1..9].iter().enumerate().fold(0u8, |byte, (i, bit)| byte | ((*bit as u8) << i)));
+            rest = &rest[start + 10..];
+        }
+        bytes
+    }
+
+    fn decode_source(format: CallerIdFormat) -> Vec<u8> {
+        let source = caller_id_source(&test_info(), format, 0.5);
+        let sample_rate = source.sample_rate();
+        let samples: Vec<f32> = source.collect();
+        decode_message(&demodulate(&samples, sample_rate))
+    }
+
+    fn assert_framing(message: &[u8], message_type: u8) {
+        assert_eq!(message[0], message_type);
+        assert_eq!(message[1] as usize, message.len() - 3, "length doesn't match the message body");
+        assert_eq!(message.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)), 0, "checksum doesn't match");
+    }
+
+    #[test]
+    fn sdmf_decodes_to_caller_info() {
+        let message = decode_source(CallerIdFormat::Sdmf);
+        assert_framing(&message, SDMF_MESSAGE_TYPE);
+        assert_eq!(&message[2..message.len() - 1], b"030514075550123456");
+    }
+
+    #[test]
+    fn mdmf_decodes_to_caller_info() {
+        let message = decode_source(CallerIdFormat::Mdmf);
+        assert_framing(&message, MDMF_MESSAGE_TYPE);
+        let mut params = HashMap::new();
+        let mut body = &message[2..message.len() - 1];
+        while !body.is_empty() {
+            let (param, len) = (body[0], body[1] as usize);
+            params.insert(param, body[2..2 + len].to_vec());
+            body = &body[2 + len..];
+        }
+        assert_eq!(params[&MDMF_PARAM_DATE_TIME], b"03051407");
+        assert_eq!(params[&MDMF_PARAM_NUMBER], b"5550123456");
+        assert_eq!(params[&MDMF_PARAM_NAME], b"Mr. Telephone");
+    }
+}
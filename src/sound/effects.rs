@@ -0,0 +1,588 @@
+use std::f32::consts::TAU;
+use std::sync::Arc;
+use std::time::Duration;
+use rodio::Source;
+use super::{db_to_amp, SharedSettings};
+
+/// Longest delay (in seconds) of the echo effect.
+const MAX_ECHO_DELAY: f32 = 2.0;
+/// Length (in seconds) of the grains that the pitch shifter crossfades between.
+const PITCH_SHIFT_WINDOW: f32 = 0.05;
+/// Comb filter delays (in samples at 44.1 kHz) of the reverb, from Freeverb.
+const REVERB_COMB_DELAYS: [usize; 4] = [1116, 1188, 1277, 1356];
+/// All-pass filter delays (in samples at 44.1 kHz) of the reverb, from Freeverb.
+const REVERB_ALLPASS_DELAYS: [usize; 2] = [556, 441];
+const REVERB_INPUT_GAIN: f32 = 0.015;
+const REVERB_WET_GAIN: f32 = 3.0;
+const REVERB_ALLPASS_FEEDBACK: f32 = 0.5;
+/// Level below which the echo and reverb tails are considered silent.
+const TAIL_SILENCE_LEVEL: f32 = 0.0001;
+/// Length (in seconds) of the tail played between checks for whether it has gone silent.
+const TAIL_CHECK_INTERVAL: f32 = 0.1;
+
+/// An effect that can be applied to a channel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EffectKind {
+    Reverb,
+    Echo,
+    PitchShift,
+    Bitcrush,
+    RingMod,
+    Distortion,
+}
+
+impl EffectKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "reverb" => EffectKind::Reverb,
+            "echo" => EffectKind::Echo,
+            "pitch_shift" => EffectKind::PitchShift,
+            "bitcrush" => EffectKind::Bitcrush,
+            "ring_mod" => EffectKind::RingMod,
+            "distortion" => EffectKind::Distortion,
+            _ => return None
+        })
+    }
+
+    /// Gets the names of the effect's parameters, along with their default values.
+    fn params(self) -> &'static [(&'static str, f32)] {
+        match self {
+            EffectKind::Reverb => &[("room_size", 0.5), ("damping", 0.5), ("mix", 0.3)],
+            EffectKind::Echo => &[("delay", 0.3), ("feedback", 0.4), ("mix", 0.5)],
+            EffectKind::PitchShift => &[("semitones", 0.0), ("mix", 1.0)],
+            EffectKind::Bitcrush => &[("bits", 8.0), ("downsample", 1.0), ("mix", 1.0)],
+            EffectKind::RingMod => &[("frequency", 30.0), ("mix", 1.0)],
+            EffectKind::Distortion => &[("drive", 20.0), ("mix", 1.0)],
+        }
+    }
+
+    fn param_index(self, param: &str) -> Result<usize, String> {
+        self.params().iter()
+            .position(|(name, _)| *name == param)
+            .ok_or_else(|| format!("{:?} has no parameter '{}'", self, param))
+    }
+}
+
+/// Parameters of an enabled effect, in the order given by [`EffectKind::params`].
+#[derive(Copy, Clone, Debug)]
+struct EffectParams([f32; 3]);
+
+impl EffectParams {
+    fn defaults(kind: EffectKind) -> Self {
+        let mut values = [0.0; 3];
+        for (value, (_, default)) in values.iter_mut().zip(kind.params()) {
+            *value = *default;
+        }
+        Self(values)
+    }
+}
+
+/// The effects enabled on a channel, and their parameters.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ChannelEffects {
+    reverb: Option<EffectParams>,
+    echo: Option<EffectParams>,
+    pitch_shift: Option<EffectParams>,
+    bitcrush: Option<EffectParams>,
+    ring_mod: Option<EffectParams>,
+    distortion: Option<EffectParams>,
+}
+
+impl ChannelEffects {
+    fn slot(&self, kind: EffectKind) -> &Option<EffectParams> {
+        match kind {
+            EffectKind::Reverb => &self.reverb,
+            EffectKind::Echo => &self.echo,
+            EffectKind::PitchShift => &self.pitch_shift,
+            EffectKind::Bitcrush => &self.bitcrush,
+            EffectKind::RingMod => &self.ring_mod,
+            EffectKind::Distortion => &self.distortion,
+        }
+    }
+
+    fn slot_mut(&mut self, kind: EffectKind) -> &mut Option<EffectParams> {
+        match kind {
+            EffectKind::Reverb => &mut self.reverb,
+            EffectKind::Echo => &mut self.echo,
+            EffectKind::PitchShift => &mut self.pitch_shift,
+            EffectKind::Bitcrush => &mut self.bitcrush,
+            EffectKind::RingMod => &mut self.ring_mod,
+            EffectKind::Distortion => &mut self.distortion,
+        }
+    }
+
+    pub fn is_enabled(&self, kind: EffectKind) -> bool {
+        self.slot(kind).is_some()
+    }
+
+    /// Enables an effect, or disables it. Newly enabled effects start with their default parameters.
+    pub fn set_enabled(&mut self, kind: EffectKind, enabled: bool) {
+        let slot = self.slot_mut(kind);
+        if !enabled {
+            *slot = None;
+        } else if slot.is_none() {
+            *slot = Some(EffectParams::defaults(kind));
+        }
+    }
+
+    /// Enables an effect and changes the specified parameters, keeping the others.
+    /// Nothing is changed if any of the parameters is invalid.
+    pub fn set_params(&mut self, kind: EffectKind, params: &[(String, f32)]) -> Result<(), String> {
+        let mut effects = *self;
+        effects.set_enabled(kind, true);
+        for (param, value) in params {
+            effects.set_param(kind, param.as_str(), *value)?;
+        }
+        *self = effects;
+        Ok(())
+    }
+
+    /// Returns `true` if any enabled effect keeps sounding after its input stops.
+    fn has_tail(&self) -> bool {
+        self.echo.is_some() || self.reverb.is_some() || self.pitch_shift.is_some()
+    }
+
+    /// Gets a parameter of an effect, or `None` if the effect isn't enabled.
+    pub fn param(&self, kind: EffectKind, param: &str) -> Result<Option<f32>, String> {
+        let index = kind.param_index(param)?;
+        Ok(self.slot(kind).map(|params| params.0[index]))
+    }
+
+    /// Sets a parameter of an enabled effect.
+    pub fn set_param(&mut self, kind: EffectKind, param: &str, value: f32) -> Result<(), String> {
+        let index = kind.param_index(param)?;
+        if !value.is_finite() {
+            return Err(format!("{:?} parameter '{}' must be a number", kind, param))
+        }
+        match self.slot_mut(kind) {
+            Some(params) => {
+                params.0[index] = value;
+                Ok(())
+            },
+            None => Err(format!("{:?} is not enabled on the channel", kind))
+        }
+    }
+}
+
+/// Effects of a channel, shared with every source playing on it.
+pub type SharedEffects = SharedSettings<ChannelEffects>;
+
+/// A circular buffer of past samples.
+struct DelayLine {
+    buffer: Vec<f32>,
+    write_pos: usize,
+}
+
+impl DelayLine {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len.max(2)],
+            write_pos: 0,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        self.buffer[self.write_pos] = sample;
+        self.write_pos = (self.write_pos + 1) % self.buffer.len();
+    }
+
+    /// Returns `true` if any of the last `len` samples is audible.
+    fn is_audible(&self, len: usize) -> bool {
+        let len = len.min(self.buffer.len());
+        (1..=len).any(|i| self.buffer[(self.write_pos + self.buffer.len() - i) % self.buffer.len()].abs() > TAIL_SILENCE_LEVEL)
+    }
+
+    /// Reads the sample written `delay` samples before the last one, interpolating between samples.
+    fn read(&self, delay: f32) -> f32 {
+        let len = self.buffer.len();
+        let delay = delay.clamp(0.0, (len - 2) as f32);
+        let whole = delay as usize;
+        let frac = delay - whole as f32;
+        let a = self.buffer[(self.write_pos + len * 2 - 1 - whole) % len];
+        let b = self.buffer[(self.write_pos + len * 2 - 2 - whole) % len];
+        a + (b - a) * frac
+    }
+}
+
+/// A Freeverb-style reverb: parallel comb filters followed by all-pass filters.
+struct Reverb {
+    combs: Vec<(DelayLine, f32)>,
+    allpasses: Vec<DelayLine>,
+}
+
+impl Reverb {
+    fn new(sample_rate: u32) -> Self {
+        let scale = |len: usize| len * sample_rate as usize / 44100;
+        Self {
+            combs: REVERB_COMB_DELAYS.iter().map(|len| (DelayLine::new(scale(*len)), 0.0)).collect(),
+            allpasses: REVERB_ALLPASS_DELAYS.iter().map(|len| DelayLine::new(scale(*len))).collect(),
+        }
+    }
+
+    fn process(&mut self, x: f32, room_size: f32, damping: f32) -> f32 {
+        let feedback = room_size.clamp(0.0, 1.0) * 0.28 + 0.7;
+        let damping = damping.clamp(0.0, 1.0);
+        let input = x * REVERB_INPUT_GAIN;
+        let mut out = 0.0;
+        for (comb, filter_store) in self.combs.iter_mut() {
+            let delayed = comb.read((comb.buffer.len() - 2) as f32);
+            *filter_store = delayed * (1.0 - damping) + *filter_store * damping;
+            comb.push(input + *filter_store * feedback);
+            out += delayed;
+        }
+        for allpass in self.allpasses.iter_mut() {
+            let delayed = allpass.read((allpass.buffer.len() - 2) as f32);
+            allpass.push(out + delayed * REVERB_ALLPASS_FEEDBACK);
+            out = delayed - out;
+        }
+        out * REVERB_WET_GAIN
+    }
+}
+
+/// Shifts pitch without changing tempo by reading a delay line at a different speed,
+/// crossfading between two read heads half a window apart.
+struct PitchShifter {
+    delay_line: DelayLine,
+    window: f32,
+    /// Position of the first read head within the window, from 0.0 to 1.0.
+    phase: f32,
+}
+
+impl PitchShifter {
+    fn new(sample_rate: u32) -> Self {
+        let window = PITCH_SHIFT_WINDOW * sample_rate as f32;
+        Self {
+            delay_line: DelayLine::new(window as usize + 3),
+            window,
+            phase: 0.0,
+        }
+    }
+
+    fn process(&mut self, x: f32, semitones: f32) -> f32 {
+        let ratio = 2.0f32.powf(semitones / 12.0);
+        self.delay_line.push(x);
+        self.phase = (self.phase + (1.0 - ratio) / self.window).rem_euclid(1.0);
+        let phase2 = (self.phase + 0.5) % 1.0;
+        // Triangular windows, so that the gains of the two heads always add up to 1
+        let a = self.delay_line.read(self.phase * self.window) * (1.0 - (2.0 * self.phase - 1.0).abs());
+        let b = self.delay_line.read(phase2 * self.window) * (1.0 - (2.0 * phase2 - 1.0).abs());
+        a + b
+    }
+}
+
+/// Effect processing state of one audio channel.
+#[derive(Default)]
+struct EffectState {
+    pitch_shifter: Option<PitchShifter>,
+    echo: Option<DelayLine>,
+    reverb: Option<Reverb>,
+    /// Sample held by the bitcrusher.
+    bitcrush_sample: f32,
+    /// Samples until the bitcrusher takes the next sample.
+    bitcrush_countdown: f32,
+}
+
+impl EffectState {
+    /// Returns `true` if the delayed sound of any effect is still audible.
+    fn is_ringing(&self, effects: &ChannelEffects, sample_rate: u32) -> bool {
+        let is_echo_ringing = match (effects.echo, &self.echo) {
+            (Some(EffectParams([delay, ..])), Some(echo)) => echo.is_audible((delay.clamp(0.0, MAX_ECHO_DELAY) * sample_rate as f32) as usize + 2),
+            _ => false
+        };
+        let is_reverb_ringing = self.reverb.as_ref().is_some_and(|reverb| {
+            reverb.combs.iter().any(|(comb, _)| comb.is_audible(comb.buffer.len()))
+                || reverb.allpasses.iter().any(|allpass| allpass.is_audible(allpass.buffer.len()))
+        });
+        let is_pitch_shifter_ringing = self.pitch_shifter.as_ref().is_some_and(|shifter| shifter.delay_line.is_audible(shifter.delay_line.buffer.len()));
+        is_echo_ringing || is_reverb_ringing || is_pitch_shifter_ringing
+    }
+}
+
+/// Wraps a source so that it plays through its channel's effects.
+///
+/// Once the source ends, the tails of delay-based effects play out until they go silent.
+pub struct EffectsSource<S> {
+    inner: S,
+    shared: Arc<SharedEffects>,
+    /// Version of the shared effects that were last loaded.
+    version: usize,
+    effects: ChannelEffects,
+    sample_rate: u32,
+    states: Vec<EffectState>,
+    /// Index of the audio channel of the next sample.
+    channel_index: usize,
+    ring_mod_phase: f32,
+    /// Indicates whether the wrapped source has ended.
+    is_inner_done: bool,
+    /// Samples of the tail left to play before checking whether it has gone silent.
+    tail_remaining: usize,
+}
+
+impl<S> EffectsSource<S> where S: Source<Item = f32> {
+    pub fn new(inner: S, shared: Arc<SharedEffects>) -> Self {
+        Self {
+            inner,
+            shared,
+            version: usize::MAX,
+            effects: Default::default(),
+            sample_rate: 0,
+            states: vec![],
+            channel_index: 0,
+            ring_mod_phase: 0.0,
+            is_inner_done: false,
+            tail_remaining: 0,
+        }
+    }
+
+    /// Loads changed effect parameters, keeping the state of effects that stay enabled.
+    fn reload(&mut self) {
+        let version = self.shared.version();
+        let (sample_rate, channel_count) = if self.is_inner_done {
+            (self.sample_rate, self.states.len())
+        } else {
+            (self.inner.sample_rate(), self.inner.channels().max(1) as usize)
+        };
+        if version == self.version && sample_rate == self.sample_rate && channel_count == self.states.len() {
+            return
+        }
+        if sample_rate != self.sample_rate || channel_count != self.states.len() {
+            self.states.clear();
+            self.states.resize_with(channel_count, Default::default);
+        }
+        self.version = version;
+        self.sample_rate = sample_rate;
+        self.effects = self.shared.get();
+        for state in self.states.iter_mut() {
+            match (self.effects.pitch_shift.is_some(), state.pitch_shifter.is_some()) {
+                (true, false) => state.pitch_shifter = Some(PitchShifter::new(sample_rate)),
+                (false, true) => state.pitch_shifter = None,
+                _ => {}
+            }
+            match (self.effects.echo.is_some(), state.echo.is_some()) {
+                (true, false) => state.echo = Some(DelayLine::new((MAX_ECHO_DELAY * sample_rate as f32) as usize + 2)),
+                (false, true) => state.echo = None,
+                _ => {}
+            }
+            match (self.effects.reverb.is_some(), state.reverb.is_some()) {
+                (true, false) => state.reverb = Some(Reverb::new(sample_rate)),
+                (false, true) => state.reverb = None,
+                _ => {}
+            }
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let effects = self.effects;
+        let sample_rate = self.sample_rate as f32;
+        let is_frame_start = self.channel_index == 0;
+        let state = match self.states.get_mut(self.channel_index) {
+            Some(state) => state,
+            None => return x
+        };
+        let mut sample = x;
+
+        if let (Some(EffectParams([semitones, mix, _])), Some(shifter)) = (effects.pitch_shift, state.pitch_shifter.as_mut()) {
+            sample = mix_wet(sample, shifter.process(sample, semitones), mix);
+        }
+
+        if let Some(EffectParams([frequency, mix, _])) = effects.ring_mod {
+            if is_frame_start {
+                self.ring_mod_phase = (self.ring_mod_phase + TAU * frequency / sample_rate) % TAU;
+            }
+            sample = mix_wet(sample, sample * self.ring_mod_phase.sin(), mix);
+        }
+
+        if let Some(EffectParams([drive, mix, _])) = effects.distortion {
+            // Dividing by the square root of the drive keeps the distorted sound about as loud as the dry sound
+            let drive = db_to_amp(drive).max(1.0);
+            sample = mix_wet(sample, (sample * drive).tanh() / drive.sqrt(), mix);
+        }
+
+        if let Some(EffectParams([bits, downsample, mix])) = effects.bitcrush {
+            state.bitcrush_countdown -= 1.0;
+            if state.bitcrush_countdown <= 0.0 {
+                let steps = 2.0f32.powf(bits.clamp(1.0, 24.0) - 1.0);
+                state.bitcrush_sample = (sample * steps).round() / steps;
+                state.bitcrush_countdown += downsample.max(1.0);
+            }
+            sample = mix_wet(sample, state.bitcrush_sample, mix);
+        }
+
+        if let (Some(EffectParams([delay, feedback, mix])), Some(echo)) = (effects.echo, state.echo.as_mut()) {
+            let delayed = echo.read(delay.clamp(0.0, MAX_ECHO_DELAY) * sample_rate);
+            echo.push(sample + delayed * feedback.clamp(0.0, 0.98));
+            sample = mix_wet(sample, sample + delayed, mix);
+        }
+
+        if let (Some(EffectParams([room_size, damping, mix])), Some(reverb)) = (effects.reverb, state.reverb.as_mut()) {
+            sample = mix_wet(sample, sample + reverb.process(sample, room_size, damping), mix);
+        }
+
+        sample
+    }
+}
+
+/// Crossfades between the dry and wet signals of an effect.
+fn mix_wet(dry: f32, wet: f32, mix: f32) -> f32 {
+    let mix = mix.clamp(0.0, 1.0);
+    dry + (wet - dry) * mix
+}
+
+impl<S> Iterator for EffectsSource<S> where S: Source<Item = f32> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel_index == 0 {
+            self.reload();
+        }
+        let sample = match self.inner.next() {
+            Some(sample) if !self.is_inner_done => sample,
+            _ => {
+                self.is_inner_done = true;
+                if self.channel_index == 0 && !self.continue_tail() {
+                    return None
+                }
+                0.0
+            }
+        };
+        let sample = self.process(sample);
+        self.channel_index = (self.channel_index + 1) % self.states.len().max(1);
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.inner.size_hint();
+        (lower, if self.effects.has_tail() { None } else { upper })
+    }
+}
+
+impl<S> EffectsSource<S> where S: Source<Item = f32> {
+    /// Returns `true` if the tail should keep playing after the wrapped source has ended.
+    fn continue_tail(&mut self) -> bool {
+        if !self.effects.has_tail() {
+            return false
+        }
+        if self.tail_remaining == 0 {
+            if !self.states.iter().any(|state| state.is_ringing(&self.effects, self.sample_rate)) {
+                return false
+            }
+            self.tail_remaining = (TAIL_CHECK_INTERVAL * self.sample_rate as f32) as usize;
+        }
+        self.tail_remaining -= 1;
+        true
+    }
+}
+
+impl<S> Source for EffectsSource<S> where S: Source<Item = f32> {
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.is_inner_done { None } else { self.inner.current_frame_len() }
+    }
+
+    fn channels(&self) -> u16 {
+        if self.is_inner_done { self.states.len().max(1) as u16 } else { self.inner.channels() }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        if self.is_inner_done { self.sample_rate } else { self.inner.sample_rate() }
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        if self.effects.has_tail() { None } else { self.inner.total_duration() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// Gets a tenth of a second of a 440 Hz sine wave.
+    fn tone() -> Vec<f32> {
+        (0..SAMPLE_RATE / 10).map(|i| 0.5 * (TAU * 440.0 * i as f32 / SAMPLE_RATE as f32).sin()).collect()
+    }
+
+    fn render(input: &[f32], effects: ChannelEffects) -> Vec<f32> {
+        let shared = Arc::new(SharedEffects::default());
+        shared.set(effects);
+        EffectsSource::new(SamplesBuffer::new(1, SAMPLE_RATE, input.to_vec()), shared).collect()
+    }
+
+    fn with_effect(kind: EffectKind) -> ChannelEffects {
+        let mut effects = ChannelEffects::default();
+        effects.set_enabled(kind, true);
+        effects
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |peak, sample| sample.abs().max(peak))
+    }
+
+    #[test]
+    fn no_effects_pass_through() {
+        let input = tone();
+        assert_eq!(render(&input, ChannelEffects::default()), input);
+    }
+
+    #[test]
+    fn effects_change_output() {
+        let input = tone();
+        for (kind, params) in [
+            (EffectKind::Bitcrush, vec![("bits".to_string(), 3.0)]),
+            (EffectKind::RingMod, vec![]),
+            (EffectKind::Distortion, vec![]),
+            (EffectKind::PitchShift, vec![("semitones".to_string(), 7.0)]),
+        ] {
+            let mut effects = ChannelEffects::default();
+            effects.set_params(kind, &params).unwrap();
+            let output = render(&input, effects);
+            let difference = peak(&output.iter().zip(&input).map(|(a, b)| a - b).collect::<Vec<_>>());
+            assert!(difference > 0.05, "{:?} changed the output by at most {}", kind, difference);
+        }
+    }
+
+    #[test]
+    fn echo_tail_plays_after_source_ends() {
+        let input = tone();
+        let output = render(&input, with_effect(EffectKind::Echo));
+        let tail = &output[input.len()..];
+        // The first repeat comes after the default 0.3 second delay
+        let first_repeat = &tail[(0.25 * SAMPLE_RATE as f32) as usize..];
+        assert!(peak(first_repeat) > 0.1, "echo tail peaked at {}", peak(first_repeat));
+        assert!(tail.len() < 10 * SAMPLE_RATE as usize, "echo tail lasted {} samples", tail.len());
+        assert!(peak(&tail[tail.len() - (SAMPLE_RATE / 10) as usize..]) < 0.001);
+    }
+
+    #[test]
+    fn reverb_tail_plays_after_source_ends() {
+        let input = tone();
+        let output = render(&input, with_effect(EffectKind::Reverb));
+        let tail = &output[input.len()..];
+        assert!(peak(tail) > 0.01, "reverb tail peaked at {}", peak(tail));
+        assert!(tail.len() < 10 * SAMPLE_RATE as usize, "reverb tail lasted {} samples", tail.len());
+    }
+
+    #[test]
+    fn set_params_keeps_unspecified_params() {
+        let mut effects = ChannelEffects::default();
+        effects.set_params(EffectKind::Echo, &[("delay".to_string(), 0.5)]).unwrap();
+        effects.set_params(EffectKind::Echo, &[("feedback".to_string(), 0.7)]).unwrap();
+        assert_eq!(effects.param(EffectKind::Echo, "delay"), Ok(Some(0.5)));
+        assert_eq!(effects.param(EffectKind::Echo, "feedback"), Ok(Some(0.7)));
+        effects.set_enabled(EffectKind::Echo, true);
+        assert_eq!(effects.param(EffectKind::Echo, "delay"), Ok(Some(0.5)));
+    }
+
+    #[test]
+    fn set_params_changes_nothing_if_any_param_is_invalid() {
+        let mut effects = ChannelEffects::default();
+        effects.set_params(EffectKind::Echo, &[("feedback".to_string(), 0.7)]).unwrap();
+        for params in [vec![("feedback".to_string(), 0.2), ("volume".to_string(), 1.0)], vec![("feedback".to_string(), 0.2), ("mix".to_string(), f32::NAN)]] {
+            assert!(effects.set_params(EffectKind::Echo, &params).is_err());
+            assert_eq!(effects.param(EffectKind::Echo, "feedback"), Ok(Some(0.7)));
+        }
+        assert!(effects.set_params(EffectKind::Reverb, &[("volume".to_string(), 1.0)]).is_err());
+        assert!(!effects.is_enabled(EffectKind::Reverb));
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use rodio::Source;
+use rodio::cpal;
+use rodio::cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rodio::cpal::{FromSample, Sample, SizedSample};
+use log::{info, error};
+
+/// Maximum amount of captured audio held before the oldest samples are dropped.
+const VOICE_INPUT_MAX_LATENCY_MS: u32 = 250;
+
+type SampleQueue = Arc<Mutex<VecDeque<f32>>>;
+
+/// Captures mono audio from a host audio input device, such as a handset microphone.
+pub struct VoiceInput {
+    _stream: cpal::Stream,
+    queue: SampleQueue,
+    sample_rate: u32,
+}
+
+impl VoiceInput {
+    /// Opens the input device with the specified name and starts capturing from it.
+    pub fn open(device_name: &str) -> Option<Self> {
+        let device = match find_input_device(device_name) {
+            Some(device) => device,
+            None => {
+                error!("Audio input device not found: {}", device_name);
+                return None
+            }
+        };
+
+        let supported_config = match device.default_input_config() {
+            Ok(config) => config,
+            Err(err) => {
+                error!("Failed to query audio input device '{}': {}", device_name, err);
+                return None
+            }
+        };
+
+        let sample_rate = supported_config.sample_rate().0;
+        let channels = supported_config.channels() as usize;
+        let max_len = (sample_rate * VOICE_INPUT_MAX_LATENCY_MS / 1000) as usize;
+        let queue: SampleQueue = Default::default();
+        let config = supported_config.config();
+
+        let stream = match supported_config.sample_format() {
+            cpal::SampleFormat::I16 => build_input_stream::<i16>(&device, &config, channels, max_len, &queue),
+            cpal::SampleFormat::U16 => build_input_stream::<u16>(&device, &config, channels, max_len, &queue),
+            cpal::SampleFormat::I32 => build_input_stream::<i32>(&device, &config, channels, max_len, &queue),
+            cpal::SampleFormat::F32 => build_input_stream::<f32>(&device, &config, channels, max_len, &queue),
+            format => {
+                error!("Unsupported sample format for audio input device '{}': {:?}", device_name, format);
+                return None
+            }
+        };
+
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                error!("Failed to open audio input device '{}': {}", device_name, err);
+                return None
+            }
+        };
+
+        if let Err(err) = stream.play() {
+            error!("Failed to start audio input device '{}': {}", device_name, err);
+            return None
+        }
+
+        info!("Capturing audio input from: {} ({} Hz)", device_name, sample_rate);
+
+        Some(Self {
+            _stream: stream,
+            queue,
+            sample_rate,
+        })
+    }
+
+    /// Creates a source that plays back the captured audio.
+    pub fn source(&self) -> VoiceInputSource {
+        // Start from live audio instead of whatever was buffered before the call
+        self.queue.lock().unwrap().clear();
+        VoiceInputSource {
+            queue: Arc::clone(&self.queue),
+            sample_rate: self.sample_rate,
+        }
+    }
+}
+
+/// Finds the host audio input device with the specified name.
+fn find_input_device(name: &str) -> Option<cpal::Device> {
+    cpal::default_host()
+        .input_devices()
+        .ok()?
+        .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+}
+
+fn build_input_stream<T>(device: &cpal::Device, config: &cpal::StreamConfig, channels: usize, max_len: usize, queue: &SampleQueue) -> Result<cpal::Stream, cpal::BuildStreamError>
+where T: SizedSample, f32: FromSample<T>
+{
+    let queue = Arc::clone(queue);
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let mut queue = queue.lock().unwrap();
+            // Downmix each frame to mono
+            for frame in data.chunks(channels.max(1)) {
+                let sum: f32 = frame.iter().map(|sample| f32::from_sample(*sample)).sum();
+                queue.push_back(sum / frame.len() as f32);
+            }
+            let excess = queue.len().saturating_sub(max_len);
+            queue.drain(..excess);
+        },
+        |err| error!("Audio input stream error: {}", err),
+        None
+    )
+}
+
+/// A never-ending source of captured audio. Plays silence whenever the capture falls behind.
+pub struct VoiceInputSource {
+    queue: SampleQueue,
+    sample_rate: u32,
+}
+
+impl Iterator for VoiceInputSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        Some(self.queue.lock().unwrap().pop_front().unwrap_or(0.0))
+    }
+}
+
+impl Source for VoiceInputSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,343 @@
+use std::f32::consts::{FRAC_1_SQRT_2, TAU};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use rodio::Source;
+use crate::config::{Companding, LineFilterConfig};
+use super::db_to_amp;
+
+/// Average number of crackles per second at full crackle.
+const CRACKLES_PER_SECOND: f32 = 40.0;
+/// Gain (in dB) of the loudest crackle.
+const CRACKLE_GAIN: f32 = -18.0;
+/// Amount by which a crackle decays each sample.
+const CRACKLE_DECAY: f32 = 0.6;
+/// Level of the second harmonic of mains hum, relative to the fundamental.
+const HUM_HARMONIC_LEVEL: f32 = 0.4;
+const MU_LAW_MU: f32 = 255.0;
+const A_LAW_A: f32 = 87.6;
+/// Number of quantization steps on each side of zero in 8-bit G.711.
+const G711_STEPS: f32 = 127.0;
+
+/// Settings of a channel, shared with every source playing on it.
+#[derive(Default)]
+pub struct SharedSettings<T> {
+    settings: Mutex<T>,
+    /// Incremented whenever the settings change, so sources know to reload them.
+    version: AtomicUsize,
+}
+
+impl<T: Clone> SharedSettings<T> {
+    pub fn get(&self) -> T {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, settings: T) {
+        self.update(|current| *current = settings);
+    }
+
+    /// Changes the settings in place.
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let result = f(&mut self.settings.lock().unwrap());
+        self.version.fetch_add(1, Ordering::Release);
+        result
+    }
+
+    /// Gets the version of the settings, which changes whenever they are changed.
+    pub fn version(&self) -> usize {
+        self.version.load(Ordering::Acquire)
+    }
+}
+
+/// Line filter settings of a channel; the filter is disabled if `None`.
+pub type SharedLineFilter = SharedSettings<Option<LineFilterConfig>>;
+
+/// A second-order IIR filter (transposed direct form II).
+#[derive(Copy, Clone, Default)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    /// Creates a Butterworth low-pass filter.
+    fn low_pass(freq: f32, sample_rate: u32) -> Self {
+        let (cos_w0, alpha) = Self::params(freq, sample_rate);
+        Self::normalized((1.0 - cos_w0) / 2.0, 1.0 - cos_w0, (1.0 - cos_w0) / 2.0, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha)
+    }
+
+    /// Creates a Butterworth high-pass filter.
+    fn high_pass(freq: f32, sample_rate: u32) -> Self {
+        let (cos_w0, alpha) = Self::params(freq, sample_rate);
+        Self::normalized((1.0 + cos_w0) / 2.0, -(1.0 + cos_w0), (1.0 + cos_w0) / 2.0, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha)
+    }
+
+    fn params(freq: f32, sample_rate: u32) -> (f32, f32) {
+        let freq = freq.clamp(1.0, sample_rate as f32 * 0.45);
+        let w0 = TAU * freq / sample_rate as f32;
+        (w0.cos(), w0.sin() / (2.0 * FRAC_1_SQRT_2))
+    }
+
+    fn normalized(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            .. Default::default()
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+/// Simulates an 8-bit μ-law encode and decode of a sample.
+fn mu_law(x: f32) -> f32 {
+    let x = x.clamp(-1.0, 1.0);
+    let encoded = (1.0 + MU_LAW_MU * x.abs()).ln() / (1.0 + MU_LAW_MU).ln();
+    let quantized = (encoded * G711_STEPS).round() / G711_STEPS;
+    x.signum() * ((1.0 + MU_LAW_MU).powf(quantized) - 1.0) / MU_LAW_MU
+}
+
+/// Simulates an 8-bit A-law encode and decode of a sample.
+fn a_law(x: f32) -> f32 {
+    let x = x.clamp(-1.0, 1.0);
+    let ln_a = 1.0 + A_LAW_A.ln();
+    let encoded = if x.abs() < 1.0 / A_LAW_A {
+        A_LAW_A * x.abs() / ln_a
+    } else {
+        (1.0 + (A_LAW_A * x.abs()).ln()) / ln_a
+    };
+    let quantized = (encoded * G711_STEPS).round() / G711_STEPS;
+    let decoded = if quantized < 1.0 / ln_a {
+        quantized * ln_a / A_LAW_A
+    } else {
+        (quantized * ln_a - 1.0).exp() / A_LAW_A
+    };
+    x.signum() * decoded
+}
+
+/// Wraps a source so that it plays through its channel's line filter, if enabled.
+pub struct LineFilterSource<S> {
+    inner: S,
+    shared: Arc<SharedLineFilter>,
+    /// Version of the shared settings that were last loaded.
+    version: usize,
+    settings: Option<LineFilterConfig>,
+    /// Sample rate the filters were designed for.
+    sample_rate: u32,
+    /// Band-pass filter stages for each audio channel.
+    band_pass: Vec<[Biquad; 4]>,
+    /// Index of the audio channel of the next sample.
+    channel_index: usize,
+    /// Amplitude of the gain applied before soft clipping.
+    drive: f32,
+    /// Amplitude of mains hum.
+    hum: f32,
+    hum_phase: f32,
+    crackle: f32,
+    rng_state: u32,
+}
+
+impl<S> LineFilterSource<S> where S: Source<Item = f32> {
+    pub fn new(inner: S, shared: Arc<SharedLineFilter>) -> Self {
+        Self {
+            inner,
+            shared,
+            version: usize::MAX,
+            settings: None,
+            sample_rate: 0,
+            band_pass: vec![],
+            channel_index: 0,
+            drive: 1.0,
+            hum: 0.0,
+            hum_phase: 0.0,
+            crackle: 0.0,
+            rng_state: rand::random::<u32>() | 1,
+        }
+    }
+
+    fn reload(&mut self) {
+        let version = self.shared.version();
+        let sample_rate = self.inner.sample_rate();
+        if version == self.version && sample_rate == self.sample_rate {
+            return
+        }
+        self.version = version;
+        self.sample_rate = sample_rate;
+        self.settings = self.shared.get();
+        self.band_pass.clear();
+        if let Some(settings) = &self.settings {
+            self.drive = db_to_amp(settings.drive);
+            self.hum = settings.hum_gain.map_or(0.0, db_to_amp);
+            let stages = [
+                Biquad::high_pass(settings.low_cutoff, sample_rate),
+                Biquad::high_pass(settings.low_cutoff, sample_rate),
+                Biquad::low_pass(settings.high_cutoff, sample_rate),
+                Biquad::low_pass(settings.high_cutoff, sample_rate),
+            ];
+            self.band_pass.resize(self.inner.channels().max(1) as usize, stages);
+        }
+    }
+
+    /// Gets a uniformly distributed random number between 0.0 and 1.0.
+    fn next_random(&mut self) -> f32 {
+        // xorshift32
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 17;
+        self.rng_state ^= self.rng_state << 5;
+        self.rng_state as f32 / u32::MAX as f32
+    }
+
+    fn filter(&mut self, sample: f32) -> f32 {
+        let (band_pass, soft_clip, hum_frequency, crackle, companding) = match &self.settings {
+            Some(settings) => (settings.band_pass, settings.soft_clip, settings.hum_frequency, settings.crackle, settings.companding),
+            None => return sample
+        };
+        let is_frame_start = self.channel_index == 0;
+        let mut sample = sample;
+
+        if band_pass {
+            if let Some(stages) = self.band_pass.get_mut(self.channel_index) {
+                for stage in stages.iter_mut() {
+                    sample = stage.process(sample);
+                }
+            }
+        }
+
+        if soft_clip {
+            sample = (sample * self.drive).tanh();
+        }
+
+        // Line noise is shared by all audio channels, so it only advances once per frame
+        if self.hum > 0.0 {
+            if is_frame_start {
+                self.hum_phase = (self.hum_phase + TAU * hum_frequency / self.sample_rate as f32) % TAU;
+            }
+            sample += self.hum * (self.hum_phase.sin() + HUM_HARMONIC_LEVEL * (2.0 * self.hum_phase).sin());
+        }
+
+        if crackle > 0.0 {
+            if is_frame_start {
+                let crackle_chance = crackle.min(1.0) * CRACKLES_PER_SECOND / self.sample_rate as f32;
+                self.crackle *= CRACKLE_DECAY;
+                if self.next_random() < crackle_chance {
+                    self.crackle = db_to_amp(CRACKLE_GAIN) * (self.next_random() * 2.0 - 1.0);
+                }
+            }
+            sample += self.crackle;
+        }
+
+        match companding {
+            Companding::None => sample,
+            Companding::MuLaw => mu_law(sample),
+            Companding::ALaw => a_law(sample),
+        }
+    }
+}
+
+impl<S> Iterator for LineFilterSource<S> where S: Source<Item = f32> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel_index == 0 {
+            self.reload();
+        }
+        let channel_count = self.inner.channels().max(1) as usize;
+        let sample = self.inner.next()?;
+        let sample = self.filter(sample);
+        self.channel_index = (self.channel_index + 1) % channel_count;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<S> Source for LineFilterSource<S> where S: Source<Item = f32> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// Gets the RMS level of a sine wave after playing it through a line filter for one second.
+    fn filtered_level(freq: f32, settings: Option<LineFilterConfig>) -> f32 {
+        let samples: Vec<f32> = (0..SAMPLE_RATE).map(|i| 0.5 * (TAU * freq * i as f32 / SAMPLE_RATE as f32).sin()).collect();
+        let shared = Arc::new(SharedLineFilter::default());
+        shared.set(settings);
+        let output: Vec<f32> = LineFilterSource::new(SamplesBuffer::new(1, SAMPLE_RATE, samples), shared).collect();
+        // Skip the filter's settling time
+        let output = &output[SAMPLE_RATE as usize / 10..];
+        (output.iter().map(|sample| sample * sample).sum::<f32>() / output.len() as f32).sqrt()
+    }
+
+    /// Line filter settings with only the band-pass filter enabled.
+    fn band_pass_only() -> LineFilterConfig {
+        LineFilterConfig {
+            companding: Companding::None,
+            soft_clip: false,
+            hum_gain: None,
+            crackle: 0.0,
+            .. Default::default()
+        }
+    }
+
+    #[test]
+    fn band_pass_limits_frequencies() {
+        let unfiltered = filtered_level(1000.0, None);
+        assert!((unfiltered - 0.5 * FRAC_1_SQRT_2).abs() < 0.01);
+        for (freq, min_ratio, max_ratio) in [(1000.0, 0.9, 1.1), (100.0, 0.0, 0.1), (8000.0, 0.0, 0.1)] {
+            let ratio = filtered_level(freq, Some(band_pass_only())) / filtered_level(freq, None);
+            assert!(ratio >= min_ratio && ratio <= max_ratio, "{} Hz was passed at {:.3} of its level", freq, ratio);
+        }
+    }
+
+    #[test]
+    fn cutoffs_are_configurable() {
+        let settings = LineFilterConfig { low_cutoff: 500.0, high_cutoff: 4000.0, .. band_pass_only() };
+        assert!(filtered_level(250.0, Some(settings.clone())) < 0.25 * filtered_level(250.0, None));
+        assert!(filtered_level(1400.0, Some(settings.clone())) > 0.8 * filtered_level(1400.0, None));
+        assert!(filtered_level(8000.0, Some(settings)) < 0.25 * filtered_level(8000.0, None));
+    }
+
+    #[test]
+    fn companding_quantizes_quiet_samples() {
+        assert_eq!(mu_law(0.0), 0.0);
+        assert_eq!(a_law(0.0), 0.0);
+        for x in [-0.9, -0.1, 0.01, 0.3, 1.0] {
+            assert!((mu_law(x) - x).abs() < 0.05 * x.abs() + 0.001, "μ-law changed {} to {}", x, mu_law(x));
+            assert!((a_law(x) - x).abs() < 0.05 * x.abs() + 0.001, "A-law changed {} to {}", x, a_law(x));
+        }
+    }
+}
@@ -0,0 +1,1271 @@
+#![allow(dead_code)]
+
+mod output;
+mod input;
+mod callerid;
+mod tone_plan;
+mod line_filter;
+mod effects;
+mod stream;
+
+pub use self::output::*;
+pub use self::input::*;
+pub use self::callerid::*;
+pub use self::tone_plan::*;
+pub use self::line_filter::*;
+pub use self::effects::*;
+pub use self::stream::*;
+
+use crate::config::*;
+pub use crate::common::{Channel, PHONE_CHANNELS, BG_CHANNELS, NON_SOUL_CHANNELS};
+use crate::clock::Clock;
+use std::path::Path;
+use std::sync::Arc;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use indexmap::map::IndexMap;
+use mlua::FromLua;
+use rodio;
+use rodio::buffer::SamplesBuffer;
+use rodio::source::Source;
+use globset;
+use rand;
+use rand::Rng;
+use log::{error, info, warn};
+use vfs::VfsPath;
+
+// DTMF tone constants
+const DTMF_COLUMN_FREQUENCIES: &[f32] = &[1209.0, 1336.0, 1477.0, 1633.0];
+const DTMF_ROW_FREQUENCIES: &[f32] = &[697.0, 770.0, 852.0, 941.0];
+const DTMF_DIGITS: &[char] = &['1', '2', '3', 'A', '4', '5', '6', 'B', '7', '8', '9', 'C', '*', '0', '#', 'D'];
+/// Greatest level (in dB) by which the high-group tone may exceed the low-group tone (ITU-T Q.24).
+const DTMF_MAX_TWIST: f32 = 4.0;
+/// Greatest level (in dB) by which the low-group tone may exceed the high-group tone (ITU-T Q.24).
+const DTMF_MAX_REVERSE_TWIST: f32 = 8.0;
+/// Greatest frequency deviation (in %) that receivers must accept (ITU-T Q.24).
+/// This is tighter than the 1.8% that Q.23 allows senders, so that drifted tones are still recognized.
+const DTMF_MAX_DRIFT: f32 = 1.5;
+
+/// Length of the beep played before each voicemail message.
+pub const VOICEMAIL_BEEP_DURATION: Duration = Duration::from_millis(500);
+/// Amount of audio rendered at a time while waiting for a channel on a headless output.
+const HEADLESS_WAIT_STEP: Duration = Duration::from_millis(10);
+
+// Special Information Tone constants
+const SIT_FREQS_FIRST: (u32, u32) = (914, 985);
+const SIT_FREQS_SECOND: (u32, u32) = (1371, 1429);
+const SIT_FREQS_THIRD: (u32, u32) = (1777, 1777);
+const SIT_SHORT_SEG_MS: u64 = 276;
+const SIT_LONG_SEG_MS: u64 = 380;
+
+/// Special Information Tone (SIT) types.
+///
+/// Descriptions from [Wikipedia](https://en.wikipedia.org/wiki/Special_information_tone)
+#[repr(u8)]
+#[derive(Copy, Clone, Debug)]
+pub enum SpecialInfoTone {
+    /// Unassigned N11 ode, CLASS code, or prefix.
+    VacantCode = 0,
+    /// Incomplete digits, internal office or feature failure (local office).
+    ReorderIntra = 1,
+    /// Call failure, no wink or partial digits received (distant office).
+    ReorderInter = 2,
+    /// All circuits busy (local office).
+    NoCircuitIntra = 3,
+    /// All circuits busy (distant office).
+    NoCircuitInter = 4,
+    /// Number changed or disconnected.
+    Intercept = 5,
+    /// General misdialing, coin deposit required or other failure.
+    Ineffective = 6,
+    /// Reserved for future use.
+    Reserved = 7
+}
+
+impl SpecialInfoTone {
+    fn as_segments(self) -> (SitSegment, SitSegment, SitSegment) {
+        use SitSegment::*;
+        use SitSegmentLength::*;
+        match self {
+            SpecialInfoTone::VacantCode => (High(Long), Low(Short), Low(Long)),
+            SpecialInfoTone::ReorderIntra => (Low(Short), High(Long), Low(Long)),
+            SpecialInfoTone::ReorderInter => (High(Short), Low(Long), Low(Long)),
+            SpecialInfoTone::NoCircuitIntra => (High(Long), High(Long), Low(Long)),
+            SpecialInfoTone::NoCircuitInter => (Low(Long), Low(Long), Low(Long)),
+            SpecialInfoTone::Intercept => (Low(Short), Low(Short), Low(Long)),
+            SpecialInfoTone::Ineffective => (Low(Long), High(Short), Low(Long)),
+            SpecialInfoTone::Reserved => (High(Short), High(Short), Low(Long)),
+        }
+    }
+}
+
+impl From<u8> for SpecialInfoTone {
+    fn from(val: u8) -> Self {
+        match val {
+            0 => SpecialInfoTone::VacantCode,
+            1 => SpecialInfoTone::ReorderIntra,
+            2 => SpecialInfoTone::ReorderInter,
+            3 => SpecialInfoTone::NoCircuitIntra,
+            4 => SpecialInfoTone::NoCircuitInter,
+            5 => SpecialInfoTone::Intercept,
+            6 => SpecialInfoTone::Ineffective,
+            7 | _ => SpecialInfoTone::Reserved
+        }
+    }
+}
+
+enum SitSegmentLength {
+    Short,
+    Long
+}
+
+impl SitSegmentLength {
+    fn as_ms(self) -> u64 {
+        use SitSegmentLength::*;
+        match self {
+            Short => SIT_SHORT_SEG_MS,
+            Long => SIT_LONG_SEG_MS
+        }
+    }
+}
+
+enum SitSegment {
+    High(SitSegmentLength),
+    Low(SitSegmentLength)
+}
+
+/// Converts decibels to amplitude (assuming a normalized signal).
+fn db_to_amp(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+/// Limits the configured DTMF twist to the range that receivers must accept.
+fn validate_dtmf_twist(twist: f32) -> f32 {
+    if !twist.is_finite() {
+        warn!("DTMF twist must be a number; ignoring it.");
+        return 0.0
+    }
+    let limited = twist.clamp(-DTMF_MAX_REVERSE_TWIST, DTMF_MAX_TWIST);
+    if limited != twist {
+        warn!("DTMF twist of {} dB is outside the ITU-T Q.24 tolerance (-{} to +{} dB); using {} dB.", twist, DTMF_MAX_REVERSE_TWIST, DTMF_MAX_TWIST, limited);
+    }
+    limited
+}
+
+/// Limits the configured DTMF drift to the deviation that receivers must accept.
+fn validate_dtmf_drift(drift: f32) -> f32 {
+    if !drift.is_finite() || drift < 0.0 {
+        warn!("DTMF drift must be a non-negative percentage; ignoring it.");
+        return 0.0
+    }
+    if drift > DTMF_MAX_DRIFT {
+        warn!("DTMF drift of {}% is outside the ITU-T Q.24 tolerance ({}%); using {}%.", drift, DTMF_MAX_DRIFT, DTMF_MAX_DRIFT);
+        return DTMF_MAX_DRIFT
+    }
+    drift
+}
+
+/// Randomly shifts a frequency by up to `drift` percent in either direction.
+fn drift_frequency(freq: f32, drift: f32) -> f32 {
+    if drift <= 0.0 {
+        return freq
+    }
+    freq * (1.0 + rand::thread_rng().gen_range(-drift..=drift) / 100.0)
+}
+
+/// Sound resources that can be shared between sound engines.
+pub struct SoundLibrary {
+    sounds_root_path: VfsPath,
+    sound_banks_root_path: VfsPath,
+    tone_plans_root_path: VfsPath,
+    stream_thresholds: SoundStreamThresholds,
+    static_sounds: SoundBank,
+    sound_banks: IndexMap<String, Rc<RefCell<SoundBank>>>,
+}
+
+pub struct SoundEngine {
+    library: Rc<RefCell<SoundLibrary>>,
+    output: SoundOutput,
+    channels: RefCell<Vec<SoundChannel>>,
+    config: Rc<CursedConfig>,
+    clock: Arc<dyn Clock>,
+    master_volume: f32,
+    /// Sounds played since the play log was last drained, if logging is enabled.
+    play_log: RefCell<Option<Vec<SoundPlayRecord>>>,
+    /// Indicates whether a message is waiting, which makes the dial tone stutter.
+    message_waiting: Cell<bool>,
+    /// DTMF twist (in dB), limited to the Q.24 tolerances.
+    dtmf_twist: f32,
+    /// DTMF frequency drift (in %), limited to the Q.24 tolerances.
+    dtmf_drift: f32,
+    /// Frequencies, cadences and levels of call progress tones.
+    tone_plan: TonePlan,
+}
+
+/// Describes a sound that was started on a channel.
+#[derive(Clone, Debug)]
+pub struct SoundPlayRecord {
+    pub channel: Channel,
+    /// Key of the sound that was played, or the name of a generated tone prefixed with `@`.
+    pub name: String,
+}
+
+struct SoundChannel {
+    id: Channel,
+    sink: rodio::Sink,
+    volume_master: f32,
+    volume_channel: f32,
+    volume_fade: f32,
+    muted: bool,
+    /// Line filter applied to sounds played on the channel.
+    line_filter: Arc<SharedLineFilter>,
+    /// Effects applied to sounds played on the channel, before the line filter.
+    effects: Arc<SharedEffects>,
+}
+
+struct Sound {
+    key: String,
+    path: VfsPath,
+    data: SoundData,
+    duration: Option<Duration>,
+}
+
+/// Limits above which sounds are streamed from their files instead of being decoded into memory.
+#[derive(Copy, Clone, Default)]
+pub struct SoundStreamThresholds {
+    /// Minimum file size (in bytes) of streamed sounds.
+    pub min_size: Option<u64>,
+    pub min_duration: Option<Duration>,
+}
+
+impl SoundStreamThresholds {
+    pub fn from_config(config: &SoundConfig) -> Self {
+        Self {
+            min_size: config.stream_min_size_kb.map(|kb| kb * 1024),
+            min_duration: config.stream_min_duration_ms.map(Duration::from_millis),
+        }
+    }
+}
+
+impl Sound {
+    fn from_file(key: String, path: VfsPath, thresholds: &SoundStreamThresholds) -> Result<Self, String> {
+        let size = path.metadata().map_err(|err| err.to_string())?.len;
+        let decoder = open_decoder(&path)?;
+        let sample_rate = decoder.sample_rate();
+        let channels = decoder.channels();
+        let samples_per_sec = sample_rate as f64 * channels as f64;
+        // Vorbis decoders don't know their length, but it can be read from the end of the file
+        let duration = decoder.total_duration().or_else(|| ogg_duration(&path, sample_rate));
+        let is_large = thresholds.min_size.is_some_and(|min_size| size >= min_size);
+        let is_long = match (duration, thresholds.min_duration) {
+            (Some(duration), Some(min_duration)) => duration >= min_duration,
+            _ => false
+        };
+
+        if is_large || is_long {
+            return Ok(Self::streamed(key, path, duration))
+        }
+
+        // Decode into memory, unless the sound turns out to be long enough to stream
+        let max_samples = thresholds.min_duration.map(|min_duration| (min_duration.as_secs_f64() * samples_per_sec) as usize);
+        let mut samples = vec![];
+        for sample in decoder {
+            samples.push(sample);
+            if max_samples.is_some_and(|max_samples| samples.len() >= max_samples) {
+                return Ok(Self::streamed(key, path, duration))
+            }
+        }
+        let src = SamplesBuffer::new(channels, sample_rate, samples).buffered();
+        
+        Ok(Self {
+            key,
+            path,
+            duration: src.total_duration(),
+            data: SoundData::Buffered(src),
+        })
+    }
+
+    fn streamed(key: String, path: VfsPath, duration: Option<Duration>) -> Self {
+        info!("Streaming sound: '{}'", key);
+        Self {
+            key,
+            data: SoundData::Streamed(path.clone()),
+            path,
+            duration,
+        }
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Creates a source that plays the sound, restarting it whenever it ends if `looping` is set.
+    fn source(&self, looping: bool) -> SoundSource {
+        SoundSource::new(&self.data, looping)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SoundPlayOptions {
+    pub volume: f32,
+    pub speed: f32,
+    pub looping: bool,
+    pub skip: SoundPlaySkip,
+    pub take: Option<Duration>,
+    pub delay: Option<Duration>,
+    pub fadein: Duration,
+}
+
+impl Default for SoundPlayOptions {
+    fn default() -> Self {
+        Self { 
+            volume: 1.0, 
+            speed: 1.0, 
+            looping: false, 
+            skip: Default::default(), 
+            take: Default::default(),
+            delay: Default::default(),
+            fadein: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum SoundPlaySkip {
+    By(Duration),
+    Random
+}
+
+impl Default for SoundPlaySkip {
+    fn default() -> Self {
+        Self::By(Duration::ZERO)
+    }
+}
+
+impl<'lua> FromLua<'lua> for SoundPlaySkip {
+    fn from_lua(lua_value: mlua::Value<'lua>, _lua: &'lua mlua::Lua) -> mlua::Result<Self> {
+        Ok(match lua_value {
+            mlua::Value::Nil => Default::default(),
+            mlua::Value::Integer(secs) => Self::By(Duration::from_secs(secs as u64)),
+            mlua::Value::Number(secs) => Self::By(Duration::from_secs_f64(secs)),
+            mlua::Value::String(kw) => match kw.to_str() {
+                Ok("random") => Self::Random,
+                Ok(kw_other) => return Err(mlua::Error::FromLuaConversionError { from: "string", to: stringify!(SoundPlaySkip), message: Some(format!("invalid sound skip keyword: \"{}\"", kw_other)) }),
+                Err(_) => return Err(mlua::Error::FromLuaConversionError { from: "string", to: stringify!(SoundPlaySkip), message: None })
+            },
+            other => return Err(mlua::Error::FromLuaConversionError { from: other.type_name(), to: stringify!(SoundPlaySkip), message: None })
+        })
+    }
+}
+
+#[derive(Hash, Eq, PartialEq, Debug)]
+pub struct SoundBankUser(pub usize);
+
+struct SoundBank {
+    name: String,
+    root_dir: VfsPath,
+    sounds: IndexMap<String, Rc<Sound>>,
+    sound_glob_cache: RefCell<HashMap<String, Vec<usize>>>,
+    users: HashSet<SoundBankUser>,
+}
+
+pub struct PlayedSoundInfo {
+    /// Key of the sound that was played, after resolving any soundglob.
+    pub key: String,
+    pub duration: Option<Duration>
+}
+
+impl SoundBank {
+    pub fn from_dir(name: String, root_dir: VfsPath, stream_thresholds: &SoundStreamThresholds) -> Self {   
+        
+        let mut bank = Self {
+            name,
+            root_dir: root_dir.clone(),
+            sounds: Default::default(),
+            sound_glob_cache: Default::default(),
+            users: Default::default()
+        };
+
+        bank.sounds.clear();
+        bank.sound_glob_cache.borrow_mut().clear();
+
+        for entry in root_dir.walk_dir().expect("unable to enumerate files in soundbank") {
+            if let Ok(path) = entry {
+                match path.extension().as_deref() {
+                    Some("wav" | "ogg") => {
+                        let path_str = path.as_str();
+                        let sound_key = Path::new(path_str)
+                        .strip_prefix(root_dir.as_str()).expect("Unable to form sound key from path")
+                        .with_extension("")
+                        .to_string_lossy()
+                        .replace("\\", "/");
+                        match Sound::from_file(sound_key.clone(), path, stream_thresholds) {
+                            Ok(sound) => {
+                                bank.sounds.insert(sound_key, Rc::new(sound));
+                            },
+                            Err(err) => error!("Unable to load sound '{}': {}", sound_key, err)
+                        }
+                    },
+                    _ => continue
+                }
+            }
+        }
+
+        bank
+    }
+
+    pub fn add_user(&mut self, user: SoundBankUser) -> bool {
+        self.users.insert(user)
+    }
+
+    pub fn remove_user(&mut self, user: &SoundBankUser) -> bool {
+        self.users.remove(user)
+    }
+
+    pub fn has_user(&self, user: &SoundBankUser) -> bool {
+        self.users.contains(user)
+    }
+
+    pub fn user_count(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn find_sound(&self, key: &str) -> Option<Rc<Sound>> {
+        // Check for exact match
+        let sound = self.sounds.get(key);
+        match sound {
+            // If it exists, just return it right away
+            Some(sound) => return Some(Rc::clone(sound)),
+            // If not, try a glob match
+            None => {
+                // First, check if there's a glob match list pre-cached
+                let mut glob_cache = self.sound_glob_cache.borrow_mut();
+                let glob_list = glob_cache.get(key);
+                if let Some(glob_list) = glob_list {
+                    let index = rand::thread_rng().gen_range(0..glob_list.len());
+                    return Some(Rc::clone(&self.sounds.get_index(glob_list[index]).unwrap().1));
+                }
+                // If not, run the search manually and cache the results
+                let glob = globset::GlobBuilder::new(key).literal_separator(true).build();
+                if let Ok(glob) = glob {
+                    let matcher = glob.compile_matcher();
+                    let mut glob_list = Vec::<usize>::new();
+                    let sound_iter = self.sounds.iter();
+                    for (k, _) in sound_iter {
+                        if matcher.is_match(k) {
+                            glob_list.push(self.sounds.get_full(k).unwrap().0);
+                        }
+                    }                    
+                    // Cache and pick only if there were results
+                    if glob_list.len() > 0 {
+                        let index = rand::thread_rng().gen_range(0..glob_list.len());
+                        let sound = Some(self.sounds.get_index(glob_list[index]).unwrap().1);
+                        glob_cache.insert(key.to_string(), glob_list);
+                        return Some(Rc::clone(sound.unwrap()));
+                    }
+                }
+                return None;
+            }
+        }
+    }
+}
+
+impl SoundLibrary {
+    pub fn new(sounds_root_path: VfsPath, sound_banks_root_path: VfsPath, tone_plans_root_path: VfsPath, stream_thresholds: SoundStreamThresholds) -> Self {
+        info!("Loading static sound resources...");
+        let static_sounds = SoundBank::from_dir("[static]".to_owned(),sounds_root_path.clone(), &stream_thresholds);
+
+        Self {
+            sounds_root_path,
+            sound_banks_root_path,
+            tone_plans_root_path,
+            stream_thresholds,
+            sound_banks: Default::default(),
+            static_sounds,
+        }
+    }
+
+    /// Loads the tone plan with the specified name from the tone plans directory, or the default plan if it can't be loaded.
+    pub fn load_tone_plan(&self, name: &str) -> TonePlan {
+        TonePlan::load(&self.tone_plans_root_path, name)
+    }
+
+    fn get_sound_bank(&self, name: &str) -> Option<Rc<RefCell<SoundBank>>> {
+        if let Some(bank) = self.sound_banks.get(name) {
+            return Some(Rc::clone(bank));
+        }
+        None
+    }
+
+    pub fn add_sound_bank_user(&mut self, name: &str, user: SoundBankUser) -> bool {
+        if let Some(bank) = self.get_sound_bank(name) {
+            return bank.borrow_mut().add_user(user);
+        }
+
+        info!("Loading sound bank: '{}'", name);
+        if let Ok(bank_path) = self.sound_banks_root_path.join(name) {
+            let mut bank = SoundBank::from_dir(name.to_owned(), bank_path, &self.stream_thresholds);
+            bank.add_user(user);
+    
+            self.sound_banks.insert(name.to_owned(), Rc::new(RefCell::new(bank)));
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn sound_bank_used_by(&self, name: &str, user: &SoundBankUser) -> bool {
+        if let Some(bank) = self.get_sound_bank(name) {
+            return bank.borrow().has_user(user);
+        }
+        false
+    }
+
+    pub fn remove_sound_bank_user(&mut self, name: &str, user: SoundBankUser, unload_if_userless: bool) -> bool {
+        if let Some(bank) = self.get_sound_bank(name) {
+            let removed = bank.borrow_mut().remove_user(&user);
+            if unload_if_userless && bank.borrow().user_count() == 0 {
+                info!("Unloading sound bank: '{}'", name);
+                self.sound_banks.remove(name);
+            }
+            return removed;
+        }
+        false
+    }
+
+    fn find_sound(&self, key: &str) -> Option<Rc<Sound>> {
+        // See if it's a soundbank sound
+        if key.starts_with("$") {
+            if let Some(separator_index) = key.find('/') {
+                let soundbank_name = &key[1..separator_index];
+                if let Some(bank) = self.get_sound_bank(soundbank_name) {
+                    let key = &key[separator_index + 1 ..];
+                    return bank.borrow().find_sound(key)
+                }
+            }
+        }
+        // Find as static sound
+        self.static_sounds.find_sound(key)
+    }
+}
+
+impl SoundEngine {
+    pub fn new(library: &Rc<RefCell<SoundLibrary>>, config: &Rc<CursedConfig>, clock: &Arc<dyn Clock>) -> Self {
+        // Load output device
+        let output = SoundOutput::from_config(&config.sound, clock.now());
+        let channels = RefCell::from(Vec::<SoundChannel>::new());
+        let config = Rc::clone(config);
+        let master_volume = config.sound.master_volume;
+        let dtmf_twist = validate_dtmf_twist(config.sound.dtmf_twist);
+        let dtmf_drift = validate_dtmf_drift(config.sound.dtmf_drift);
+        let tone_plan = match config.sound.tone_plan.as_deref() {
+            Some(name) => library.borrow().load_tone_plan(name),
+            None => TonePlan::default()
+        };
+
+        let mut engine = Self {
+            library: Rc::clone(library),
+            output,
+            channels,
+            config,
+            clock: Arc::clone(clock),
+            master_volume,
+            play_log: Default::default(),
+            message_waiting: Default::default(),
+            dtmf_twist,
+            dtmf_drift,
+            tone_plan,
+        };
+
+        // Create channels
+        for ch in enum_iterator::all::<Channel>() {
+            let channel = SoundChannel::new(&engine, ch);
+            engine.channels.borrow_mut().push(channel);
+        }
+
+        engine.set_master_volume(master_volume);
+        for ch in enum_iterator::all::<Channel>() {
+            engine.reset_channel_filter(ch);
+        }
+
+        engine
+    }
+}
+
+impl SoundEngine {
+    /// Advances headless audio output to the current time.
+    pub fn tick(&self) {
+        self.output.render(self.clock.now());
+    }
+
+    /// Flushes any audio output that is buffered by the engine.
+    pub fn flush_output(&self) {
+        self.output.flush();
+    }
+
+    /// Enables or disables recording of played sounds.
+    pub fn set_play_logging(&self, enabled: bool) {
+        self.play_log.replace(if enabled { Some(vec![]) } else { None });
+    }
+
+    /// Removes and returns all sounds recorded since the last call.
+    pub fn drain_play_log(&self) -> Vec<SoundPlayRecord> {
+        self.play_log.borrow_mut().as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn log_play(&self, channel: Channel, name: impl Into<String>) {
+        if let Some(play_log) = self.play_log.borrow_mut().as_mut() {
+            play_log.push(SoundPlayRecord { channel, name: name.into() });
+        }
+    }
+
+    pub fn play(&self, key: &str, channel: Channel, wait: bool, interrupt: bool, opts: SoundPlayOptions) -> Option<PlayedSoundInfo> {
+        let sound = self.find_sound(key);
+        match sound {
+            Some(sound) => {
+                if interrupt {
+                    self.stop(channel);
+                }
+
+                // Log the resolved key so that globbed sounds can be told apart
+                let resolved_key = match key.strip_prefix('$').and_then(|key| key.split_once('/')) {
+                    Some((bank_name, _)) => format!("${}/{}", bank_name, sound.key),
+                    None => sound.key.clone(),
+                };
+                self.log_play(channel, resolved_key.as_str());
+
+                let ch = &mut self.channels.borrow_mut()[channel.as_index()];
+                let info = PlayedSoundInfo {
+                    key: resolved_key,
+                    duration: sound.duration()
+                };
+
+                // Queue sound in sink
+                ch.set_volume(VolumeLayer::Fade, 1.0);
+                ch.queue(sound, opts);
+                
+                // Optionally wait
+                if wait {
+                    self.sleep_until_end(ch);
+                }
+
+                Some(info)
+            },
+            None => {
+                warn!("WARNING: Tried to play nonexistent sound or soundglob '{}'", key);
+                None
+            }
+        }
+    }
+
+    /// Plays a live source, such as captured voice from another line, on the specified channel.
+    pub fn play_source<S>(&self, source: S, channel: Channel) where S: Source<Item = f32> + Send + 'static {
+        self.stop(channel);
+        self.log_play(channel, "@voice");
+        self.channels.borrow()[channel.as_index()].append(source);
+    }
+
+    /// Sets the line filter of a channel, or disables it if `None`. Applies to sounds that are already playing.
+    pub fn set_channel_filter(&self, channel: Channel, filter: Option<LineFilterConfig>) {
+        self.channels.borrow()[channel.as_index()].line_filter.set(filter);
+    }
+
+    /// Restores the line filter of a channel to the one in the config.
+    pub fn reset_channel_filter(&self, channel: Channel) {
+        let line_filter = &self.config.line_filter;
+        let is_enabled = line_filter.channels.iter().any(|group| group.channels().contains(&channel));
+        self.set_channel_filter(channel, if is_enabled { Some(line_filter.clone()) } else { None });
+    }
+
+    /// Gets the effects enabled on a channel.
+    pub fn channel_effects(&self, channel: Channel) -> ChannelEffects {
+        self.channels.borrow()[channel.as_index()].effects.get()
+    }
+
+    /// Changes the effects of a channel. Applies to sounds that are already playing.
+    pub fn update_channel_effects<R>(&self, channel: Channel, f: impl FnOnce(&mut ChannelEffects) -> R) -> R {
+        self.channels.borrow()[channel.as_index()].effects.update(f)
+    }
+
+    /// Disables all effects on a channel.
+    pub fn clear_channel_effects(&self, channel: Channel) {
+        self.channels.borrow()[channel.as_index()].effects.set(Default::default());
+    }
+
+    /// Gets the line filter settings from the config.
+    pub fn line_filter_config(&self) -> &LineFilterConfig {
+        &self.config.line_filter
+    }
+
+    pub fn channel_busy(&self, channel: Channel) -> bool {
+        let ch = &self.channels.borrow()[channel.as_index()];
+        ch.busy()
+    }
+
+    pub fn wait(&self, channel: Channel) {
+        let ch = &self.channels.borrow()[channel.as_index()];
+        self.sleep_until_end(ch);
+    }
+
+    /// Blocks until a channel has finished playing.
+    ///
+    /// Headless outputs only render when the engine ticks, so they are rendered here while the clock moves on
+    /// until the channel drains. With a simulated clock, this moves the clock forward by the rest of the sound's
+    /// duration without blocking.
+    fn sleep_until_end(&self, ch: &SoundChannel) {
+        if !self.output.is_headless() {
+            ch.sink.sleep_until_end();
+            return
+        }
+        while ch.busy() {
+            self.clock.sleep(HEADLESS_WAIT_STEP);
+            self.output.render(self.clock.now());
+        }
+    }
+
+    fn find_sound(&self, key: &str) -> Option<Rc<Sound>> {
+        self.library.borrow().find_sound(key)
+    }
+
+    pub fn stop_all(&self) {
+        for ch in enum_iterator::all::<Channel>() {
+            self.stop(ch);            
+        }
+    }
+
+    pub fn stop_all_except(&self, except: Channel) {
+        for ch in enum_iterator::all::<Channel>() {
+            if ch == except {
+                continue;
+            }
+            self.stop(ch);            
+        }
+    }
+
+    pub fn stop_all_nonsignal(&self) {
+        for ch in enum_iterator::all::<Channel>() {
+            match ch {
+                Channel::NoiseIn | Channel::SignalIn => continue,
+                _ => self.stop(ch)
+            }    
+        }
+    }
+
+    pub fn stop(&self, channel: Channel) {
+        let mut ch = &mut self.channels.borrow_mut()[channel.as_index()];
+        if !ch.sink.empty() {
+            ch.sink.stop();
+            ch.sink = self.output.create_sink();
+        }
+    }
+
+    pub fn is_muted(&self, channel: Channel) -> bool {
+        self.channels.borrow()[channel.as_index()].muted()
+    }
+
+    pub fn channel_speed(&self, channel: Channel) -> f32 {
+        self.channels.borrow()[channel.as_index()].speed()
+    }
+
+    pub fn set_channel_speed(&mut self, channel: Channel, speed: f32) {
+        self.channels.borrow_mut()[channel.as_index()].set_speed(speed);
+    }
+
+    pub fn set_muted(&mut self, channel: Channel, muted: bool) {
+        self.channels.borrow_mut()[channel.as_index()].set_muted(muted);
+    }
+
+    pub fn set_channel_volume(&mut self, channel: Channel, volume: f32) {
+        self.channels.borrow_mut()[channel.as_index()].set_volume(VolumeLayer::Channel, volume);
+    }
+
+    pub fn channel_volume(&self, channel: Channel) -> f32 {
+        self.channels.borrow()[channel.as_index()].volume(VolumeLayer::Channel)
+    }
+
+    pub fn set_channel_fade_volume(&mut self, channel: Channel, volume: f32) {
+        self.channels.borrow_mut()[channel.as_index()].set_volume(VolumeLayer::Fade, volume);
+    }
+
+    pub fn channel_fade_volume(&self, channel: Channel) -> f32 {
+        self.channels.borrow()[channel.as_index()].volume(VolumeLayer::Fade)
+    }
+
+    pub fn master_volume(&self) -> f32 {
+        self.master_volume
+    }
+
+    pub fn set_master_volume(&mut self, master_volume: f32) {
+        self.master_volume = master_volume;
+        for ch in enum_iterator::all::<Channel>() {
+            self.channels.borrow_mut()[ch.as_index()].set_volume(VolumeLayer::Master, master_volume);
+        }
+    }
+
+    pub fn play_dtmf(&self, key: char, dur: Duration, volume: f32) -> bool {
+        let index = DTMF_DIGITS.iter().position(|&c| c == key);
+        let f_row = match index {
+            Some(index) => DTMF_ROW_FREQUENCIES[index / 4],
+            None => return false
+        };
+        let f_col = match index {
+            Some(index) => DTMF_COLUMN_FREQUENCIES[index % 4],
+            None => return false
+        };
+        let f_row = drift_frequency(f_row, self.dtmf_drift);
+        let f_col = drift_frequency(f_col, self.dtmf_drift);
+        self.channels.borrow()[Channel::SignalOut.as_index()].queue_dtmf(f_row, f_col, self.dtmf_twist, dur, volume * self.config.sound.dtmf_volume);
+        self.log_play(Channel::SignalOut, format!("@dtmf-{}", key));
+        true
+    }
+
+    // TODO: Cache dB-to-amplitude conversions for call progress tones
+
+    /// Gets the amplitude of a tone from the tone plan, falling back to the configured gain if the plan doesn't specify a level.
+    fn tone_volume(tone: &Tone, configured_gain: f32) -> f32 {
+        db_to_amp(tone.level.unwrap_or(configured_gain))
+    }
+
+    pub fn play_ringback_tone(&self) {
+        self.stop(Channel::SignalIn);
+        let tone = &self.tone_plan.ringback;
+        self.channels.borrow()[Channel::SignalIn.as_index()].queue_tone(tone, Self::tone_volume(tone, self.config.sound.ringback_tone_gain));
+        self.log_play(Channel::SignalIn, "@ringback");
+    }
+
+    /// Plays the dial tone, or the stutter dial tone if a message is waiting.
+    pub fn play_dial_tone(&self) {
+        self.stop(Channel::SignalIn);
+        let channel = &self.channels.borrow()[Channel::SignalIn.as_index()];
+        let tone = &self.tone_plan.dial;
+        let volume = Self::tone_volume(tone, self.config.sound.dial_tone_gain);
+        if self.message_waiting.get() {
+            channel.queue_stutter_dial_tone(&tone.frequencies, volume);
+            self.log_play(Channel::SignalIn, "@stutter-dial");
+        } else {
+            channel.queue_tone(tone, volume);
+            self.log_play(Channel::SignalIn, "@dial");
+        }
+    }
+
+    /// Sets whether a message is waiting, which makes the dial tone stutter.
+    pub fn set_message_waiting(&self, message_waiting: bool) {
+        self.message_waiting.set(message_waiting);
+    }
+
+    /// Plays the confirmation tone (three short bursts of dial tone), followed by a steady dial tone.
+    pub fn play_confirmation_tone(&self) {
+        self.stop(Channel::SignalIn);
+        let tone = &self.tone_plan.dial;
+        self.channels.borrow()[Channel::SignalIn.as_index()].queue_confirmation_tone(&tone.frequencies, Self::tone_volume(tone, self.config.sound.dial_tone_gain));
+        self.log_play(Channel::SignalIn, "@confirm");
+    }
+
+    pub fn play_call_waiting_tone(&self) {
+        self.stop(Channel::SignalIn);
+        self.channels.borrow()[Channel::SignalIn.as_index()].queue_call_waiting_tone(db_to_amp(self.config.sound.call_waiting_tone_gain));
+        self.log_play(Channel::SignalIn, "@call-waiting");
+    }
+
+    /// Plays a caller ID burst on the specified channel.
+    pub fn play_caller_id(&self, info: &CallerIdInfo, format: CallerIdFormat, channel: Channel, gain: f32) {
+        self.stop(channel);
+        self.channels.borrow()[channel.as_index()].append(caller_id_source(info, format, db_to_amp(gain)));
+        self.log_play(channel, "@caller-id");
+    }
+
+    pub fn play_voicemail_beep(&self) {
+        self.stop(Channel::SignalIn);
+        self.channels.borrow()[Channel::SignalIn.as_index()].queue_voicemail_beep(db_to_amp(self.config.sound.dial_tone_gain));
+        self.log_play(Channel::SignalIn, "@voicemail-beep");
+    }
+
+    pub fn play_busy_tone(&self) {
+        self.stop(Channel::SignalIn);
+        let tone = &self.tone_plan.busy;
+        self.channels.borrow()[Channel::SignalIn.as_index()].queue_tone(tone, Self::tone_volume(tone, self.config.sound.busy_tone_gain));
+        self.log_play(Channel::SignalIn, "@busy");
+    }
+
+    pub fn play_fast_busy_tone(&self) {
+        self.stop(Channel::SignalIn);
+        let tone = &self.tone_plan.fast_busy;
+        self.channels.borrow()[Channel::SignalIn.as_index()].queue_tone(tone, Self::tone_volume(tone, self.config.sound.busy_tone_gain));
+        self.log_play(Channel::SignalIn, "@fast-busy");
+    }
+
+    pub fn play_off_hook_tone(&self) {
+        self.stop(Channel::SignalIn);
+        let tone = &self.tone_plan.off_hook;
+        self.channels.borrow()[Channel::SignalIn.as_index()].queue_tone(tone, Self::tone_volume(tone, self.config.sound.off_hook_tone_gain));
+        self.log_play(Channel::SignalIn, "@off-hook");
+    }
+
+    pub fn play_panic_tone(&self) {
+        self.stop(Channel::SignalIn);
+        self.channels.borrow()[Channel::Debug.as_index()].queue_panic_tone(1.0);
+        self.log_play(Channel::Debug, "@panic");
+    }
+
+    pub fn play_special_info_tone(&self, sit: SpecialInfoTone) {
+        let (first, second, third) = sit.as_segments();
+        self.stop(Channel::SignalIn);
+        self.channels.borrow()[Channel::SignalIn.as_index()].queue_special_info_tone(
+            first, 
+            second, 
+            third, 
+            db_to_amp(self.config.sound.special_info_tone_gain));
+        self.log_play(Channel::SignalIn, format!("@sit-{:?}", sit));
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum VolumeLayer {
+    /// Master volume mix
+    Master,
+    /// Channel volume mix
+    Channel,
+    /// Fade volume mix
+    Fade,
+}
+
+impl SoundChannel {
+    fn new(engine: &SoundEngine, id: Channel) -> Self {
+        let sink = engine.output.create_sink();
+        let ch = Self {
+            sink,
+            id,
+            volume_master: 1.0,
+            volume_channel: 1.0,
+            volume_fade: 1.0,
+            muted: false,
+            line_filter: Default::default(),
+            effects: Default::default(),
+        };
+        //ch.update_sink_volume(engine.master_volume);
+        ch
+    }
+}
+
+impl SoundChannel {
+    fn update_sink_volume(&mut self) -> &mut Self {
+        self.sink.set_volume(self.mixed_volume());
+        // trace!("Sink volume for Channel {:?} is now {}", self.id, self.sink.volume());
+        self
+    }
+
+    fn mixed_volume(&self) -> f32 {
+        self.volume_master * self.volume_channel * self.volume_fade * if self.muted { 0.0 } else { 1.0 }
+    }
+
+    fn volume(&self, layer: VolumeLayer) -> f32 {
+        match layer {
+            VolumeLayer::Master => self.volume_master,
+            VolumeLayer::Channel => self.volume_channel,
+            VolumeLayer::Fade => self.volume_fade,
+        }
+    }
+
+    fn set_volume(&mut self, layer: VolumeLayer, volume: f32) -> &mut Self {
+        let dst_volume = match layer {
+            VolumeLayer::Master => &mut self.volume_master,
+            VolumeLayer::Channel => &mut self.volume_channel,
+            VolumeLayer::Fade => &mut self.volume_fade,
+        };
+        *dst_volume = volume;
+        self.update_sink_volume();
+        self
+    }
+
+    fn speed(&self) -> f32 {
+        self.sink.speed()
+    }
+
+    fn set_speed(&self, speed: f32) {
+        self.sink.set_speed(speed);
+    }
+
+    fn muted(&self) -> bool {
+        self.muted
+    }
+
+    fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.update_sink_volume();
+    }
+
+    fn busy(&self) -> bool {
+        !self.sink.empty()
+    }
+
+    /// Queues a source to play through the channel's effects and line filter.
+    fn append<S>(&self, source: S) where S: Source + Send + 'static, S::Item: rodio::Sample + Send, f32: rodio::cpal::FromSample<S::Item> {
+        let source = EffectsSource::new(source.convert_samples::<f32>(), Arc::clone(&self.effects));
+        self.sink.append::<LineFilterSource<_>>(LineFilterSource::new(source, Arc::clone(&self.line_filter)));
+    }
+
+    fn kill(&self) {
+        self.sink.stop();
+    }
+
+    fn queue(&self, snd: Rc<Sound>, opts: SoundPlayOptions) {
+        let src = snd.source(opts.looping).amplify(opts.volume);
+        if let Some(delay) = opts.delay {
+            self.append(rodio::source::Empty::<i16>::new().delay(delay))
+        }
+        let skip = match &opts.skip {
+            SoundPlaySkip::By(duration) => *duration,
+            SoundPlaySkip::Random => Duration::from_secs_f64(rand::thread_rng().gen_range(0.0 ..= snd.duration().unwrap_or_default().as_secs_f64())),
+        };
+        let is_nonstandard_speed = opts.speed != 1.0;
+        if let Some(take) = opts.take {
+            if is_nonstandard_speed {
+                self.append(src.skip_duration(skip).take_duration(take).speed(opts.speed).fade_in(opts.fadein));
+            } else {
+                self.append(src.skip_duration(skip).take_duration(take).fade_in(opts.fadein));
+            }
+        } else {
+            if is_nonstandard_speed {
+                self.append(src.skip_duration(skip).speed(opts.speed).fade_in(opts.fadein));
+            } else {
+                self.append(src.skip_duration(skip).fade_in(opts.fadein));
+            }
+        }
+    }
+
+    /// Queues a DTMF tone, with the column tone `twist` dB louder than the row tone.
+    /// The combined peak amplitude of both tones is `volume`.
+    fn queue_dtmf(&self, f_row: f32, f_col: f32, twist: f32, dur: Duration, volume: f32) {
+        let col_ratio = db_to_amp(twist);
+        let row_volume = volume / (1.0 + col_ratio);
+        let col_volume = volume * col_ratio / (1.0 + col_ratio);
+        let sine_row = rodio::source::SineWave::new(f_row).amplify(row_volume);
+        let sine_col = rodio::source::SineWave::new(f_col).amplify(col_volume);
+        let dtmf_tone = sine_row.mix(sine_col)
+        .take_duration(dur);
+        self.append(dtmf_tone);
+    }
+
+    fn queue_tone(&self, tone: &Tone, volume: f32) {
+        self.append(tone.source(volume));
+    }
+
+    fn queue_stutter_dial_tone(&self, frequencies: &[f32], volume: f32) {
+        const BURST_COUNT: u32 = 10;
+        self.queue_dial_tone_bursts(frequencies, BURST_COUNT, volume);
+    }
+
+    fn queue_confirmation_tone(&self, frequencies: &[f32], volume: f32) {
+        const BURST_COUNT: u32 = 3;
+        self.queue_dial_tone_bursts(frequencies, BURST_COUNT, volume);
+    }
+
+    /// Queues short bursts of dial tone, followed by a steady dial tone.
+    fn queue_dial_tone_bursts(&self, frequencies: &[f32], burst_count: u32, volume: f32) {
+        const CADENCE_MS: u64 = 100;
+        let bursts = Tone::new(frequencies, &[CADENCE_MS, CADENCE_MS]);
+        let steady = Tone::new(frequencies, &[]);
+        self.append(bursts.source(volume).take_duration(Duration::from_millis(CADENCE_MS * 2) * burst_count));
+        self.append(steady.source(volume));
+    }
+
+    fn queue_call_waiting_tone(&self, volume: f32) {
+        const FREQ_CALL_WAITING: f32 = 440.0;
+        let sine = rodio::source::SineWave::new(FREQ_CALL_WAITING);
+        let call_waiting_tone = sine.take_duration(Duration::from_millis(300)).amplify(volume);
+        self.append(call_waiting_tone);
+    }
+
+    fn queue_voicemail_beep(&self, volume: f32) {
+        const FREQ_VOICEMAIL_BEEP: f32 = 1000.0;
+        let sine = rodio::source::SineWave::new(FREQ_VOICEMAIL_BEEP);
+        let beep = sine.take_duration(VOICEMAIL_BEEP_DURATION).amplify(volume);
+        self.append(beep);
+    }
+
+    fn queue_panic_tone(&self, volume: f32) {
+        const FREQ_PANIC_A: f32 = 720.0;
+        const FREQ_PANIC_B: f32 = 900.0;
+        let half_volume = volume * 0.5;
+        let sine1 = rodio::source::SineWave::new(FREQ_PANIC_A);
+        let sine2 = rodio::source::SineWave::new(FREQ_PANIC_B);
+        let cadence = Duration::from_millis(375);
+        let busy_start = sine1.mix(sine2).take_duration(cadence).amplify(half_volume);
+        let busy_loop = busy_start.clone().delay(cadence).repeat_infinite();
+        self.append(busy_start);
+        self.append(busy_loop);
+    }
+
+    fn queue_special_info_tone(&self, first: SitSegment, second: SitSegment, third: SitSegment, volume: f32) {
+        use SitSegment::*;
+        let (f1, d1) = match first {
+            Low(len) => (SIT_FREQS_FIRST.0, len.as_ms()),
+            High(len) => (SIT_FREQS_FIRST.1, len.as_ms())
+        };
+        let (f2, d2) = match second {
+            Low(len) => (SIT_FREQS_SECOND.0, len.as_ms()),
+            High(len) => (SIT_FREQS_SECOND.1, len.as_ms())
+        };
+        let (f3, d3) = match third {
+            Low(len) => (SIT_FREQS_THIRD.0, len.as_ms()),
+            High(len) => (SIT_FREQS_THIRD.1, len.as_ms())
+        };
+        let sine1 = rodio::source::SineWave::new(f1 as f32)
+            .take_duration(Duration::from_millis(d1))
+            .amplify(volume);
+        let sine2 = rodio::source::SineWave::new(f2 as f32)
+            .take_duration(Duration::from_millis(d2))
+            .amplify(volume);
+        let sine3 = rodio::source::SineWave::new(f3 as f32)
+            .take_duration(Duration::from_millis(d3))
+            .amplify(volume);
+        self.append(sine1);
+        self.append(sine2);
+        self.append(sine3);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use crate::clock::SimulatedClock;
+    use vfs::MemoryFS;
+
+    fn test_config() -> CursedConfig {
+        let mut config = crate::config::load_config(concat!(env!("CARGO_MANIFEST_DIR"), "/cursed_phone.conf"), &Default::default());
+        config.sound.output = SoundOutputType::Null;
+        config
+    }
+
+    fn test_sound_engine(config: CursedConfig) -> (SoundEngine, Arc<SimulatedClock>) {
+        let root: VfsPath = MemoryFS::new().into();
+        for dir in ["sounds", "soundbanks", "tone_plans"] {
+            root.join(dir).unwrap().create_dir().unwrap();
+        }
+        let wav = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/res/sounds/rotary/pulse.wav")).unwrap();
+        root.join("sounds/pulse.wav").unwrap().create_file().unwrap().write_all(&wav).unwrap();
+        let library = Rc::new(RefCell::new(SoundLibrary::new(root.join("sounds").unwrap(), root.join("soundbanks").unwrap(), root.join("tone_plans").unwrap(), Default::default())));
+        let simulated_clock = Arc::new(SimulatedClock::new());
+        let clock: Arc<dyn Clock> = simulated_clock.clone();
+        (SoundEngine::new(&library, &Rc::new(config), &clock), simulated_clock)
+    }
+
+    /// Plays a DTMF key into a WAV file and returns the recorded samples and their sample rate.
+    fn render_dtmf(name: &str, key: char, twist: f32, drift: f32) -> (Vec<f32>, u32) {
+        let path = std::env::temp_dir().join(format!("cursed_phone_test_{}_{}.wav", name, std::process::id()));
+        let mut config = test_config();
+        config.sound.output = SoundOutputType::Wav;
+        config.sound.output_path = Some(path.to_string_lossy().into_owned());
+        config.sound.dtmf_twist = twist;
+        config.sound.dtmf_drift = drift;
+        config.line_filter.channels.clear();
+        let (sound_engine, _) = test_sound_engine(config);
+        assert!(sound_engine.play_dtmf(key, Duration::from_millis(200), 1.0));
+        sound_engine.wait(Channel::SignalOut);
+        sound_engine.flush_output();
+        drop(sound_engine);
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        let sample_rate = reader.spec().sample_rate;
+        let samples = reader.samples::<i16>().map(|s| s.unwrap() as f32 / i16::MAX as f32).collect();
+        std::fs::remove_file(&path).unwrap();
+        (samples, sample_rate)
+    }
+
+    /// Measures the amplitude of one frequency in a signal, using a Hann window to keep other tones from leaking into it.
+    fn tone_amplitude(samples: &[f32], sample_rate: u32, freq: f32) -> f32 {
+        use std::f64::consts::TAU;
+        let (mut re, mut im) = (0.0f64, 0.0f64);
+        for (i, s) in samples.iter().enumerate() {
+            let window = 0.5 - 0.5 * (TAU * i as f64 / samples.len() as f64).cos();
+            let phase = TAU * freq as f64 * i as f64 / sample_rate as f64;
+            re += *s as f64 * window * phase.cos();
+            im += *s as f64 * window * phase.sin();
+        }
+        (4.0 * (re * re + im * im).sqrt() / samples.len() as f64) as f32
+    }
+
+    /// Finds the strongest frequency within `spread` percent of `freq`.
+    fn peak_frequency(samples: &[f32], sample_rate: u32, freq: f32, spread: f32) -> f32 {
+        let steps = (freq * spread / 100.0 / 0.25) as i32;
+        (-steps..=steps)
+            .map(|step| freq + step as f32 * 0.25)
+            .max_by(|a, b| tone_amplitude(samples, sample_rate, *a).total_cmp(&tone_amplitude(samples, sample_rate, *b)))
+            .unwrap()
+    }
+
+    /// Returns the samples of a rendered tone, without its start and end.
+    fn steady_part(samples: &[f32], sample_rate: u32) -> &[f32] {
+        let margin = sample_rate as usize / 100;
+        assert!(samples.len() > margin * 4, "rendered only {} samples", samples.len());
+        &samples[margin..samples.len() - margin]
+    }
+
+    #[test]
+    fn headless_wait_advances_simulated_clock() {
+        let (sound_engine, simulated_clock) = test_sound_engine(test_config());
+
+        let duration = sound_engine.play("pulse", Channel::SignalOut, true, true, Default::default()).unwrap().duration.unwrap();
+        let elapsed = simulated_clock.elapsed();
+        assert!(!sound_engine.channel_busy(Channel::SignalOut));
+        assert!(elapsed >= duration && elapsed <= duration + 2 * HEADLESS_WAIT_STEP, "waited {:?} for a {:?} sound", elapsed, duration);
+
+        // Waiting for an idle channel returns at once
+        sound_engine.wait(Channel::SignalOut);
+        assert_eq!(simulated_clock.elapsed(), elapsed);
+    }
+
+    #[test]
+    fn dtmf_twist_sets_column_level_relative_to_row() {
+        // Key 5 is 770 Hz (row) and 1336 Hz (column)
+        for (name, twist, expected) in [("twist_flat", 0.0, 0.0), ("twist_3db", 3.0, 3.0), ("twist_reverse", -6.0, -6.0), ("twist_over", 10.0, DTMF_MAX_TWIST), ("twist_under", -12.0, -DTMF_MAX_REVERSE_TWIST)] {
+            let (samples, sample_rate) = render_dtmf(name, '5', twist, 0.0);
+            let samples = steady_part(&samples, sample_rate);
+            let row = tone_amplitude(samples, sample_rate, 770.0);
+            let col = tone_amplitude(samples, sample_rate, 1336.0);
+            let measured = 20.0 * (col / row).log10();
+            assert!((measured - expected).abs() < 0.1, "twist of {} dB rendered as {} dB, expected {} dB", twist, measured, expected);
+        }
+    }
+
+    #[test]
+    fn dtmf_drift_stays_within_tolerance() {
+        for (name, drift) in [("drift_max", DTMF_MAX_DRIFT), ("drift_over", 5.0)] {
+            let (samples, sample_rate) = render_dtmf(name, '9', 0.0, drift);
+            let samples = steady_part(&samples, sample_rate);
+            // Key 9 is 852 Hz (row) and 1477 Hz (column)
+            for freq in [852.0, 1477.0] {
+                let peak = peak_frequency(samples, sample_rate, freq, 3.0);
+                assert!((peak - freq).abs() <= freq * DTMF_MAX_DRIFT / 100.0 + 0.5, "{} Hz drifted to {} Hz with {}% drift", freq, peak, drift);
+            }
+        }
+
+        let drifted: Vec<f32> = (0..1000).map(|_| drift_frequency(1000.0, DTMF_MAX_DRIFT)).collect();
+        assert!(drifted.iter().all(|f| (985.0..=1015.0).contains(f)));
+        let spread = drifted.iter().cloned().fold(f32::MIN, f32::max) - drifted.iter().cloned().fold(f32::MAX, f32::min);
+        assert!(spread > 20.0, "drifted frequencies only spread over {} Hz", spread);
+        assert_eq!(drift_frequency(1000.0, 0.0), 1000.0);
+    }
+
+    #[test]
+    fn out_of_range_dtmf_settings_are_limited() {
+        assert_eq!(validate_dtmf_twist(2.5), 2.5);
+        assert_eq!(validate_dtmf_twist(10.0), DTMF_MAX_TWIST);
+        assert_eq!(validate_dtmf_twist(-12.0), -DTMF_MAX_REVERSE_TWIST);
+        assert_eq!(validate_dtmf_twist(f32::NAN), 0.0);
+        assert_eq!(validate_dtmf_drift(1.0), 1.0);
+        assert_eq!(validate_dtmf_drift(5.0), DTMF_MAX_DRIFT);
+        assert_eq!(validate_dtmf_drift(-1.0), 0.0);
+        assert_eq!(validate_dtmf_drift(f32::INFINITY), 0.0);
+
+        let mut config = test_config();
+        config.sound.dtmf_twist = 10.0;
+        config.sound.dtmf_drift = -1.0;
+        let (sound_engine, _) = test_sound_engine(config);
+        assert_eq!(sound_engine.dtmf_twist, DTMF_MAX_TWIST);
+        assert_eq!(sound_engine.dtmf_drift, 0.0);
+    }
+}
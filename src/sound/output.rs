@@ -0,0 +1,166 @@
+use crate::config::*;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::Arc;
+use std::time::Instant;
+use rodio::dynamic_mixer::{DynamicMixer, DynamicMixerController};
+use log::{info, warn, error};
+
+const HEADLESS_DEFAULT_SAMPLE_RATE: u32 = 44100;
+const HEADLESS_CHANNEL_COUNT: u16 = 1;
+
+/// The audio backend that sound channels are mixed into.
+pub enum SoundOutput {
+    /// Plays through the default audio device of the host.
+    Device {
+        _stream: rodio::OutputStream,
+        handle: rodio::OutputStreamHandle,
+    },
+    /// Mixes all channels in software without touching an audio device.
+    /// Samples are pulled from the mixer as time passes and are either discarded or written to a WAV file.
+    Headless(RefCell<HeadlessOutput>),
+}
+
+pub struct HeadlessOutput {
+    controller: Arc<DynamicMixerController<f32>>,
+    mixer: DynamicMixer<f32>,
+    sample_rate: u32,
+    /// Time up to which the mixer has been rendered.
+    rendered_until: Instant,
+    /// Fractional frame carried over between renders.
+    frame_remainder: f64,
+    /// Destination of the rendered samples, if any.
+    wav_writer: Option<hound::WavWriter<BufWriter<File>>>,
+    /// Number of frames written since the WAV header was last updated.
+    frames_since_flush: u64,
+}
+
+impl SoundOutput {
+    pub fn from_config(config: &SoundConfig, now: Instant) -> Self {
+        match config.output {
+            SoundOutputType::Device => {
+                let (stream, handle) = match config.output_device.as_deref() {
+                    Some(device_name) => {
+                        let device = find_output_device(device_name).unwrap_or_else(|| panic!("Audio output device not found: {}", device_name));
+                        info!("Using audio output device: {}", device_name);
+                        rodio::OutputStream::try_from_device(&device).expect("Failed to open audio output device!")
+                    },
+                    None => rodio::OutputStream::try_default().expect("Failed to open audio output device!")
+                };
+                Self::Device {
+                    _stream: stream,
+                    handle
+                }
+            },
+            SoundOutputType::Null => {
+                info!("Using null audio output.");
+                Self::Headless(RefCell::new(HeadlessOutput::new(config.output_sample_rate, now, None)))
+            },
+            SoundOutputType::Wav => {
+                let path = config.output_path.as_deref().expect("missing output-path for WAV audio output");
+                let spec = hound::WavSpec {
+                    channels: HEADLESS_CHANNEL_COUNT,
+                    sample_rate: config.output_sample_rate.unwrap_or(HEADLESS_DEFAULT_SAMPLE_RATE),
+                    bits_per_sample: 16,
+                    sample_format: hound::SampleFormat::Int,
+                };
+                let writer = hound::WavWriter::create(path, spec).expect("Failed to create WAV audio output file");
+                info!("Recording audio output to: {}", path);
+                Self::Headless(RefCell::new(HeadlessOutput::new(config.output_sample_rate, now, Some(writer))))
+            }
+        }
+    }
+
+    /// Creates a new sink that plays into this output.
+    pub fn create_sink(&self) -> rodio::Sink {
+        match self {
+            Self::Device { handle, .. } => rodio::Sink::try_new(handle).expect("Failed to create sound channel"),
+            Self::Headless(output) => {
+                let (sink, queue) = rodio::Sink::new_idle();
+                output.borrow().controller.add(queue);
+                sink
+            }
+        }
+    }
+
+    /// Returns `true` if the output is mixed in software.
+    pub fn is_headless(&self) -> bool {
+        matches!(self, Self::Headless(_))
+    }
+
+    /// Advances a headless output to the specified time. Does nothing for device outputs.
+    pub fn render(&self, now: Instant) {
+        if let Self::Headless(output) = self {
+            output.borrow_mut().render(now);
+        }
+    }
+
+    /// Writes any buffered samples to their destination.
+    pub fn flush(&self) {
+        if let Self::Headless(output) = self {
+            output.borrow_mut().flush();
+        }
+    }
+}
+
+/// Finds the host audio output device with the specified name.
+fn find_output_device(name: &str) -> Option<rodio::Device> {
+    use rodio::cpal::traits::HostTrait;
+    use rodio::DeviceTrait;
+    rodio::cpal::default_host()
+        .output_devices()
+        .ok()?
+        .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+}
+
+impl HeadlessOutput {
+    fn new(sample_rate: Option<u32>, now: Instant, wav_writer: Option<hound::WavWriter<BufWriter<File>>>) -> Self {
+        let sample_rate = sample_rate.unwrap_or(HEADLESS_DEFAULT_SAMPLE_RATE);
+        let (controller, mixer) = rodio::dynamic_mixer::mixer::<f32>(HEADLESS_CHANNEL_COUNT, sample_rate);
+        Self {
+            controller,
+            mixer,
+            sample_rate,
+            rendered_until: now,
+            frame_remainder: 0.0,
+            wav_writer,
+            frames_since_flush: 0,
+        }
+    }
+
+    fn render(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.rendered_until);
+        self.rendered_until = now;
+        let frames_exact = elapsed.as_secs_f64() * self.sample_rate as f64 + self.frame_remainder;
+        let frames = frames_exact.floor();
+        self.frame_remainder = frames_exact - frames;
+
+        for _ in 0..(frames as u64 * HEADLESS_CHANNEL_COUNT as u64) {
+            // The mixer runs dry when every sink has been dropped; treat that as silence
+            let sample = self.mixer.next().unwrap_or(0.0);
+            if let Some(writer) = self.wav_writer.as_mut() {
+                let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                if let Err(err) = writer.write_sample(sample) {
+                    error!("Failed to write audio output: {}", err);
+                    self.wav_writer = None;
+                }
+            }
+        }
+
+        // Keep the WAV header current so the recording stays readable if the process is killed
+        self.frames_since_flush += frames as u64;
+        if self.frames_since_flush >= self.sample_rate as u64 {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        self.frames_since_flush = 0;
+        if let Some(writer) = self.wav_writer.as_mut() {
+            if let Err(err) = writer.flush() {
+                warn!("Failed to flush audio output: {}", err);
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::sync::Mutex;
+use std::time::Duration;
+use log::error;
+use rodio::Source;
+use rodio::buffer::SamplesBuffer;
+use rodio::source::Buffered;
+use vfs::{SeekAndRead, VfsPath};
+
+/// Number of bytes at the end of an Ogg file that are searched for the last page.
+const OGG_TAIL_LEN: u64 = 65536;
+
+/// A file opened from the VFS, made shareable between threads so that it can be decoded on the audio thread.
+pub struct VfsFileReader {
+    file: Mutex<Box<dyn SeekAndRead + Send>>,
+}
+
+impl VfsFileReader {
+    pub fn open(path: &VfsPath) -> Result<Self, String> {
+        let file = path.open_file().map_err(|err| err.to_string())?;
+        Ok(Self {
+            file: Mutex::new(file)
+        })
+    }
+}
+
+impl Read for VfsFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.file.get_mut().unwrap().read(buf)
+    }
+}
+
+impl Seek for VfsFileReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.get_mut().unwrap().seek(pos)
+    }
+}
+
+pub type VfsDecoder = rodio::Decoder<BufReader<VfsFileReader>>;
+
+/// Opens a decoder that reads a sound file from the VFS as it plays.
+pub fn open_decoder(path: &VfsPath) -> Result<VfsDecoder, String> {
+    let reader = VfsFileReader::open(path)?;
+    rodio::Decoder::new(BufReader::new(reader)).map_err(|err| format!("unable to decode {}: {}", path.as_str(), err))
+}
+
+/// Finds the duration of an Ogg file from the granule position of its last page, without decoding it.
+pub fn ogg_duration(path: &VfsPath, sample_rate: u32) -> Option<Duration> {
+    let mut file = path.open_file().ok()?;
+    let len = file.seek(SeekFrom::End(0)).ok()?;
+    file.seek(SeekFrom::Start(len.saturating_sub(OGG_TAIL_LEN))).ok()?;
+    let mut tail = vec![];
+    file.read_to_end(&mut tail).ok()?;
+    // Page headers start with the capture pattern and version, followed by the header type and granule position
+    let granule_pos = tail.windows(14).rev()
+        .filter(|header| header.starts_with(b"OggS\0"))
+        .map(|header| i64::from_le_bytes(header[6..14].try_into().unwrap()))
+        .find(|granule_pos| *granule_pos >= 0)?;
+    Some(Duration::from_secs_f64(granule_pos as f64 / sample_rate as f64))
+}
+
+/// The samples of a sound.
+#[derive(Clone)]
+pub enum SoundData {
+    /// Samples fully decoded into memory.
+    Buffered(Buffered<SamplesBuffer<i16>>),
+    /// Samples decoded from the file whenever the sound plays.
+    Streamed(VfsPath),
+}
+
+impl SoundData {
+    fn open(&self) -> Option<SoundDataSource> {
+        match self {
+            Self::Buffered(src) => Some(SoundDataSource::Buffered(src.clone())),
+            Self::Streamed(path) => match open_decoder(path) {
+                Ok(decoder) => Some(SoundDataSource::Streamed(Box::new(decoder))),
+                Err(err) => {
+                    error!("Unable to stream sound: {}", err);
+                    None
+                }
+            }
+        }
+    }
+}
+
+enum SoundDataSource {
+    Buffered(Buffered<SamplesBuffer<i16>>),
+    Streamed(Box<VfsDecoder>),
+}
+
+impl SoundDataSource {
+    fn as_source(&self) -> &dyn Source<Item = i16> {
+        match self {
+            Self::Buffered(src) => src,
+            Self::Streamed(src) => src.as_ref(),
+        }
+    }
+}
+
+impl Iterator for SoundDataSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        match self {
+            Self::Buffered(src) => src.next(),
+            Self::Streamed(src) => src.next(),
+        }
+    }
+}
+
+/// Plays the samples of a sound, optionally restarting them whenever they end.
+///
+/// Unlike `Source::repeat_infinite`, looping doesn't keep a copy of the samples, so streamed sounds stay out of memory.
+pub struct SoundSource {
+    data: SoundData,
+    current: Option<SoundDataSource>,
+    looping: bool,
+}
+
+impl SoundSource {
+    pub fn new(data: &SoundData, looping: bool) -> Self {
+        Self {
+            data: data.clone(),
+            current: data.open(),
+            looping,
+        }
+    }
+}
+
+impl Iterator for SoundSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let current = self.current.as_mut()?;
+        if let Some(sample) = current.next() {
+            return Some(sample)
+        }
+        if !self.looping {
+            self.current = None;
+            return None
+        }
+        // Stop instead of restarting forever if the sound has no samples
+        self.current = self.data.open();
+        let sample = self.current.as_mut()?.next();
+        if sample.is_none() {
+            self.current = None;
+        }
+        sample
+    }
+}
+
+impl Source for SoundSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        match &self.current {
+            Some(current) => current.as_source().current_frame_len(),
+            None => Some(0)
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        match &self.current {
+            Some(current) => current.as_source().channels(),
+            None => 1
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        match &self.current {
+            Some(current) => current.as_source().sample_rate(),
+            None => 44100
+        }
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        if self.looping {
+            return None
+        }
+        self.current.as_ref().and_then(|current| current.as_source().total_duration())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vfs::PhysicalFS;
+
+    #[test]
+    fn ogg_duration_matches_decoded_length() {
+        let root: VfsPath = PhysicalFS::new(concat!(env!("CARGO_MANIFEST_DIR"), "/res")).into();
+        let path = root.join("soundbanks/beyond/amb_crows.ogg").unwrap();
+        let decoder = open_decoder(&path).unwrap();
+        let samples_per_sec = decoder.sample_rate() as f64 * decoder.channels() as f64;
+        let sample_rate = decoder.sample_rate();
+        let decoded_duration = decoder.count() as f64 / samples_per_sec;
+        let duration = ogg_duration(&path, sample_rate).unwrap().as_secs_f64();
+        assert!((duration - decoded_duration).abs() < 0.01, "read {} s, decoded {} s", duration, decoded_duration);
+    }
+}
@@ -0,0 +1,251 @@
+use std::f32::consts::TAU;
+use std::time::Duration;
+use rodio::Source;
+use serde::Deserialize;
+use log::{info, error};
+use vfs::VfsPath;
+
+/// Sample rate of generated call progress tones.
+const TONE_SAMPLE_RATE: u32 = 48000;
+
+/// A call progress tone: a mix of frequencies, switched on and off by a cadence.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Tone {
+    /// Frequencies (in Hz) mixed at equal levels.
+    pub frequencies: Vec<f32>,
+    /// Alternating on and off times (in milliseconds), repeated forever. The tone is continuous if empty.
+    #[serde(default)]
+    pub cadence: Vec<u64>,
+    /// Level (in dB) of the tone. Uses the configured gain for the tone if unspecified.
+    #[serde(default)]
+    pub level: Option<f32>,
+}
+
+impl Tone {
+    pub fn new(frequencies: &[f32], cadence: &[u64]) -> Self {
+        Self {
+            frequencies: frequencies.to_vec(),
+            cadence: cadence.to_vec(),
+            level: None,
+        }
+    }
+
+    fn validate(&self, name: &str) -> Result<(), String> {
+        if self.frequencies.is_empty() {
+            return Err(format!("{} tone has no frequencies", name))
+        }
+        if self.frequencies.iter().any(|f| !f.is_finite() || *f <= 0.0) {
+            return Err(format!("{} tone has an invalid frequency", name))
+        }
+        if self.cadence.len() % 2 == 1 {
+            return Err(format!("{} tone cadence must have an off time for every on time", name))
+        }
+        if !self.cadence.is_empty() && self.cadence.iter().sum::<u64>() == 0 {
+            return Err(format!("{} tone cadence has no length", name))
+        }
+        Ok(())
+    }
+
+    /// Creates a source that plays the tone at the specified total amplitude.
+    pub fn source(&self, volume: f32) -> ToneSource {
+        ToneSource::new(&self.frequencies, &self.cadence, volume)
+    }
+}
+
+/// Describes the call progress tones of a region's telephone network.
+///
+/// Tones that a plan doesn't define use the North American Precise Tone Plan.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct TonePlan {
+    pub name: String,
+    pub dial: Tone,
+    pub ringback: Tone,
+    pub busy: Tone,
+    pub fast_busy: Tone,
+    pub off_hook: Tone,
+}
+
+impl Default for TonePlan {
+    /// Creates the North American Precise Tone Plan.
+    fn default() -> Self {
+        Self {
+            name: "North American Precise Tone Plan".to_owned(),
+            dial: Tone::new(&[350.0, 440.0], &[]),
+            ringback: Tone::new(&[440.0, 480.0], &[2000, 4000]),
+            busy: Tone::new(&[480.0, 620.0], &[500, 500]),
+            fast_busy: Tone::new(&[480.0, 620.0], &[250, 250]),
+            off_hook: Tone::new(&[1400.0, 2060.0, 2450.0, 2600.0], &[100, 100]),
+        }
+    }
+}
+
+impl TonePlan {
+    /// Loads the tone plan with the specified name from a directory of tone plans.
+    /// Uses the default plan if it can't be loaded.
+    pub fn load(root_dir: &VfsPath, name: &str) -> Self {
+        let plan = root_dir.join(format!("{}.toml", name))
+            .map_err(|err| err.to_string())
+            .and_then(|path| Self::from_file(&path));
+        match plan {
+            Ok(plan) => {
+                info!("Using tone plan: {}", plan.name);
+                plan
+            },
+            Err(err) => {
+                error!("Failed to load tone plan '{}', using the default: {}", name, err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Loads a tone plan from a TOML file.
+    pub fn from_file(path: &VfsPath) -> Result<Self, String> {
+        let src = path.read_to_string().map_err(|err| format!("unable to read {}: {}", path.as_str(), err))?;
+        let plan: TonePlan = toml::from_str(&src).map_err(|err| format!("unable to parse {}: {}", path.as_str(), err))?;
+        plan.dial.validate("dial")?;
+        plan.ringback.validate("ringback")?;
+        plan.busy.validate("busy")?;
+        plan.fast_busy.validate("fast-busy")?;
+        plan.off_hook.validate("off-hook")?;
+        Ok(plan)
+    }
+}
+
+/// An endless mix of sine waves, gated by a cadence.
+#[derive(Clone)]
+pub struct ToneSource {
+    /// Phase increment per sample of each frequency.
+    steps: Vec<f32>,
+    phases: Vec<f32>,
+    /// Alternating on and off times, in samples.
+    cadence: Vec<u64>,
+    cycle_len: u64,
+    amplitude: f32,
+    position: u64,
+}
+
+impl ToneSource {
+    fn new(frequencies: &[f32], cadence: &[u64], volume: f32) -> Self {
+        let cadence: Vec<u64> = cadence.iter().map(|ms| ms * TONE_SAMPLE_RATE as u64 / 1000).collect();
+        Self {
+            steps: frequencies.iter().map(|f| TAU * f / TONE_SAMPLE_RATE as f32).collect(),
+            phases: vec![0.0; frequencies.len()],
+            cycle_len: cadence.iter().sum(),
+            cadence,
+            amplitude: volume / frequencies.len().max(1) as f32,
+            position: 0,
+        }
+    }
+
+    fn is_on(&self) -> bool {
+        if self.cycle_len == 0 {
+            return true
+        }
+        let mut pos = self.position % self.cycle_len;
+        for (i, len) in self.cadence.iter().enumerate() {
+            if pos < *len {
+                return i % 2 == 0
+            }
+            pos -= len;
+        }
+        false
+    }
+}
+
+impl Iterator for ToneSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let is_on = self.is_on();
+        let mut sample = 0.0;
+        for (phase, step) in self.phases.iter_mut().zip(self.steps.iter()) {
+            if is_on {
+                sample += phase.sin();
+            }
+            *phase = (*phase + step) % TAU;
+        }
+        self.position += 1;
+        Some(sample * self.amplitude)
+    }
+}
+
+impl Source for ToneSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        TONE_SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use vfs::{MemoryFS, PhysicalFS};
+
+    fn tone_plans_dir() -> VfsPath {
+        PhysicalFS::new(concat!(env!("CARGO_MANIFEST_DIR"), "/res/tone_plans")).into()
+    }
+
+    #[test]
+    fn loads_uk_plan() {
+        let plan = TonePlan::load(&tone_plans_dir(), "uk");
+        assert_eq!(plan.dial.frequencies, [400.0]);
+        assert!(plan.dial.cadence.is_empty());
+        assert_eq!(plan.ringback.frequencies, [400.0, 450.0]);
+        assert_eq!(plan.ringback.cadence, [400, 200, 400, 2000]);
+        assert_eq!(plan.busy.cadence, [375, 375]);
+        // Tones that the plan doesn't define use the default plan
+        assert_eq!(plan.off_hook.frequencies, TonePlan::default().off_hook.frequencies);
+    }
+
+    #[test]
+    fn included_plans_are_valid() {
+        for entry in tone_plans_dir().read_dir().unwrap() {
+            if let Err(err) = TonePlan::from_file(&entry) {
+                panic!("{}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_plan_falls_back_to_default() {
+        let root: VfsPath = MemoryFS::new().into();
+        let plans = [
+            ("odd_cadence", "[busy]\nfrequencies = [480, 620]\ncadence = [500]\n"),
+            ("no_frequencies", "[dial]\nfrequencies = []\n"),
+            ("not_toml", "dial = [350, 440\n"),
+        ];
+        for (name, src) in plans {
+            root.join(format!("{}.toml", name)).unwrap().create_file().unwrap().write_all(src.as_bytes()).unwrap();
+            let plan = TonePlan::load(&root, name);
+            assert_eq!(plan.name, TonePlan::default().name, "plan '{}' was loaded", name);
+            assert_eq!(plan.busy.cadence, [500, 500]);
+        }
+        assert_eq!(TonePlan::load(&root, "missing").name, TonePlan::default().name);
+    }
+
+    #[test]
+    fn source_follows_cadence() {
+        let plan = TonePlan::load(&tone_plans_dir(), "uk");
+        let samples: Vec<f32> = plan.ringback.source(1.0).take(TONE_SAMPLE_RATE as usize * 3).collect();
+        let ms = |ms: usize| ms * TONE_SAMPLE_RATE as usize / 1000;
+        let peak = |from: usize, to: usize| samples[ms(from)..ms(to)].iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert!(peak(0, 400) > 0.5);
+        assert_eq!(peak(400, 600), 0.0);
+        assert!(peak(600, 1000) > 0.5);
+        assert_eq!(peak(1000, 3000), 0.0);
+    }
+}